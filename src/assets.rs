@@ -0,0 +1,183 @@
+//! The assets bundled into the binary, which can be overridden by files in
+//! the `unibar` directory of the XDG data directories
+
+#[cfg(feature = "gui")]
+use amethyst::{
+    assets::{AssetStorage, Loader, SimpleFormat},
+    renderer::{
+        PngFormat, Texture, TextureData, TextureHandle, TextureMetadata,
+    },
+};
+#[cfg(feature = "gui")]
+use failure::{format_err, Fallible};
+#[cfg(feature = "gui")]
+use log::warn;
+#[cfg(feature = "gui")]
+use std::fs;
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+/// Bundle the files of the assets directory by their relative path
+#[cfg(feature = "gui")]
+macro_rules! bundle {
+    ($($name:literal),*) => {
+        &[$(($name, include_bytes!(concat!("../assets/", $name)))),*]
+    };
+}
+
+/// The bundled assets by their path relative to the assets directory
+#[cfg(feature = "gui")]
+const BUNDLED: &[(&str, &[u8])] = bundle![
+    "font/meslo.ttf",
+    "images/background.png",
+    "images/button-bg.png",
+    "images/comment.png",
+    "images/cyan.png",
+    "images/foreground.png",
+    "images/green.png",
+    "images/orange.png",
+    "images/pink.png",
+    "images/purple.png",
+    "images/red.png",
+    "images/selection.png",
+    "images/separator.png",
+    "images/yellow.png",
+    "sprites/colors.png",
+    "sprites/weather.png"
+];
+
+/// Retrieve the XDG data directories ordered by priority
+pub fn data_dirs() -> Vec<PathBuf> {
+    let mut dirs = vec![];
+    match env::var_os("XDG_DATA_HOME") {
+        Some(d) => dirs.push(PathBuf::from(d)),
+        None => {
+            if let Some(home) = env::var_os("HOME") {
+                dirs.push(Path::new(&home).join(".local/share"))
+            }
+        }
+    }
+    let system = env::var("XDG_DATA_DIRS")
+        .unwrap_or_else(|_| "/usr/local/share:/usr/share".to_owned());
+    dirs.extend(
+        system
+            .split(':')
+            .filter(|d| !d.is_empty())
+            .map(PathBuf::from),
+    );
+    dirs
+}
+
+/// The lookup of assets, which prefers files of the override directories
+/// over the bundled ones
+#[cfg(feature = "gui")]
+#[derive(Clone, Debug)]
+pub struct Assets {
+    dirs: Vec<PathBuf>,
+}
+
+#[cfg(feature = "gui")]
+impl Default for Assets {
+    /// Search the `unibar` directories of the XDG data directories, like
+    /// `~/.local/share/unibar` and `/usr/share/unibar`
+    fn default() -> Self {
+        Self::new(data_dirs().into_iter().map(|d| d.join("unibar")).collect())
+    }
+}
+
+#[cfg(feature = "gui")]
+impl Assets {
+    /// Create a new lookup with override directories ordered by priority
+    pub fn new(dirs: Vec<PathBuf>) -> Self {
+        Self { dirs }
+    }
+
+    /// Retrieve the override directories
+    pub fn dirs(&self) -> &[PathBuf] {
+        &self.dirs
+    }
+
+    /// Read an asset by its relative path, or any file by an absolute one
+    pub fn read(&self, name: &str) -> Fallible<Vec<u8>> {
+        let path = Path::new(name);
+        if path.is_absolute() {
+            return fs::read(path).map_err(|e| {
+                format_err!("unable to read {}: {}", path.display(), e)
+            });
+        }
+        if let Some(path) =
+            self.dirs.iter().map(|d| d.join(path)).find(|p| p.is_file())
+        {
+            return fs::read(&path).map_err(|e| {
+                format_err!("unable to read {}: {}", path.display(), e)
+            });
+        }
+        BUNDLED
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, data)| data.to_vec())
+            .ok_or_else(|| format_err!("no asset {}", name))
+    }
+
+    /// Load a PNG asset as texture, which is transparent if unavailable
+    pub fn texture(
+        &self,
+        name: &str,
+        loader: &Loader,
+        storage: &AssetStorage<Texture>,
+    ) -> TextureHandle {
+        let data = self.read(name).and_then(|data| {
+            // Screen pixel will be taken from nearest pixel of texture
+            PngFormat
+                .import(data, TextureMetadata::srgb_scale())
+                .map_err(|e| format_err!("invalid image {}: {}", name, e))
+        });
+        match data {
+            Ok(data) => loader.load_from_data(data, (), storage),
+            Err(e) => {
+                warn!("Unable to load texture: {}", e);
+                loader.load_from_data(TextureData::color([0.; 4]), (), storage)
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "gui"))]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn overrides() {
+        let dir = env::temp_dir().join(format!("unibar-{}", Uuid::new_v4()));
+        let (user, system) = (dir.join("user"), dir.join("system"));
+        for (path, content) in &[
+            (user.join("images/red.png"), "user"),
+            (system.join("images/red.png"), "system"),
+            (system.join("images/custom.png"), "custom"),
+        ] {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        let assets = Assets::new(vec![user, system.clone()]);
+
+        assert_eq!(assets.read("images/red.png").unwrap(), b"user");
+        assert_eq!(assets.read("images/custom.png").unwrap(), b"custom");
+        let path = system.join("images/custom.png");
+        assert_eq!(assets.read(&path.to_string_lossy()).unwrap(), b"custom");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn bundled() {
+        let assets = Assets::new(vec![PathBuf::from("/does/not/exist")]);
+        assert_eq!(
+            assets.read("sprites/weather.png").unwrap(),
+            include_bytes!("../assets/sprites/weather.png").to_vec()
+        );
+        assert!(assets.read("images/missing.png").is_err());
+        assert!(assets.read("/does/not/exist/font.ttf").is_err());
+    }
+}
@@ -1,6 +1,9 @@
 //! Bar definitions and functions
 
-use crate::{bundle::Bundle, color::ColorScheme, state::State};
+use crate::{
+    assets::Assets, bundle::Bundle, color::ColorScheme, config::SegmentLoader,
+    control::Control, font::Fonts, ipc::Ipc, layout::Layout, state::State,
+};
 use amethyst::{
     assets::Processor,
     audio::Source,
@@ -12,7 +15,7 @@ use amethyst::{
         ALPHA,
     },
     ui::{DrawUi, UiBundle},
-    utils::{application_root_dir, fps_counter::FPSCounterBundle},
+    utils::fps_counter::FPSCounterBundle,
     winit::{dpi::LogicalSize, WindowBuilder},
     LoggerConfig, StdoutLog,
 };
@@ -23,12 +26,22 @@ use log::{debug, LevelFilter};
 pub struct Bar;
 
 impl Bar {
-    /// Create a new Bar instance and run it
-    pub fn run(level_filter: LevelFilter) -> Fallible<()> {
+    /// Create a new Bar instance and run it with the loaded segments
+    pub fn run(
+        level_filter: LevelFilter,
+        loader: SegmentLoader,
+        ipc: Ipc,
+    ) -> Fallible<()> {
         // Setup the internal logger
         Self::setup_logging(level_filter);
         debug!("Logger setup done");
 
+        // Load the fonts and create the segments
+        let assets = Assets::default();
+        let fonts = Fonts::load(&loader.config()?.font, &assets)?;
+        let control = Control::new(loader);
+        let segments = control.load()?;
+
         // Build the window
         let window_builder = WindowBuilder::new()
             .with_title("unibar")
@@ -37,7 +50,10 @@ impl Bar {
             .with_transparency(true)
             .with_always_on_top(true)
             .with_window_icon(None)
-            .with_dimensions(LogicalSize::new(1000., 20.));
+            .with_dimensions(LogicalSize::new(
+                f64::from(Layout::width()),
+                f64::from(Layout::height()),
+            ));
         let mut display_config = DisplayConfig::from(window_builder);
         display_config.multisampling = 0;
         display_config.vsync = true;
@@ -84,13 +100,17 @@ impl Bar {
             .map_err(|_| err_msg("Unable to load RenderBundle"))?;
         debug!("Application data setup done");
 
-        // Create and start the applicaiton
-        let mut app =
-            Application::build(application_root_dir()?.join("assets"), State)
-                .map_err(|_| err_msg("Unable to create application builder"))?
-                .with_frame_limit(FrameRateLimitStrategy::Sleep, 30)
-                .build(app_data)
-                .map_err(|_| err_msg("Unable to create application"))?;
+        // Create and start the applicaiton, whose assets are all loaded
+        // from data instead of the asset directory
+        let directory = assets.dirs().first().cloned().unwrap_or_default();
+        let mut app = Application::build(
+            directory,
+            State::new(control, segments, ipc, fonts, assets),
+        )
+        .map_err(|_| err_msg("Unable to create application builder"))?
+        .with_frame_limit(FrameRateLimitStrategy::Sleep, 30)
+        .build(app_data)
+        .map_err(|_| err_msg("Unable to create application"))?;
 
         debug!("Initialization done, starting app");
         app.run();
@@ -98,7 +118,7 @@ impl Bar {
     }
 
     /// Setup the application logger
-    fn setup_logging(level_filter: LevelFilter) {
+    pub fn setup_logging(level_filter: LevelFilter) {
         // Initialize logging
         let logger_config = LoggerConfig {
             stdout: StdoutLog::Colored,
@@ -9,7 +9,7 @@ impl<'a, 'b> SystemBundle<'a, 'b> for Bundle {
         self,
         builder: &mut DispatcherBuilder<'a, 'b>,
     ) -> Result<(), Error> {
-        builder.add(WorkspaceSystem::new()?, "workspace_system", &[]);
+        builder.add(WorkspaceSystem::new(), "workspace_system", &[]);
         builder.add(SegmentSystem::default(), "segment_system", &[]);
         Ok(())
     }
@@ -0,0 +1,24 @@
+//! Connection handling of D-Bus based segments
+
+use dbus::{blocking::Connection, channel::Channel};
+use failure::Fallible;
+
+/// Connect to the provided address or the session bus if not set
+pub fn connect(address: Option<&str>) -> Fallible<Connection> {
+    Ok(match address {
+        Some(address) => {
+            let mut channel = Channel::open_private(address)?;
+            channel.register()?;
+            channel.into()
+        }
+        None => Connection::new_session()?,
+    })
+}
+
+/// Connect to the provided address or the system bus if not set
+pub fn connect_system(address: Option<&str>) -> Fallible<Connection> {
+    match address {
+        Some(_) => connect(address),
+        None => Ok(Connection::new_system()?),
+    }
+}
@@ -1,50 +1,137 @@
 //! Main color scheme definitions and structures
 
+/// The color scheme of the bar as RGBA values
 pub struct ColorScheme;
 
-#[allow(dead_code)]
 impl ColorScheme {
+    /// A black color
     pub const fn black() -> [f32; 4] {
         [0.05, 0.05, 0.05, 1.]
     }
 
+    /// The background color of the bar
     pub const fn background() -> [f32; 4] {
         [0.156, 0.164, 0.211, 1.]
     }
 
+    /// A cyan color
     pub const fn cyan() -> [f32; 4] {
         [0.545, 0.913, 0.992, 1.]
     }
 
+    /// The default text color
     pub const fn foreground() -> [f32; 4] {
         [0.972, 0.972, 0.949, 1.]
     }
 
+    /// A green color
     pub const fn green() -> [f32; 4] {
         [0.313, 0.98, 0.482, 1.]
     }
 
+    /// An orange color
     pub const fn orange() -> [f32; 4] {
         [1., 0.721, 0.423, 1.]
     }
 
+    /// A pink color
     pub const fn pink() -> [f32; 4] {
         [1., 0.474, 0.776, 1.]
     }
 
+    /// A purple color
     pub const fn purple() -> [f32; 4] {
         [0.741, 0.576, 0.976, 1.]
     }
 
+    /// A red color
     pub const fn red() -> [f32; 4] {
         [1., 0.333, 0.333, 1.]
     }
 
+    /// The color of selected items
     pub const fn selection() -> [f32; 4] {
         [0.384, 0.447, 0.643, 1.]
     }
 
+    /// A yellow color
     pub const fn yellow() -> [f32; 4] {
         [0.945, 0.98, 0.549, 1.]
     }
 }
+
+impl ColorScheme {
+    /// Convert a color into its `#rrggbb` representation
+    pub fn to_hex(color: [f32; 4]) -> String {
+        format!(
+            "#{:02x}{:02x}{:02x}",
+            (color[0] * 255.).round() as u8,
+            (color[1] * 255.).round() as u8,
+            (color[2] * 255.).round() as u8,
+        )
+    }
+
+    /// Parse a color from its `#rrggbb` or `#rrggbbaa` representation
+    pub fn from_hex(hex: &str) -> Option<[f32; 4]> {
+        let hex = hex.trim().trim_start_matches('#');
+        if (hex.len() != 6 && hex.len() != 8) || !hex.is_ascii() {
+            return None;
+        }
+        let mut color = [1.; 4];
+        for (c, i) in color.iter_mut().zip((0..hex.len()).step_by(2)) {
+            *c = f32::from(u8::from_str_radix(&hex[i..i + 2], 16).ok()?) / 255.;
+        }
+        Some(color)
+    }
+
+    /// Parse a color of the scheme by its name like `red` or a hex color
+    pub fn from_name(name: &str) -> Option<[f32; 4]> {
+        Some(match name.trim() {
+            "black" => Self::black(),
+            "background" => Self::background(),
+            "cyan" => Self::cyan(),
+            "foreground" => Self::foreground(),
+            "green" => Self::green(),
+            "orange" => Self::orange(),
+            "pink" => Self::pink(),
+            "purple" => Self::purple(),
+            "red" => Self::red(),
+            "selection" => Self::selection(),
+            "yellow" => Self::yellow(),
+            hex => return Self::from_hex(hex),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn to_hex() {
+        assert_eq!(ColorScheme::to_hex([1., 0., 0.5, 1.]), "#ff0080");
+        assert_eq!(ColorScheme::to_hex(ColorScheme::background()), "#282a36");
+    }
+
+    #[test]
+    fn from_hex() {
+        assert_eq!(ColorScheme::from_hex("#ff0000"), Some([1., 0., 0., 1.]));
+        assert_eq!(ColorScheme::from_hex("00ff0000"), Some([0., 1., 0., 0.]));
+        assert_eq!(
+            ColorScheme::from_hex(&ColorScheme::to_hex(ColorScheme::red()))
+                .map(ColorScheme::to_hex),
+            Some(ColorScheme::to_hex(ColorScheme::red()))
+        );
+        assert_eq!(ColorScheme::from_hex("#ff00"), None);
+        assert_eq!(ColorScheme::from_hex("#gg0000"), None);
+        assert_eq!(ColorScheme::from_hex("#ff00ä"), None);
+    }
+
+    #[test]
+    fn from_name() {
+        assert_eq!(ColorScheme::from_name("red"), Some(ColorScheme::red()));
+        assert_eq!(ColorScheme::from_name(" cyan "), Some(ColorScheme::cyan()));
+        assert_eq!(ColorScheme::from_name("#ff0000"), Some([1., 0., 0., 1.]));
+        assert_eq!(ColorScheme::from_name("magenta"), None);
+    }
+}
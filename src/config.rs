@@ -0,0 +1,368 @@
+//! Configuration file handling
+
+#[cfg(feature = "dbus")]
+use crate::segment::{
+    Mpris, MprisConfig, Notifications, NotificationsConfig, Tray, TrayConfig,
+};
+use crate::{
+    segment::{
+        default_segments, Backlight, BacklightConfig, Command, CommandConfig,
+        Cpu, CpuConfig, Date, DateConfig, Json, JsonConfig, Keyboard,
+        KeyboardConfig, Load, LoadConfig, Pressure, PressureConfig, Pushed,
+        PushedConfig, Segment, Segments, Text, TextConfig, ThresholdConfig,
+        Thresholds, Volume, VolumeConfig, Weather, WeatherConfig,
+    },
+    source::SysSource,
+};
+use failure::{format_err, Fallible};
+use serde_derive::Deserialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// The bar configuration
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct Config {
+    /// The segments ordered from left to right, the default segments are
+    /// used if empty
+    #[serde(default)]
+    pub segments: Vec<SegmentEntry>,
+
+    /// The fonts of the bar
+    #[serde(default)]
+    pub font: FontConfig,
+}
+
+/// The primary font and its fallbacks
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct FontConfig {
+    /// The primary font file, either absolute or relative to the assets, or
+    /// a fontconfig pattern like `Iosevka:size=12`
+    #[serde(default = "FontConfig::default_name")]
+    pub name: String,
+
+    /// The font size in pixels
+    #[serde(default = "FontConfig::default_size")]
+    pub size: f32,
+
+    /// Font files or patterns searched in order for characters missing in
+    /// the primary font, like icons or CJK workspace names
+    #[serde(default)]
+    pub fallbacks: Vec<String>,
+}
+
+impl Default for FontConfig {
+    fn default() -> Self {
+        Self {
+            name: Self::default_name(),
+            size: Self::default_size(),
+            fallbacks: vec![],
+        }
+    }
+}
+
+impl FontConfig {
+    fn default_name() -> String {
+        "font/meslo.ttf".to_owned()
+    }
+
+    fn default_size() -> f32 {
+        14.
+    }
+}
+
+/// A configured segment together with the options shared by all types
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct SegmentEntry {
+    /// The type specific configuration
+    #[serde(flatten)]
+    pub segment: SegmentConfig,
+
+    /// Colors the segment by the band its value falls into
+    #[serde(default)]
+    pub thresholds: Option<ThresholdConfig>,
+}
+
+/// The configuration of a single segment
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SegmentConfig {
+    /// The CPU utilization and frequency
+    Cpu(CpuConfig),
+
+    /// The current date and time
+    Date(DateConfig),
+
+    /// The output of an external command
+    Command(CommandConfig),
+
+    /// A text which can be changed at runtime
+    Text(TextConfig),
+
+    /// Content pushed via IPC or a named pipe
+    Pushed(PushedConfig),
+
+    /// The brightness of a backlight device
+    Backlight(BacklightConfig),
+
+    /// The active keyboard layout
+    Keyboard(KeyboardConfig),
+
+    /// The load average and uptime
+    Load(LoadConfig),
+
+    /// The Pressure Stall Information of CPU, memory and IO
+    Pressure(PressureConfig),
+
+    /// Values of a periodically requested JSON document
+    Json(JsonConfig),
+
+    /// The volume of the default audio sink
+    Volume(VolumeConfig),
+
+    /// The current weather of a HTTP provider
+    Weather(WeatherConfig),
+
+    /// The current track of MPRIS media players
+    #[cfg(feature = "dbus")]
+    Mpris(MprisConfig),
+
+    /// A notification daemon with a history
+    #[cfg(feature = "dbus")]
+    Notifications(NotificationsConfig),
+
+    /// A system tray hosting StatusNotifierItems
+    #[cfg(feature = "dbus")]
+    Tray(TrayConfig),
+}
+
+/// Creates the segments from an optional configuration file, which allows
+/// reloading them at runtime
+#[derive(Clone, Debug, Default)]
+pub struct SegmentLoader {
+    path: Option<PathBuf>,
+    source: SysSource,
+}
+
+impl Config {
+    /// Load the configuration from a YAML file
+    pub fn from_file(path: &Path) -> Fallible<Self> {
+        let content = fs::read_to_string(path).map_err(|e| {
+            format_err!("unable to read config {}: {}", path.display(), e)
+        })?;
+        content.parse()
+    }
+
+    /// Create the configured segments reading from the provided source
+    pub fn segments(&self, source: &SysSource) -> Fallible<Segments> {
+        if self.segments.is_empty() {
+            return default_segments(source);
+        }
+
+        let mut segments: Segments = vec![];
+        for entry in &self.segments {
+            let segment: Box<dyn Segment> = match &entry.segment {
+                SegmentConfig::Cpu(c) => {
+                    Box::new(Cpu::new(c.clone(), source.clone())?)
+                }
+                SegmentConfig::Date(c) => Box::new(Date::new(c.clone())?),
+                SegmentConfig::Command(c) => Box::new(Command::new(c.clone())?),
+                SegmentConfig::Text(c) => Box::new(Text::new(c.clone())),
+                SegmentConfig::Pushed(c) => Box::new(Pushed::new(c.clone())?),
+                SegmentConfig::Backlight(c) => {
+                    Box::new(Backlight::new(c.clone(), source.clone())?)
+                }
+                SegmentConfig::Keyboard(c) => {
+                    Box::new(Keyboard::new(c.clone())?)
+                }
+                SegmentConfig::Load(c) => {
+                    Box::new(Load::new(c.clone(), source.clone())?)
+                }
+                SegmentConfig::Pressure(c) => {
+                    Box::new(Pressure::new(c.clone(), source.clone())?)
+                }
+                SegmentConfig::Json(c) => Box::new(Json::new(c.clone())?),
+                SegmentConfig::Volume(c) => Box::new(Volume::new(c.clone())?),
+                SegmentConfig::Weather(c) => Box::new(Weather::new(c.clone())?),
+                #[cfg(feature = "dbus")]
+                SegmentConfig::Mpris(c) => Box::new(Mpris::new(c.clone())?),
+                #[cfg(feature = "dbus")]
+                SegmentConfig::Notifications(c) => {
+                    Box::new(Notifications::new(c.clone())?)
+                }
+                #[cfg(feature = "dbus")]
+                SegmentConfig::Tray(c) => Box::new(Tray::new(c.clone())?),
+            };
+            segments.push(match &entry.thresholds {
+                Some(c) => Box::new(Thresholds::new(segment, c.clone())?),
+                None => segment,
+            });
+        }
+        Ok(segments)
+    }
+}
+
+impl SegmentLoader {
+    /// Create a new loader, which uses the default configuration if no path
+    /// is provided
+    pub fn new(path: Option<PathBuf>, source: SysSource) -> Self {
+        Self { path, source }
+    }
+
+    /// Load the configuration, which is the default one without a path
+    pub fn config(&self) -> Fallible<Config> {
+        match &self.path {
+            Some(path) => Config::from_file(path),
+            None => Ok(Config::default()),
+        }
+    }
+
+    /// Load the configuration and create the segments
+    pub fn load(&self) -> Fallible<Segments> {
+        self.config()?.segments(&self.source)
+    }
+}
+
+impl FromStr for Config {
+    type Err = failure::Error;
+
+    /// Parse the configuration from YAML
+    fn from_str(content: &str) -> Fallible<Self> {
+        Ok(serde_yaml::from_str(content)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::segment::Band;
+
+    #[test]
+    fn default_config() {
+        let config = "{}".parse::<Config>().unwrap();
+        assert_eq!(config, Config::default());
+
+        let segments = config.segments(&SysSource::default()).unwrap();
+        let names: Vec<&str> = segments.iter().map(|s| s.name()).collect();
+        assert_eq!(names, vec!["cpu", "date"]);
+    }
+
+    #[test]
+    fn segments() {
+        let config: Config = "segments:\n\
+             - type: date\n\
+             - type: command\n\
+             \x20 command: echo hello\n\
+             \x20 interval: 5\n\
+             \x20 name: hello\n\
+             - type: cpu\n\
+             \x20 format: \"{usage}%\"\n\
+             \x20 thresholds:\n\
+             \x20   degraded: 70\n\
+             \x20   critical: 90\n\
+             \x20   styles:\n\
+             \x20     critical:\n\
+             \x20       background: red\n\
+             - type: text\n\
+             - type: pushed\n\
+             \x20 name: ci\n\
+             \x20 expiry: 60\n"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            config.segments[1].segment,
+            SegmentConfig::Command(CommandConfig {
+                command: "echo hello".to_owned(),
+                interval: Some(5),
+                name: "hello".to_owned(),
+                width: 100,
+            })
+        );
+
+        assert_eq!(
+            config.segments[0].segment,
+            SegmentConfig::Date(DateConfig::default())
+        );
+        assert_eq!(
+            config.segments[2].segment,
+            SegmentConfig::Cpu(CpuConfig {
+                format: "{usage}%".to_owned(),
+                ..CpuConfig::default()
+            })
+        );
+        let thresholds = config.segments[2].thresholds.as_ref().unwrap();
+        assert_eq!((thresholds.degraded, thresholds.critical), (70., 90.));
+        assert_eq!(
+            thresholds.styles[&Band::Critical].background.as_deref(),
+            Some("red")
+        );
+        assert_eq!(config.segments[0].thresholds, None);
+
+        let segments = config.segments(&SysSource::default()).unwrap();
+        let names: Vec<&str> = segments.iter().map(|s| s.name()).collect();
+        assert_eq!(names, vec!["date", "hello", "cpu", "text", "ci"]);
+    }
+
+    #[cfg(feature = "dbus")]
+    #[test]
+    fn mpris() {
+        let config: Config = "segments:\n\
+             - type: mpris\n\
+             \x20 player: spotify\n"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            config.segments[0].segment,
+            SegmentConfig::Mpris(MprisConfig {
+                player: Some("spotify".into()),
+                ..MprisConfig::default()
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_segment() {
+        assert!("segments:\n- type: unknown\n".parse::<Config>().is_err());
+        assert!("segments:\n- type: cpu\n  thresholds:\n    critical: 1\n"
+            .parse::<Config>()
+            .is_err());
+        assert!("segments:\n- type: command\n".parse::<Config>().is_err());
+    }
+
+    #[test]
+    fn missing_file() {
+        assert!(Config::from_file(Path::new("/does/not/exist")).is_err());
+    }
+
+    #[test]
+    fn font() {
+        let config: Config = "font:\n\
+             \x20 size: 12\n\
+             \x20 fallbacks: [/usr/share/fonts/noto/NotoSansCJK.ttc]\n"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            config.font,
+            FontConfig {
+                size: 12.,
+                fallbacks: vec!["/usr/share/fonts/noto/NotoSansCJK.ttc".into()],
+                ..FontConfig::default()
+            }
+        );
+        assert_eq!(config.font.name, "font/meslo.ttf");
+    }
+
+    #[test]
+    fn loader() {
+        let segments = SegmentLoader::default().load().unwrap();
+        assert_eq!(segments.len(), 2);
+
+        let loader = SegmentLoader::new(
+            Some(PathBuf::from("/does/not/exist")),
+            SysSource::default(),
+        );
+        assert!(loader.load().is_err());
+    }
+}
@@ -0,0 +1,346 @@
+//! Renderer independent runtime state of the bar
+
+use crate::{
+    config::SegmentLoader,
+    ipc::{BarState, Request, Response, SegmentState},
+    segment::{Segment, SegmentOutput, Segments},
+};
+use failure::{format_err, Fallible};
+use log::warn;
+use std::collections::{HashMap, HashSet};
+
+/// The visibility and latest outputs of all segments, which can be changed
+/// via IPC requests
+#[derive(Default)]
+pub struct Control {
+    loader: SegmentLoader,
+    hidden: bool,
+    hidden_segments: HashSet<String>,
+    outputs: HashMap<String, SegmentOutput>,
+    popups: HashMap<String, Vec<SegmentOutput>>,
+}
+
+impl Control {
+    /// Create a new control which reloads segments via the loader
+    pub fn new(loader: SegmentLoader) -> Self {
+        Self {
+            loader,
+            ..Self::default()
+        }
+    }
+
+    /// Create the segments by using the loader
+    pub fn load(&self) -> Fallible<Segments> {
+        self.loader.load()
+    }
+
+    /// Update all segments and store their outputs and opened popups
+    pub fn update(&mut self, segments: &mut Segments) {
+        for segment in segments.iter_mut() {
+            match segment.update() {
+                Ok(output) => {
+                    self.outputs.insert(segment.id().to_owned(), output);
+                }
+                Err(e) => {
+                    warn!("Unable to update {} segment: {}", segment.name(), e)
+                }
+            }
+            match segment.popup() {
+                Some(popup) => {
+                    self.popups.insert(segment.id().to_owned(), popup);
+                }
+                None => {
+                    self.popups.remove(segment.id());
+                }
+            }
+        }
+    }
+
+    /// Retrieve the latest output of the segment with the provided id
+    pub fn output(&self, id: &str) -> Option<&SegmentOutput> {
+        self.outputs.get(id)
+    }
+
+    /// Retrieve the lines of the opened popup of the segment with the
+    /// provided id
+    pub fn popup(&self, id: &str) -> Option<&[SegmentOutput]> {
+        self.popups.get(id).map(Vec::as_slice)
+    }
+
+    /// Returns true if the bar is visible
+    pub fn is_visible(&self) -> bool {
+        !self.hidden
+    }
+
+    /// Returns true if the segment with the provided id is visible, which
+    /// is not the case if hidden via IPC or by the segment itself
+    pub fn is_segment_visible(&self, id: &str) -> bool {
+        !self.hidden_segments.contains(id)
+            && !self.output(id).map(|o| o.hidden).unwrap_or(false)
+    }
+
+    /// Handle an IPC request
+    pub fn handle(
+        &mut self,
+        request: Request,
+        segments: &mut Segments,
+    ) -> Response {
+        match self.try_handle(request, segments) {
+            Ok(response) => response,
+            Err(e) => Response::error(e.to_string()),
+        }
+    }
+
+    fn try_handle(
+        &mut self,
+        request: Request,
+        segments: &mut Segments,
+    ) -> Fallible<Response> {
+        match request {
+            Request::Set { segment, text } => {
+                let segment = Self::find(segments, &segment)?;
+                segment.set_text(&text)?;
+                let output = segment.update()?;
+                self.outputs.insert(segment.id().to_owned(), output);
+            }
+            Request::Show { segment } => {
+                let id = Self::find(segments, &segment)?.id().to_owned();
+                self.hidden_segments.remove(&id);
+            }
+            Request::Hide { segment } => {
+                let id = Self::find(segments, &segment)?.id().to_owned();
+                self.hidden_segments.insert(id);
+            }
+            Request::Toggle => self.hidden = !self.hidden,
+            Request::Reload => {
+                *segments = self.load()?;
+                self.hidden_segments.clear();
+                self.outputs.clear();
+                self.popups.clear();
+                self.update(segments);
+            }
+            Request::State => return Ok(Response::state(self.state(segments))),
+        }
+        Ok(Response::ok())
+    }
+
+    /// Retrieve the current state of the bar
+    fn state(&self, segments: &Segments) -> BarState {
+        BarState {
+            visible: self.is_visible(),
+            segments: segments
+                .iter()
+                .map(|s| {
+                    let output =
+                        self.output(s.id()).cloned().unwrap_or_default();
+                    SegmentState {
+                        id: s.id().to_owned(),
+                        name: s.name().to_owned(),
+                        text: output.text,
+                        visible: self.is_segment_visible(s.id()),
+                        urgent: output.urgent,
+                        popup: self
+                            .popup(s.id())
+                            .unwrap_or_default()
+                            .iter()
+                            .map(|o| o.text.clone())
+                            .collect(),
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    /// Find a segment by its id or name
+    fn find<'a>(
+        segments: &'a mut Segments,
+        segment: &str,
+    ) -> Fallible<&'a mut Box<dyn Segment>> {
+        segments
+            .iter_mut()
+            .find(|s| s.id() == segment || s.name() == segment)
+            .ok_or_else(|| format_err!("segment '{}' not found", segment))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        mock::segment::Counter,
+        segment::{Button, Pushed, PushedConfig, Text, TextConfig},
+    };
+
+    fn segments() -> Segments {
+        vec![
+            Box::new(Counter::new("a")),
+            Box::new(Text::new(TextConfig {
+                name: "status".into(),
+                text: "initial".into(),
+                width: 100,
+            })),
+        ]
+    }
+
+    fn set(segment: &str, text: &str) -> Request {
+        Request::Set {
+            segment: segment.into(),
+            text: text.into(),
+        }
+    }
+
+    #[test]
+    fn update() {
+        let mut control = Control::default();
+        let mut segments = segments();
+        assert!(control.output("a").is_none());
+
+        control.update(&mut segments);
+        assert_eq!(control.output("a").unwrap().text, "0");
+        assert_eq!(control.output(segments[1].id()).unwrap().text, "initial");
+    }
+
+    #[test]
+    fn set_text() {
+        let mut control = Control::default();
+        let mut segments = segments();
+
+        assert!(
+            control
+                .handle(set("status", "changed"), &mut segments)
+                .success
+        );
+        assert_eq!(control.output(segments[1].id()).unwrap().text, "changed");
+        assert_eq!(segments[1].update().unwrap().text, "changed");
+
+        let id = segments[1].id().to_owned();
+        assert!(control.handle(set(&id, "by id"), &mut segments).success);
+        assert_eq!(control.output(&id).unwrap().text, "by id");
+
+        let response = control.handle(set("a", "text"), &mut segments);
+        assert!(!response.success);
+        assert_eq!(
+            response.error.unwrap(),
+            "counter segment does not support setting text"
+        );
+
+        let response = control.handle(set("unknown", "text"), &mut segments);
+        assert_eq!(response.error.unwrap(), "segment 'unknown' not found");
+    }
+
+    #[test]
+    fn show_hide() {
+        let mut control = Control::default();
+        let mut segments = segments();
+        let hide = Request::Hide {
+            segment: "status".into(),
+        };
+        let show = Request::Show {
+            segment: "status".into(),
+        };
+
+        let id = segments[1].id().to_owned();
+        assert!(control.is_segment_visible(&id));
+        assert!(control.handle(hide, &mut segments).success);
+        assert!(!control.is_segment_visible(&id));
+        assert!(control.is_segment_visible("a"));
+        assert!(control.handle(show, &mut segments).success);
+        assert!(control.is_segment_visible(&id));
+    }
+
+    #[test]
+    fn hidden_by_segment() {
+        let mut control = Control::default();
+        let mut segments: Segments =
+            vec![Box::new(Pushed::new(PushedConfig::default()).unwrap())];
+        let id = segments[0].id().to_owned();
+        assert!(control.is_segment_visible(&id));
+
+        control.update(&mut segments);
+        assert!(!control.is_segment_visible(&id));
+
+        assert!(control.handle(set(&id, "pushed"), &mut segments).success);
+        assert!(control.is_segment_visible(&id));
+    }
+
+    #[test]
+    fn popup() {
+        let mut control = Control::default();
+        let mut segments: Segments =
+            vec![Box::new(Counter::new("a").with_popup())];
+        control.update(&mut segments);
+        assert!(control.popup("a").is_none());
+
+        segments[0].click(Button::Left);
+        control.update(&mut segments);
+        assert_eq!(control.popup("a").unwrap()[0].text, "1 clicks");
+        let state =
+            control.handle(Request::State, &mut segments).state.unwrap();
+        assert_eq!(state.segments[0].popup, vec!["1 clicks"]);
+
+        segments[0].click(Button::Left);
+        control.update(&mut segments);
+        assert!(control.popup("a").is_none());
+    }
+
+    #[test]
+    fn toggle() {
+        let mut control = Control::default();
+        let mut segments = segments();
+        assert!(control.is_visible());
+        assert!(control.handle(Request::Toggle, &mut segments).success);
+        assert!(!control.is_visible());
+        assert!(control.handle(Request::Toggle, &mut segments).success);
+        assert!(control.is_visible());
+    }
+
+    #[test]
+    fn reload() {
+        let mut control = Control::default();
+        let mut segments = segments();
+        control.handle(
+            Request::Hide {
+                segment: "a".into(),
+            },
+            &mut segments,
+        );
+
+        assert!(control.handle(Request::Reload, &mut segments).success);
+        let names: Vec<&str> = segments.iter().map(|s| s.name()).collect();
+        assert_eq!(names, vec!["cpu", "date"]);
+        assert!(control.is_segment_visible("a"));
+        assert!(control.output(segments[1].id()).is_some());
+    }
+
+    #[test]
+    fn state() {
+        let mut control = Control::default();
+        let mut segments = segments();
+        control.update(&mut segments);
+        control.handle(
+            Request::Hide {
+                segment: "a".into(),
+            },
+            &mut segments,
+        );
+
+        let state =
+            control.handle(Request::State, &mut segments).state.unwrap();
+        assert!(state.visible);
+        assert_eq!(state.segments.len(), 2);
+        assert_eq!(
+            state.segments[0],
+            SegmentState {
+                id: "a".into(),
+                name: "counter".into(),
+                text: "0".into(),
+                visible: false,
+                urgent: false,
+                popup: vec![],
+            }
+        );
+        assert_eq!(state.segments[1].name, "status");
+        assert_eq!(state.segments[1].text, "initial");
+        assert!(state.segments[1].visible);
+    }
+}
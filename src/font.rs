@@ -0,0 +1,367 @@
+//! The primary font of the bar and its fallbacks for missing characters,
+//! which are either files or fontconfig patterns like `Iosevka:size=12`
+
+use crate::{assets::Assets, config::FontConfig};
+use amethyst::{
+    assets::{AssetStorage, Loader, SimpleFormat},
+    ui::{FontAsset, FontData, FontHandle, TtfFormat},
+};
+use failure::{format_err, Fallible};
+use log::warn;
+use rusttype::{Font, GlyphId, Scale};
+use std::{fmt::Display, fs, path::PathBuf, process::Command};
+
+/// The bundled font used if the configured one is unavailable
+const BUNDLED: &str = "font/meslo.ttf";
+
+/// The generic families of fontconfig, which resolve to another family
+const GENERIC_FAMILIES: &[&str] = &[
+    "monospace",
+    "mono",
+    "sans-serif",
+    "sans",
+    "serif",
+    "cursive",
+    "fantasy",
+    "emoji",
+    "math",
+    "system-ui",
+];
+
+/// A fontconfig pattern like `Iosevka:size=12` or `Iosevka-12`
+#[derive(Debug, PartialEq)]
+struct Pattern {
+    family: String,
+    size: Option<f32>,
+}
+
+impl Pattern {
+    /// Parse a font name, which is no pattern if it refers to a file
+    fn parse(name: &str) -> Option<Self> {
+        let lowercase = name.to_lowercase();
+        if name.contains('/')
+            || [".ttf", ".otf", ".ttc"]
+                .iter()
+                .any(|e| lowercase.ends_with(e))
+        {
+            return None;
+        }
+
+        let mut properties = name.split(':');
+        let mut family = properties.next().unwrap_or_default().trim();
+        let mut size = None;
+        if let Some(i) = family.rfind('-') {
+            if let Ok(s) = family[i + 1..].parse() {
+                size = Some(s);
+                family = &family[..i];
+            }
+        }
+        for property in properties {
+            if let Some(s) = property.trim().strip_prefix("size=") {
+                size = s.parse().ok().or(size);
+            }
+        }
+        Some(Self {
+            family: family.to_owned(),
+            size,
+        })
+    }
+
+    /// Retrieve the file of the best matching system font via `fc-match`
+    /// and the index of the face within it
+    fn resolve(&self, name: &str) -> Fallible<(PathBuf, usize)> {
+        let output = Command::new("fc-match")
+            .arg("--format=%{file}\n%{family}\n%{index}")
+            .arg(name)
+            .output()
+            .map_err(|e| format_err!("unable to run fc-match: {}", e))?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let mut lines = stdout.lines();
+        let file = match lines.next() {
+            Some(file) if output.status.success() && !file.is_empty() => file,
+            _ => return Err(format_err!("no font matches '{}'", name)),
+        };
+
+        // Fontconfig substitutes a default font for unknown families
+        let families = lines.next().unwrap_or_default();
+        let generic = GENERIC_FAMILIES
+            .iter()
+            .any(|f| f.eq_ignore_ascii_case(&self.family));
+        if !self.family.is_empty()
+            && !generic
+            && !families
+                .split(',')
+                .any(|f| f.trim().eq_ignore_ascii_case(&self.family))
+        {
+            return Err(format_err!(
+                "no font matches '{}', the closest is '{}'",
+                name,
+                families
+            ));
+        }
+        let index = lines.next().and_then(|i| i.parse().ok()).unwrap_or(0);
+        Ok((PathBuf::from(file), index))
+    }
+}
+
+/// Extract a face of a font collection into a standalone font, since
+/// amethyst is unable to load collections. Other fonts are kept as is.
+fn face(data: Vec<u8>, index: usize) -> Fallible<Vec<u8>> {
+    if !data.starts_with(b"ttcf") {
+        return Ok(data);
+    }
+    let bytes = |offset: usize, length: usize| {
+        data.get(offset..offset + length)
+            .ok_or_else(|| format_err!("truncated font collection"))
+    };
+    let number = |offset: usize, length: usize| {
+        bytes(offset, length)
+            .map(|b| b.iter().fold(0, |n, b| n << 8 | *b as usize))
+    };
+    if index >= number(8, 4)? {
+        return Err(format_err!("font collection has no face {}", index));
+    }
+
+    // Copy the tables of the face behind its directory
+    let start = number(12 + 4 * index, 4)?;
+    let tables = number(start + 4, 2)?;
+    let mut font = bytes(start, 12)?.to_vec();
+    let mut body = vec![];
+    for table in 0..tables {
+        let record = start + 12 + 16 * table;
+        let length = number(record + 12, 4)?;
+        let offset = 12 + 16 * tables + body.len();
+        font.extend_from_slice(bytes(record, 8)?);
+        font.extend_from_slice(&(offset as u32).to_be_bytes());
+        font.extend_from_slice(&(length as u32).to_be_bytes());
+        body.extend_from_slice(bytes(number(record + 8, 4)?, length)?);
+        while body.len() % 4 != 0 {
+            body.push(0);
+        }
+    }
+    font.extend(body);
+    Ok(font)
+}
+
+/// The loaded fonts, where the first one is the primary font
+pub struct Fonts {
+    size: f32,
+    fonts: Vec<Font<'static>>,
+    data: Vec<FontData>,
+    handles: Vec<FontHandle>,
+}
+
+impl Fonts {
+    /// Load the configured fonts, where relative paths refer to assets and
+    /// unavailable fallbacks are skipped. The bundled font is
+    /// used if the primary one is unavailable, whereas the size of its
+    /// pattern overrides the configured one.
+    pub fn load(config: &FontConfig, assets: &Assets) -> Fallible<Self> {
+        let mut fonts = Self {
+            size: config.size,
+            fonts: vec![],
+            data: vec![],
+            handles: vec![],
+        };
+        match fonts.add(&config.name, assets) {
+            Ok(size) => fonts.size = size.unwrap_or(config.size),
+            Err(e) if config.name != BUNDLED => {
+                warn!("Unable to load font, using the bundled one: {}", e);
+                fonts.add(BUNDLED, assets)?;
+            }
+            Err(e) => return Err(e),
+        }
+        for name in &config.fallbacks {
+            if let Err(e) = fonts.add(name, assets) {
+                warn!("Unable to load fallback font: {}", e);
+            }
+        }
+        Ok(fonts)
+    }
+
+    /// Add a font file or the font matching a pattern, which results in the
+    /// size of the pattern if set
+    fn add(&mut self, name: &str, assets: &Assets) -> Fallible<Option<f32>> {
+        let (data, index, size) = match Pattern::parse(name) {
+            Some(pattern) => {
+                let (path, index) = pattern.resolve(name)?;
+                let data = fs::read(&path).map_err(|e| {
+                    format_err!("unable to read {}: {}", path.display(), e)
+                })?;
+                (data, index, pattern.size)
+            }
+            None => (assets.read(name)?, 0, None),
+        };
+        let invalid =
+            |e: &dyn Display| format_err!("invalid font {}: {}", name, e);
+        let data = face(data, index).map_err(|e| invalid(&e))?;
+        let font = Font::from_bytes(data.clone()).map_err(|e| invalid(&e))?;
+        let data = TtfFormat.import(data, ()).map_err(|e| invalid(&e))?;
+        self.fonts.push(font);
+        self.data.push(data);
+        Ok(size)
+    }
+
+    /// The font size in pixels
+    pub fn size(&self) -> f32 {
+        self.size
+    }
+
+    /// Retrieve the font with the provided index
+    pub fn font(&self, index: usize) -> &Font<'static> {
+        &self.fonts[index]
+    }
+
+    /// Returns true if the font with the provided index contains the
+    /// character
+    pub fn contains(&self, index: usize, c: char) -> bool {
+        self.fonts[index].glyph(c).id() != GlyphId(0)
+    }
+
+    /// Retrieve the index of the first font containing the character,
+    /// which is the primary one if none does
+    pub fn index(&self, c: char) -> usize {
+        (0..self.fonts.len())
+            .find(|i| self.contains(*i, c))
+            .unwrap_or(0)
+    }
+
+    /// Retrieve the horizontal advance of a character drawn with the font
+    /// of the provided index in pixels
+    pub fn advance(&self, index: usize, c: char) -> f32 {
+        self.font(index)
+            .glyph(c)
+            .scaled(Scale::uniform(self.size))
+            .h_metrics()
+            .advance_width
+    }
+
+    /// Load the fonts as assets, which is required for retrieving handles
+    pub fn register(
+        &mut self,
+        loader: &Loader,
+        storage: &AssetStorage<FontAsset>,
+    ) {
+        for data in self.data.drain(..) {
+            self.handles.push(loader.load_from_data(data, (), storage));
+        }
+    }
+
+    /// Retrieve the handle of the font with the provided index
+    pub fn font_handle(&self, index: usize) -> FontHandle {
+        self.handles[index].clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use uuid::Uuid;
+
+    fn assets() -> Assets {
+        Assets::new(vec![])
+    }
+
+    #[test]
+    fn fallbacks() {
+        let config = FontConfig {
+            fallbacks: vec!["font/missing.ttf".into(), "font/meslo.ttf".into()],
+            ..FontConfig::default()
+        };
+        let fonts = Fonts::load(&config, &assets()).unwrap();
+        assert_eq!(fonts.size(), 14.);
+        assert_eq!(fonts.fonts.len(), 2);
+        assert_eq!(fonts.index('a'), 0);
+        assert!(fonts.contains(1, 'a'));
+        assert!(fonts.advance(0, 'a') > 0.);
+
+        // Characters missing in all fonts use the primary one
+        assert_eq!(fonts.index('\u{10ffff}'), 0);
+    }
+
+    #[test]
+    fn missing_primary() {
+        let config = FontConfig {
+            name: "font/missing.ttf".into(),
+            fallbacks: vec!["unibar missing family".into()],
+            ..FontConfig::default()
+        };
+        let fonts = Fonts::load(&config, &assets()).unwrap();
+        assert_eq!(fonts.fonts.len(), 1);
+        assert_eq!(fonts.size(), 14.);
+
+        // Broken overrides of the bundled font can not be replaced
+        let dir = env::temp_dir().join(format!("unibar-{}", Uuid::new_v4()));
+        fs::create_dir_all(dir.join("font")).unwrap();
+        fs::write(dir.join(BUNDLED), "invalid").unwrap();
+        let assets = Assets::new(vec![dir.clone()]);
+        assert!(Fonts::load(&FontConfig::default(), &assets).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn patterns() {
+        let pattern = |family: &str, size| Pattern {
+            family: family.into(),
+            size,
+        };
+        assert_eq!(Pattern::parse("Iosevka"), Some(pattern("Iosevka", None)));
+        assert_eq!(
+            Pattern::parse("Iosevka:size=12"),
+            Some(pattern("Iosevka", Some(12.)))
+        );
+        assert_eq!(
+            Pattern::parse("Noto Sans CJK JP-10.5:style=Bold"),
+            Some(pattern("Noto Sans CJK JP", Some(10.5)))
+        );
+        assert_eq!(
+            Pattern::parse("Font Awesome 6 Free-Solid"),
+            Some(pattern("Font Awesome 6 Free-Solid", None))
+        );
+        assert_eq!(Pattern::parse(":style=Bold"), Some(pattern("", None)));
+        assert_eq!(Pattern::parse("font/meslo.ttf"), None);
+        assert_eq!(Pattern::parse("Meslo.TTF"), None);
+
+        // Unknown families are not replaced by the default font
+        let missing = "unibar missing family";
+        assert!(Pattern::parse(missing).unwrap().resolve(missing).is_err());
+
+        // Generic families resolve to any font
+        if Command::new("fc-match").output().is_ok() {
+            let generic = "monospace:size=12";
+            let pattern = Pattern::parse(generic).unwrap();
+            assert_eq!(pattern.size, Some(12.));
+            assert!(pattern.resolve(generic).is_ok());
+        }
+    }
+
+    #[test]
+    fn collections() {
+        // Bundle the font twice behind a collection header
+        let font = assets().read(BUNDLED).unwrap();
+        let tables = font[4] as usize * 256 + font[5] as usize;
+        let mut collection = b"ttcf\0\x01\0\0\0\0\0\x02".to_vec();
+        for _ in 0..2 {
+            collection.extend_from_slice(&20u32.to_be_bytes());
+        }
+        let mut copy = font.clone();
+        for table in 0..tables {
+            let record = 12 + 16 * table + 8;
+            let mut offset = [0; 4];
+            offset.copy_from_slice(&copy[record..record + 4]);
+            let offset = u32::from_be_bytes(offset) + 20;
+            copy[record..record + 4].copy_from_slice(&offset.to_be_bytes());
+        }
+        collection.extend(copy);
+
+        let extracted = face(collection.clone(), 1).unwrap();
+        assert!(Font::from_bytes(extracted.clone()).is_ok());
+        assert_eq!(extracted.len(), face(font.clone(), 0).unwrap().len());
+        assert!(face(collection.clone(), 2).is_err());
+        assert!(face(collection[..100].to_vec(), 0).is_err());
+
+        // Single fonts are kept
+        assert_eq!(face(font.clone(), 0).unwrap(), font);
+    }
+}
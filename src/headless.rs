@@ -0,0 +1,328 @@
+//! Offscreen software rendering of the bar into PNG frames
+
+use crate::{
+    assets::Assets, bar::Bar, color::ColorScheme, config::SegmentLoader,
+    font::Fonts, i3::I3, layout::Layout, workspace::Workspace,
+};
+use failure::Fallible;
+use log::{debug, LevelFilter};
+use png::{BitDepth, ColorType, Decoder, Encoder, HasParameters};
+use rusttype::{point, Scale};
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::BufWriter,
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// The headless bar, which renders without any display or GPU
+pub struct Headless;
+
+impl Headless {
+    /// Render the provided amount of frames into the output directory
+    pub fn run(
+        level_filter: LevelFilter,
+        output: &Path,
+        frames: u32,
+        loader: SegmentLoader,
+    ) -> Fallible<()> {
+        // Setup the internal logger
+        Bar::setup_logging(level_filter);
+        debug!("Logger setup done");
+
+        fs::create_dir_all(output)?;
+        let assets = Assets::default();
+        let fonts = Fonts::load(&loader.config()?.font, &assets)?;
+        let mut segments = loader.load()?;
+        let mut canvas = Canvas::new(assets, fonts);
+
+        // Wait until the i3 connection state is known
+        let mut i3 = I3::new();
+        let timeout = Instant::now() + Duration::from_secs(1);
+        while i3.poll().is_empty() && Instant::now() < timeout {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        for frame in 0..frames {
+            if frame > 0 {
+                thread::sleep(Duration::from_secs(1));
+                i3.poll();
+            }
+
+            canvas.clear(ColorScheme::background());
+
+            // Draw the workspaces
+            if i3.is_connected() {
+                for workspace in i3.workspaces()? {
+                    let (image, text_color) = Workspace::style(&workspace);
+                    canvas.draw_button(
+                        Layout::workspace_x(workspace.num),
+                        Layout::workspace_width(),
+                        image,
+                        None,
+                        &[(Workspace::label(&workspace), text_color, false)],
+                    )?;
+                }
+            } else {
+                let (x, width) = Layout::disconnected();
+                let (image, text_color) = Workspace::disconnected_style();
+                canvas.draw_button(
+                    x,
+                    width,
+                    image,
+                    None,
+                    &[(Workspace::disconnected_label(), text_color, false)],
+                )?;
+            }
+
+            // Draw the visible segments
+            let mut outputs = vec![];
+            for segment in segments.iter_mut() {
+                let output = segment.update()?;
+                if !output.hidden {
+                    outputs.push((segment.width(), output));
+                }
+            }
+            let widths: Vec<u16> = outputs.iter().map(|(w, _)| *w).collect();
+            let right = Layout::width() as f32;
+            for ((width, output), (x, separator_x)) in
+                outputs.iter().zip(Layout::segments(&widths))
+            {
+                // Styled spans fall back to the color of the whole output
+                let color = output.text_color(ColorScheme::foreground());
+                let parts: Vec<(&str, [f32; 4], bool)> = if output
+                    .spans
+                    .is_empty()
+                {
+                    vec![(&output.text, color, false)]
+                } else {
+                    output
+                        .spans
+                        .iter()
+                        .map(|s| {
+                            (s.text.as_str(), s.color.unwrap_or(color), s.bold)
+                        })
+                        .collect()
+                };
+                canvas.draw_button(
+                    right + x,
+                    f32::from(*width),
+                    "images/background.png",
+                    output.background,
+                    &parts,
+                )?;
+                canvas.draw_image(
+                    right + separator_x,
+                    10.,
+                    "images/separator.png",
+                )?;
+            }
+
+            let path = output.join(format!("frame-{:04}.png", frame));
+            debug!("Writing frame {}", path.display());
+            canvas.save(&path)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A decoded RGBA image
+struct Image {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+/// An RGBA pixel buffer with the size of the bar
+struct Canvas {
+    assets: Assets,
+    fonts: Fonts,
+    images: HashMap<String, Image>,
+    data: Vec<u8>,
+}
+
+impl Canvas {
+    fn new(assets: Assets, fonts: Fonts) -> Self {
+        Self {
+            assets,
+            fonts,
+            images: HashMap::new(),
+            data: vec![0; (Layout::width() * Layout::height() * 4) as usize],
+        }
+    }
+
+    /// Fill the whole canvas with a single color
+    fn clear(&mut self, color: [f32; 4]) {
+        for pixel in self.data.chunks_mut(4) {
+            for (p, c) in pixel.iter_mut().zip(color.iter()) {
+                *p = (c * 255.) as u8;
+            }
+        }
+    }
+
+    /// Draw a button with the height of the bar centered at `x`, whose
+    /// image is covered by the background color if set
+    fn draw_button(
+        &mut self,
+        x: f32,
+        width: f32,
+        image: &str,
+        background: Option<[f32; 4]>,
+        parts: &[(&str, [f32; 4], bool)],
+    ) -> Fallible<()> {
+        self.draw_image(x, width, image)?;
+        if let Some(background) = background {
+            self.fill(x, width, background);
+        }
+        self.draw_text(x, parts);
+        Ok(())
+    }
+
+    /// Fill an area with the height of the bar centered at `x`
+    fn fill(&mut self, x: f32, width: f32, color: [f32; 4]) {
+        let left = (x - width / 2.) as i32;
+        for dy in 0..Layout::height() as i32 {
+            for dx in 0..width as i32 {
+                Self::blend(&mut self.data, left + dx, dy, color, color[3]);
+            }
+        }
+    }
+
+    /// Draw a scaled image with the height of the bar centered at `x`
+    fn draw_image(&mut self, x: f32, width: f32, name: &str) -> Fallible<()> {
+        if !self.images.contains_key(name) {
+            let image = Self::load_image(&self.assets.read(name)?)?;
+            self.images.insert(name.to_owned(), image);
+        }
+        let image = &self.images[name];
+
+        let left = (x - width / 2.) as i32;
+        let height = Layout::height();
+        for dy in 0..height {
+            for dx in 0..width as u32 {
+                let sx = dx * image.width / width as u32;
+                let sy = dy * image.height / height;
+                let i = ((sy * image.width + sx) * 4) as usize;
+                let pixel = &image.data[i..i + 4];
+                let color = [
+                    f32::from(pixel[0]) / 255.,
+                    f32::from(pixel[1]) / 255.,
+                    f32::from(pixel[2]) / 255.,
+                    1.,
+                ];
+                Self::blend(
+                    &mut self.data,
+                    left + dx as i32,
+                    dy as i32,
+                    color,
+                    f32::from(pixel[3]) / 255.,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Draw differently colored parts of a text centered at `x`, where
+    /// every character is drawn with the first font containing it and bold
+    /// characters are drawn twice with an offset of a single pixel
+    fn draw_text(&mut self, x: f32, parts: &[(&str, [f32; 4], bool)]) {
+        let scale = Scale::uniform(self.fonts.size());
+        let v_metrics = self.fonts.font(0).v_metrics(scale);
+        let baseline =
+            (Layout::height() as f32 + v_metrics.ascent + v_metrics.descent)
+                / 2.;
+
+        // Lay out the characters, kerning those of the same font
+        let mut glyphs = vec![];
+        let mut caret = 0.;
+        let mut last: Option<(usize, char)> = None;
+        for (c, color, bold) in parts.iter().flat_map(|(t, color, bold)| {
+            t.chars().map(move |c| (c, *color, *bold))
+        }) {
+            let index = self.fonts.index(c);
+            let font = self.fonts.font(index);
+            if let Some((last_index, last_c)) = last {
+                if last_index == index {
+                    caret += font.pair_kerning(scale, last_c, c);
+                }
+            }
+            let glyph = font.glyph(c).scaled(scale);
+            let advance = glyph.h_metrics().advance_width;
+            glyphs.push((glyph.clone(), caret, color));
+            if bold {
+                glyphs.push((glyph, caret + 1., color));
+            }
+            caret += advance;
+            last = Some((index, c));
+        }
+
+        let left = x - caret / 2.;
+        let data = &mut self.data;
+        for (glyph, offset, color) in glyphs {
+            let glyph = glyph.positioned(point(left + offset, baseline));
+            if let Some(bounds) = glyph.pixel_bounding_box() {
+                glyph.draw(|gx, gy, coverage| {
+                    Self::blend(
+                        data,
+                        bounds.min.x + gx as i32,
+                        bounds.min.y + gy as i32,
+                        color,
+                        coverage,
+                    )
+                });
+            }
+        }
+    }
+
+    /// Blend a color with the provided coverage into a single pixel
+    fn blend(data: &mut [u8], x: i32, y: i32, color: [f32; 4], alpha: f32) {
+        if x < 0
+            || y < 0
+            || x >= Layout::width() as i32
+            || y >= Layout::height() as i32
+        {
+            return;
+        }
+        let i = ((y as u32 * Layout::width() + x as u32) * 4) as usize;
+        let alpha = alpha * color[3];
+        for (p, c) in data[i..i + 3].iter_mut().zip(color.iter()) {
+            *p = (f32::from(*p) * (1. - alpha) + c * 255. * alpha) as u8;
+        }
+        data[i + 3] = 255;
+    }
+
+    fn load_image(data: &[u8]) -> Fallible<Image> {
+        let (info, mut reader) = Decoder::new(data).read_info()?;
+        let mut data = vec![0; info.buffer_size()];
+        reader.next_frame(&mut data)?;
+
+        // Expand RGB images to RGBA
+        if info.color_type == ColorType::RGB {
+            data = data
+                .chunks(3)
+                .flat_map(|p| vec![p[0], p[1], p[2], 255])
+                .collect();
+        }
+
+        Ok(Image {
+            width: info.width,
+            height: info.height,
+            data,
+        })
+    }
+
+    /// Write the canvas into a PNG file
+    fn save(&self, path: &Path) -> Fallible<()> {
+        let mut encoder = Encoder::new(
+            BufWriter::new(File::create(path)?),
+            Layout::width(),
+            Layout::height(),
+        );
+        encoder.set(ColorType::RGBA).set(BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&self.data)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,105 @@
+//! Polling of HTTP resources on background threads
+
+use failure::{format_err, Fallible};
+use log::{debug, warn};
+use std::{
+    io::Read,
+    sync::mpsc::{self, Receiver},
+    thread,
+    time::Duration,
+};
+
+/// The timeout of a single request
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The maximum size of a response body in bytes
+const MAX_BODY: u64 = 1024 * 1024;
+
+/// The longest delay until failed requests are retried
+const RETRY: Duration = Duration::from_secs(60);
+
+/// Retrieve the body of a successful `GET` request with additional headers
+pub fn get(url: &str, headers: &[(String, String)]) -> Fallible<String> {
+    let mut request = ureq::get(url)
+        .timeout(TIMEOUT)
+        .set("User-Agent", concat!("unibar/", env!("CARGO_PKG_VERSION")));
+    for (name, value) in headers {
+        request = request.set(name, value);
+    }
+    let response = request
+        .call()
+        .map_err(|e| format_err!("request to {} failed: {}", url, e))?;
+
+    let mut body = String::new();
+    response
+        .into_reader()
+        .take(MAX_BODY)
+        .read_to_string(&mut body)?;
+    Ok(body)
+}
+
+/// Call `fetch` immediately and then every `interval` on a background
+/// thread, which stops once the receiver got dropped. Failures are retried
+/// after a minute at the latest.
+pub fn poll<T, F>(interval: Duration, mut fetch: F) -> Receiver<T>
+where
+    T: Send + 'static,
+    F: FnMut() -> Fallible<T> + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || loop {
+        let delay = match fetch() {
+            Ok(result) => {
+                if tx.send(result).is_err() {
+                    debug!("Stopped polling");
+                    return;
+                }
+                interval
+            }
+            Err(e) => {
+                warn!("Unable to poll: {}", e);
+                interval.min(RETRY)
+            }
+        };
+        thread::sleep(delay);
+    });
+    rx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::http::Server;
+
+    #[test]
+    fn requests() {
+        let server = Server::start();
+        server.respond("/ok", 200, "body");
+        server.respond("/missing", 404, "not found");
+
+        assert_eq!(get(&server.url("/ok"), &[]).unwrap(), "body");
+        assert!(get(&server.url("/missing"), &[]).is_err());
+        assert!(get(&server.url("/other"), &[]).is_err());
+        assert_eq!(server.requests(), vec!["/ok", "/missing", "/other"]);
+
+        let headers = [("X-Token".to_owned(), "secret".to_owned())];
+        assert_eq!(get(&server.url("/ok"), &headers).unwrap(), "body");
+        assert!(server.headers().contains(&"x-token: secret".to_owned()));
+    }
+
+    #[test]
+    fn polling() {
+        let mut count = 0;
+        let rx = poll(Duration::from_millis(10), move || {
+            count += 1;
+            if count == 2 {
+                return Err(format_err!("failure"));
+            }
+            Ok(count)
+        });
+
+        let timeout = Duration::from_secs(5);
+        assert_eq!(rx.recv_timeout(timeout).unwrap(), 1);
+        assert_eq!(rx.recv_timeout(timeout).unwrap(), 3);
+    }
+}
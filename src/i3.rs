@@ -0,0 +1,307 @@
+//! Connection handling for the i3 window manager
+
+use failure::{format_err, Fallible};
+use i3ipc::{
+    event::Event, reply::Workspace, I3Connection, I3EventListener, Subscription,
+};
+use log::{debug, error, info, warn};
+use std::{
+    cmp,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::Duration,
+};
+
+/// The initial delay between two reconnection attempts
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The maximum delay between two reconnection attempts
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Messages sent by the i3 event listener thread
+pub enum Message {
+    /// The connection to i3 has been established
+    Connected,
+
+    /// The connection to i3 has been lost
+    Disconnected,
+
+    /// An event has been received from i3
+    Event(Event),
+}
+
+/// A message together with the connection established for it
+type Update = (Message, Option<I3Connection>);
+
+/// An i3 connection which survives restarts of the window manager
+pub struct I3 {
+    connection: Option<I3Connection>,
+    receiver: Receiver<Update>,
+}
+
+impl I3 {
+    /// Create a new i3 instance and start listening for events
+    pub fn new() -> Self {
+        let (tx, rx): (Sender<Update>, Receiver<Update>) = mpsc::channel();
+        thread::spawn(move || Self::listen(&tx));
+
+        Self {
+            connection: None,
+            receiver: rx,
+        }
+    }
+
+    /// Retrieve all pending messages and update the connection accordingly
+    pub fn poll(&mut self) -> Vec<Message> {
+        let mut messages = vec![];
+        for (message, connection) in self.receiver.try_iter() {
+            match message {
+                Message::Connected => self.connection = connection,
+                Message::Disconnected => self.connection = None,
+                Message::Event(_) => {}
+            }
+            messages.push(message);
+        }
+        messages
+    }
+
+    /// Returns true if a connection to i3 is available
+    pub fn is_connected(&self) -> bool {
+        self.connection.is_some()
+    }
+
+    /// Retrieve all workspaces
+    pub fn workspaces(&mut self) -> Fallible<Vec<Workspace>> {
+        Ok(self.connection()?.get_workspaces()?.workspaces)
+    }
+
+    /// Switch to the workspace with the provided name
+    pub fn switch_workspace(&mut self, name: &str) -> Fallible<()> {
+        self.connection()?
+            .run_command(&format!("workspace {}", name))?;
+        Ok(())
+    }
+
+    fn connection(&mut self) -> Fallible<&mut I3Connection> {
+        self.connection
+            .as_mut()
+            .ok_or_else(|| format_err!("i3 is not connected"))
+    }
+
+    /// Listen for i3 events and reconnect with an increasing backoff, which
+    /// establishes the connection for commands as well
+    fn listen(tx: &Sender<Update>) {
+        let mut backoff = MIN_BACKOFF;
+        let mut connected = true;
+
+        loop {
+            match Self::subscribe() {
+                Ok((mut event_listener, connection)) => {
+                    info!("Connected to i3");
+                    backoff = MIN_BACKOFF;
+                    connected = true;
+                    if tx.send((Message::Connected, Some(connection))).is_err()
+                    {
+                        return;
+                    }
+
+                    for event in event_listener.listen() {
+                        match event {
+                            Ok(e) => {
+                                debug!("Received i3 event: {:?}", e);
+                                if let Err(err) =
+                                    tx.send((Message::Event(e), None))
+                                {
+                                    error!("Unable to send i3 event: {}", err);
+                                    return;
+                                }
+                            }
+                            Err(e) => {
+                                warn!("Lost i3 connection: {}", e);
+                                break;
+                            }
+                        }
+                    }
+                }
+                Err(e) => debug!("{}", e),
+            }
+
+            // Notify only once about a lost connection
+            if connected {
+                connected = false;
+                if tx.send((Message::Disconnected, None)).is_err() {
+                    return;
+                }
+            }
+
+            debug!("Reconnecting to i3 in {}s", backoff.as_secs());
+            thread::sleep(backoff);
+            backoff = cmp::min(backoff * 2, MAX_BACKOFF);
+        }
+    }
+
+    fn subscribe() -> Fallible<(I3EventListener, I3Connection)> {
+        let mut event_listener = I3EventListener::connect()
+            .map_err(|_| format_err!("unable to establish i3 connection"))?;
+
+        event_listener
+            .subscribe(&[Subscription::Workspace])
+            .map_err(|_| format_err!("unable to subscribe to i3 events"))?;
+
+        let connection = I3Connection::connect().map_err(|e| {
+            format_err!("unable to establish i3 connection: {}", e)
+        })?;
+        Ok((event_listener, connection))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{
+        i3::{self, Server},
+        wait::wait_until,
+    };
+
+    /// Poll the i3 instance until a message matches or the timeout exceeds
+    fn wait_for<F>(i3: &mut I3, matches: F) -> Vec<Message>
+    where
+        F: Fn(&Message) -> bool,
+    {
+        let mut messages = vec![];
+        wait_until(|| {
+            messages.extend(i3.poll());
+            messages.iter().any(&matches)
+        });
+        messages
+    }
+
+    fn is_connected(message: &Message) -> bool {
+        matches!(message, Message::Connected)
+    }
+
+    fn is_disconnected(message: &Message) -> bool {
+        matches!(message, Message::Disconnected)
+    }
+
+    fn is_event(message: &Message) -> bool {
+        matches!(message, Message::Event(_))
+    }
+
+    fn changes(messages: &[Message]) -> Vec<String> {
+        messages
+            .iter()
+            .filter_map(|m| match m {
+                Message::Event(Event::WorkspaceEvent(e)) => {
+                    Some(format!("{:?}", e.change))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn retrieve_workspaces() {
+        let _lock = i3::lock();
+        let path = i3::socket_path();
+        i3::set_socket_path(&path);
+        let server = Server::start(&path);
+        server.set_workspaces(&[
+            (1, "1:term", true, true),
+            (2, "2", false, false),
+        ]);
+
+        let mut i3 = I3::new();
+        wait_for(&mut i3, is_connected);
+        assert!(i3.is_connected());
+
+        let workspaces = i3.workspaces().unwrap();
+        assert_eq!(workspaces.len(), 2);
+        assert_eq!(workspaces[0].num, 1);
+        assert_eq!(workspaces[0].name, "1:term");
+        assert!(workspaces[0].focused);
+        assert_eq!(workspaces[1].name, "2");
+        assert!(!workspaces[1].visible);
+    }
+
+    #[test]
+    fn receive_events_in_order() {
+        let _lock = i3::lock();
+        let path = i3::socket_path();
+        i3::set_socket_path(&path);
+        let server = Server::start(&path);
+
+        let mut i3 = I3::new();
+        wait_for(&mut i3, is_connected);
+
+        server.send_workspace_event("init");
+        server.send_workspace_event("focus");
+        server.send_workspace_event("empty");
+
+        let mut messages = vec![];
+        while changes(&messages).len() < 3 {
+            messages.extend(wait_for(&mut i3, is_event));
+        }
+        assert_eq!(changes(&messages), vec!["Init", "Focus", "Empty"]);
+    }
+
+    #[test]
+    fn switch_workspace() {
+        let _lock = i3::lock();
+        let path = i3::socket_path();
+        i3::set_socket_path(&path);
+        let server = Server::start(&path);
+
+        let mut i3 = I3::new();
+        wait_for(&mut i3, is_connected);
+
+        i3.switch_workspace("2:web").unwrap();
+        i3.switch_workspace("3").unwrap();
+        assert_eq!(server.commands(), vec!["workspace 2:web", "workspace 3"]);
+    }
+
+    #[test]
+    fn start_disconnected() {
+        let _lock = i3::lock();
+        let path = i3::socket_path();
+        i3::set_socket_path(&path);
+
+        let mut i3 = I3::new();
+        wait_for(&mut i3, is_disconnected);
+        assert!(!i3.is_connected());
+        assert!(i3.workspaces().is_err());
+        assert!(i3.switch_workspace("1").is_err());
+
+        // Start i3 later on
+        let server = Server::start(&path);
+        server.set_workspaces(&[(1, "1", true, true)]);
+        wait_for(&mut i3, is_connected);
+        assert_eq!(i3.workspaces().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn reconnect_after_restart() {
+        let _lock = i3::lock();
+        let path = i3::socket_path();
+        i3::set_socket_path(&path);
+        let server = Server::start(&path);
+        server.set_workspaces(&[(1, "1", true, true)]);
+
+        let mut i3 = I3::new();
+        wait_for(&mut i3, is_connected);
+
+        server.restart();
+        server.set_workspaces(&[(1, "1", true, false), (2, "2", true, true)]);
+        let messages = wait_for(&mut i3, is_disconnected);
+        assert!(!messages.iter().any(is_connected));
+
+        wait_for(&mut i3, is_connected);
+        assert!(i3.is_connected());
+        assert_eq!(i3.workspaces().unwrap().len(), 2);
+
+        // Events of the new subscription are still received
+        server.send_workspace_event("focus");
+        let messages = wait_for(&mut i3, is_event);
+        assert_eq!(changes(&messages), vec!["Focus"]);
+    }
+}
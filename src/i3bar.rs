@@ -0,0 +1,406 @@
+//! Output of all segments via the i3bar JSON protocol
+
+use crate::{
+    color::ColorScheme,
+    config::SegmentLoader,
+    control::Control,
+    ipc::Ipc,
+    segment::{Button, Segments, Span},
+};
+use failure::Fallible;
+use log::{debug, warn, LevelFilter, Log, Metadata, Record};
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    io::{self, prelude::*},
+    sync::{
+        mpsc::{self, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// The bar, which prints the segments for a stock i3bar or swaybar
+pub struct I3bar;
+
+/// The header starting the protocol
+#[derive(Serialize)]
+struct Header {
+    version: u8,
+    click_events: bool,
+}
+
+/// A single block of a status line
+#[derive(Debug, PartialEq, Serialize)]
+struct Block {
+    full_text: String,
+    name: String,
+    instance: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    color: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    background: Option<String>,
+    #[serde(skip_serializing_if = "is_false")]
+    urgent: bool,
+    min_width: u16,
+    align: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    markup: Option<&'static str>,
+}
+
+/// A click event received from the bar
+#[derive(Debug, Deserialize, PartialEq)]
+struct Click {
+    name: Option<String>,
+    instance: Option<String>,
+    button: u8,
+    #[serde(default)]
+    x: i32,
+    #[serde(default)]
+    y: i32,
+}
+
+/// A logger writing to stderr, since stdout is reserved for the protocol
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        eprintln!(
+            "[{}][{}] {}",
+            record.level(),
+            record.target(),
+            record.args()
+        );
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+impl I3bar {
+    /// Run the loaded segments and print their output to stdout
+    pub fn run(
+        level_filter: LevelFilter,
+        loader: SegmentLoader,
+        ipc: Ipc,
+    ) -> Fallible<()> {
+        // Setup the internal logger
+        if log::set_logger(&LOGGER).is_ok() {
+            log::set_max_level(level_filter);
+        }
+        debug!("Logger setup done");
+
+        let mut control = Control::new(loader);
+        let segments = Arc::new(Mutex::new(control.load()?));
+
+        // Handle click events in the background
+        let (tx, rx) = mpsc::channel();
+        let click_segments = segments.clone();
+        thread::spawn(move || {
+            let stdin = io::stdin();
+            Self::read_clicks(stdin.lock(), &click_segments, &tx)
+        });
+
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        writeln!(
+            stdout,
+            "{}",
+            serde_json::to_string(&Header {
+                version: 1,
+                click_events: true,
+            })?
+        )?;
+        writeln!(stdout, "[")?;
+
+        let mut separator = "";
+        let mut last_update: Option<Instant> = None;
+        let mut clicked = false;
+        loop {
+            // Handle IPC requests
+            let requests = ipc.poll();
+            let changed = !requests.is_empty();
+            for (request, responder) in requests {
+                let response =
+                    control.handle(request, &mut segments.lock().unwrap());
+                responder.send(response).ok();
+            }
+
+            // Update every second or directly after a click
+            let update = clicked
+                || match last_update {
+                    Some(t) => t.elapsed() >= Duration::from_secs(1),
+                    None => true,
+                };
+            if update {
+                control.update(&mut segments.lock().unwrap());
+                last_update = Some(Instant::now());
+            }
+
+            if update || changed {
+                let blocks = Self::blocks(&segments.lock().unwrap(), &control);
+                writeln!(
+                    stdout,
+                    "{}{}",
+                    separator,
+                    serde_json::to_string(&blocks)?
+                )?;
+                stdout.flush()?;
+                separator = ",";
+            }
+
+            clicked = match rx.recv_timeout(Duration::from_millis(100)) {
+                Ok(()) => true,
+                Err(RecvTimeoutError::Timeout) => false,
+                Err(RecvTimeoutError::Disconnected) => {
+                    thread::sleep(Duration::from_millis(100));
+                    false
+                }
+            };
+        }
+    }
+
+    /// Convert the latest outputs of all visible segments into blocks
+    fn blocks(segments: &Segments, control: &Control) -> Vec<Block> {
+        if !control.is_visible() {
+            return vec![];
+        }
+        segments
+            .iter()
+            .filter(|segment| control.is_segment_visible(segment.id()))
+            .filter_map(|segment| {
+                control.output(segment.id()).map(|output| Block {
+                    full_text: if output.spans.is_empty() {
+                        output.text.clone()
+                    } else {
+                        Self::markup(&output.spans)
+                    },
+                    name: segment.name().to_owned(),
+                    instance: segment.id().to_owned(),
+                    color: output.color.map(ColorScheme::to_hex),
+                    background: output.background.map(ColorScheme::to_hex),
+                    urgent: output.urgent,
+                    min_width: segment.width(),
+                    align: "center",
+                    markup: if output.spans.is_empty() {
+                        None
+                    } else {
+                        Some("pango")
+                    },
+                })
+            })
+            .collect()
+    }
+
+    /// Convert styled spans into Pango markup
+    fn markup(spans: &[Span]) -> String {
+        let mut markup = String::new();
+        for span in spans {
+            let text = span
+                .text
+                .replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;");
+            let mut attributes = String::new();
+            if let Some(color) = span.color {
+                attributes +=
+                    &format!(" foreground=\"{}\"", ColorScheme::to_hex(color));
+            }
+            if span.bold {
+                attributes += " weight=\"bold\"";
+            }
+            if attributes.is_empty() {
+                markup += &text;
+            } else {
+                markup += &format!("<span{}>{}</span>", attributes, text);
+            }
+        }
+        markup
+    }
+
+    /// Read click events and forward them to the segments
+    fn read_clicks<R>(input: R, segments: &Mutex<Segments>, tx: &Sender<()>)
+    where
+        R: BufRead,
+    {
+        for line in input.lines().scan((), |_, x| x.ok()) {
+            if Self::click(&line, &mut segments.lock().unwrap())
+                && tx.send(()).is_err()
+            {
+                return;
+            }
+        }
+        debug!("Click event input closed");
+    }
+
+    /// Handle a single line of the click event stream, returns true if a
+    /// segment has been clicked
+    fn click(line: &str, segments: &mut Segments) -> bool {
+        // The events are elements of an infinite array
+        let line = line.trim().trim_start_matches(&['[', ','][..]);
+        if line.is_empty() {
+            return false;
+        }
+
+        let click: Click = match serde_json::from_str(line) {
+            Ok(c) => c,
+            Err(e) => {
+                warn!("Unable to parse click event '{}': {}", line, e);
+                return false;
+            }
+        };
+        debug!("Received click event: {:?}", click);
+
+        let button = match Button::from_number(click.button) {
+            Some(b) => b,
+            None => return false,
+        };
+        match segments
+            .iter_mut()
+            .find(|s| Some(s.id()) == click.instance.as_deref())
+        {
+            Some(segment) => {
+                segment.click_at(button, (click.x, click.y));
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ipc::Request, mock::segment::Counter};
+
+    fn segments() -> Segments {
+        vec![Box::new(Counter::new("a")), Box::new(Counter::new("b"))]
+    }
+
+    /// Update the segments and convert them into blocks
+    fn update_blocks(
+        segments: &mut Segments,
+        control: &mut Control,
+    ) -> Vec<Block> {
+        control.update(segments);
+        I3bar::blocks(segments, control)
+    }
+
+    #[test]
+    fn blocks() {
+        let mut segments = segments();
+        let blocks = I3bar::blocks(&segments, &Control::default());
+        assert!(blocks.is_empty());
+
+        let blocks = update_blocks(&mut segments, &mut Control::default());
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].full_text, "0");
+        assert_eq!(blocks[0].name, "counter");
+        assert_eq!(blocks[0].instance, "a");
+        assert_eq!(blocks[0].color, None);
+        assert_eq!(blocks[1].instance, "b");
+
+        assert_eq!(
+            serde_json::to_string(&blocks[0]).unwrap(),
+            r#"{"full_text":"0","name":"counter","instance":"a","min_width":100,"align":"center"}"#
+        );
+    }
+
+    #[test]
+    fn markup() {
+        let spans = vec![
+            Span {
+                text: "a < b & ".into(),
+                ..Span::default()
+            },
+            Span {
+                text: "c".into(),
+                color: Some(ColorScheme::red()),
+                bold: true,
+            },
+        ];
+        assert_eq!(
+            I3bar::markup(&spans),
+            "a &lt; b &amp; \
+             <span foreground=\"#ff5555\" weight=\"bold\">c</span>"
+        );
+    }
+
+    #[test]
+    fn blocks_urgent() {
+        let mut segments: Segments =
+            vec![Box::new(Counter::new("a").with_urgent_after(1))];
+        segments[0].click(Button::Left);
+        let blocks = update_blocks(&mut segments, &mut Control::default());
+        assert!(blocks[0].urgent);
+        assert_eq!(blocks[0].color, None);
+        assert!(serde_json::to_string(&blocks[0])
+            .unwrap()
+            .contains(r#""urgent":true"#));
+    }
+
+    #[test]
+    fn click() {
+        let mut segments = segments();
+        assert!(!I3bar::click("[", &mut segments));
+        assert!(I3bar::click(
+            r#"{"name":"counter","instance":"b","button":1,"x":1,"y":2}"#,
+            &mut segments
+        ));
+        assert!(I3bar::click(
+            r#",{"name":"counter","instance":"b","button":4}"#,
+            &mut segments
+        ));
+        assert!(!I3bar::click(
+            r#",{"instance":"c","button":1}"#,
+            &mut segments
+        ));
+        assert!(!I3bar::click(
+            r#",{"instance":"a","button":9}"#,
+            &mut segments
+        ));
+        assert!(!I3bar::click("invalid", &mut segments));
+
+        let blocks = update_blocks(&mut segments, &mut Control::default());
+        assert_eq!(blocks[0].full_text, "0");
+        assert_eq!(blocks[1].full_text, "2");
+    }
+
+    #[test]
+    fn blocks_hidden() {
+        let mut segments = segments();
+        let mut control = Control::default();
+        let hide = Request::Hide {
+            segment: "a".into(),
+        };
+        assert!(control.handle(hide, &mut segments).success);
+
+        let blocks = update_blocks(&mut segments, &mut control);
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].instance, "b");
+
+        assert!(control.handle(Request::Toggle, &mut segments).success);
+        assert!(update_blocks(&mut segments, &mut control).is_empty());
+    }
+
+    #[test]
+    fn read_clicks() {
+        let segments = Mutex::new(segments());
+        let (tx, rx) = mpsc::channel();
+        let input = "[\n\
+                     {\"instance\":\"a\",\"button\":1}\n\
+                     ,{\"instance\":\"a\",\"button\":3}\n";
+        I3bar::read_clicks(input.as_bytes(), &segments, &tx);
+        assert_eq!(rx.try_iter().count(), 2);
+    }
+}
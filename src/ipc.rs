@@ -0,0 +1,334 @@
+//! Control of a running bar via a Unix domain socket
+
+use failure::{format_err, Fallible};
+use log::{debug, warn};
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    env, fs,
+    io::{prelude::*, BufReader},
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::Duration,
+};
+
+/// The maximum time to wait for the bar to handle a request
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A request sent to a running bar
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum Request {
+    /// Set the text of a segment
+    Set {
+        /// The name or identifier of the segment
+        segment: String,
+
+        /// The new text
+        text: String,
+    },
+
+    /// Show a hidden segment
+    Show {
+        /// The name or identifier of the segment
+        segment: String,
+    },
+
+    /// Hide a segment
+    Hide {
+        /// The name or identifier of the segment
+        segment: String,
+    },
+
+    /// Toggle the visibility of the whole bar
+    Toggle,
+
+    /// Reload the configuration file
+    Reload,
+
+    /// Retrieve the current state of the bar
+    State,
+}
+
+/// The response to a request
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Response {
+    /// Indicates if the request has been handled successfully
+    pub success: bool,
+
+    /// The error message if not successful
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+
+    /// The state of the bar if requested
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state: Option<BarState>,
+}
+
+/// The current state of the bar
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct BarState {
+    /// Indicates if the bar is visible
+    pub visible: bool,
+
+    /// All segments ordered from left to right
+    pub segments: Vec<SegmentState>,
+}
+
+/// The current state of a single segment
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct SegmentState {
+    /// The unique identifier
+    pub id: String,
+
+    /// The name of the segment
+    pub name: String,
+
+    /// The currently displayed text
+    pub text: String,
+
+    /// Indicates if the segment is visible
+    pub visible: bool,
+
+    /// Indicates that the segment needs attention
+    pub urgent: bool,
+
+    /// The lines of the opened popup
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub popup: Vec<String>,
+}
+
+impl Response {
+    /// Create a successful response
+    pub fn ok() -> Self {
+        Self {
+            success: true,
+            ..Self::default()
+        }
+    }
+
+    /// Create a failed response with the provided message
+    pub fn error<T>(message: T) -> Self
+    where
+        T: Into<String>,
+    {
+        Self {
+            error: Some(message.into()),
+            ..Self::default()
+        }
+    }
+
+    /// Create a successful response containing the bar state
+    pub fn state(state: BarState) -> Self {
+        Self {
+            state: Some(state),
+            ..Self::ok()
+        }
+    }
+}
+
+/// The IPC server of the bar
+pub struct Ipc {
+    path: PathBuf,
+    receiver: Receiver<(Request, Sender<Response>)>,
+}
+
+impl Ipc {
+    /// The default socket path within the runtime directory
+    pub fn default_path() -> PathBuf {
+        env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(env::temp_dir)
+            .join("unibar.sock")
+    }
+
+    /// Start listening for requests on the provided socket path
+    pub fn start(path: &Path) -> Fallible<Self> {
+        // Remove stale sockets of previous instances
+        if path.exists() {
+            if UnixStream::connect(path).is_ok() {
+                return Err(format_err!(
+                    "socket {} is already in use by another bar",
+                    path.display()
+                ));
+            }
+            fs::remove_file(path)?;
+        }
+
+        let listener = UnixListener::bind(path).map_err(|e| {
+            format_err!("unable to bind socket {}: {}", path.display(), e)
+        })?;
+        debug!("Listening for IPC requests on {}", path.display());
+
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let tx = tx.clone();
+                        thread::spawn(move || {
+                            if let Err(e) = Self::serve(stream, &tx) {
+                                warn!("Unable to handle IPC request: {}", e)
+                            }
+                        });
+                    }
+                    Err(e) => warn!("Unable to accept IPC connection: {}", e),
+                }
+            }
+        });
+
+        Ok(Self {
+            path: path.to_owned(),
+            receiver: rx,
+        })
+    }
+
+    /// Retrieve all pending requests together with their response channel
+    pub fn poll(&self) -> Vec<(Request, Sender<Response>)> {
+        self.receiver.try_iter().collect()
+    }
+
+    /// Send a request to the bar listening on the provided socket path
+    pub fn send(path: &Path, request: &Request) -> Fallible<Response> {
+        let mut stream = UnixStream::connect(path).map_err(|e| {
+            format_err!("unable to connect to {}: {}", path.display(), e)
+        })?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        writeln!(stream, "{}", serde_json::to_string(request)?)?;
+
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line)?;
+        Ok(serde_json::from_str(&line)?)
+    }
+
+    /// Read a single request and write back its response
+    fn serve(
+        stream: UnixStream,
+        tx: &Sender<(Request, Sender<Response>)>,
+    ) -> Fallible<()> {
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line)?;
+        debug!("Received IPC request: {}", line.trim());
+
+        let response = match serde_json::from_str(&line) {
+            Ok(request) => {
+                let (response_tx, response_rx) = mpsc::channel();
+                tx.send((request, response_tx))
+                    .map_err(|_| format_err!("bar is not running"))?;
+                response_rx.recv_timeout(TIMEOUT)?
+            }
+            Err(e) => Response::error(format!("invalid request: {}", e)),
+        };
+        writeln!(&stream, "{}", serde_json::to_string(&response)?)?;
+        Ok(())
+    }
+}
+
+impl Drop for Ipc {
+    fn drop(&mut self) {
+        fs::remove_file(&self.path).ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::wait::wait_until;
+    use uuid::Uuid;
+
+    fn socket_path() -> PathBuf {
+        env::temp_dir().join(format!("unibar-test-{}.sock", Uuid::new_v4()))
+    }
+
+    /// Send a request in the background and answer it with the response
+    fn roundtrip(ipc: &Ipc, request: Request, response: Response) -> Response {
+        let path = ipc.path.clone();
+        let client = thread::spawn(move || Ipc::send(&path, &request));
+
+        let mut response = Some(response);
+        wait_until(|| match ipc.poll().pop() {
+            Some((_, tx)) => tx.send(response.take().unwrap()).is_ok(),
+            None => false,
+        });
+        client.join().unwrap().unwrap()
+    }
+
+    #[test]
+    fn request_format() {
+        assert_eq!(
+            serde_json::to_string(&Request::Set {
+                segment: "name".into(),
+                text: "text".into(),
+            })
+            .unwrap(),
+            r#"{"action":"set","segment":"name","text":"text"}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<Request>(r#"{"action":"toggle"}"#).unwrap(),
+            Request::Toggle
+        );
+    }
+
+    #[test]
+    fn send_requests() {
+        let path = socket_path();
+        let ipc = Ipc::start(&path).unwrap();
+
+        assert_eq!(
+            roundtrip(&ipc, Request::Reload, Response::ok()),
+            Response::ok()
+        );
+
+        let state = BarState {
+            visible: true,
+            segments: vec![SegmentState {
+                name: "text".into(),
+                ..SegmentState::default()
+            }],
+        };
+        assert_eq!(
+            roundtrip(&ipc, Request::State, Response::state(state.clone())),
+            Response::state(state)
+        );
+
+        let response =
+            roundtrip(&ipc, Request::Toggle, Response::error("failure"));
+        assert!(!response.success);
+        assert_eq!(response.error, Some("failure".into()));
+
+        drop(ipc);
+        assert!(!path.exists());
+        assert!(Ipc::send(&path, &Request::State).is_err());
+    }
+
+    #[test]
+    fn invalid_request() {
+        let path = socket_path();
+        let _ipc = Ipc::start(&path).unwrap();
+
+        let mut stream = UnixStream::connect(&path).unwrap();
+        writeln!(stream, "{{\"action\":\"unknown\"}}").unwrap();
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line).unwrap();
+
+        let response: Response = serde_json::from_str(&line).unwrap();
+        assert!(!response.success);
+        assert!(response.error.unwrap().starts_with("invalid request"));
+    }
+
+    #[test]
+    fn socket_in_use() {
+        let path = socket_path();
+        let _ipc = Ipc::start(&path).unwrap();
+        assert!(Ipc::start(&path).is_err());
+    }
+
+    #[test]
+    fn stale_socket() {
+        let path = socket_path();
+        drop(UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+        let _ipc = Ipc::start(&path).unwrap();
+    }
+}
@@ -0,0 +1,184 @@
+//! Extraction of values from JSON documents via paths like `$.jobs[0].state`
+
+use failure::{format_err, Fallible};
+use serde_json::Value;
+use std::str::FromStr;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Step {
+    /// A member of an object
+    Member(String),
+
+    /// An element of an array, where negative indices count from the end
+    Index(i64),
+
+    /// All members of an object or elements of an array
+    Wildcard,
+
+    /// The amount of members, elements or characters
+    Length,
+}
+
+/// A parsed path supporting members like `.name` or `['name']`, indices
+/// like `[0]` or `[-1]`, wildcards like `[*]` and a trailing `.length()`
+#[derive(Clone, Debug, PartialEq)]
+pub struct JsonPath {
+    steps: Vec<Step>,
+}
+
+impl FromStr for JsonPath {
+    type Err = failure::Error;
+
+    fn from_str(s: &str) -> Fallible<Self> {
+        let invalid =
+            |reason: &str| format_err!("invalid path '{}': {}", s, reason);
+        // Paths may omit the leading `$.` like `jobs[0]`
+        let trimmed = s.trim();
+        let (mut rest, mut bare) = match trimmed.strip_prefix('$') {
+            Some(rest) => (rest, false),
+            None => (trimmed, true),
+        };
+
+        let mut steps = vec![];
+        while !rest.is_empty() {
+            if steps.last() == Some(&Step::Length) {
+                return Err(invalid("length() has to be the last step"));
+            }
+            if let Some(r) = rest.strip_prefix('[') {
+                let end = r.find(']').ok_or_else(|| invalid("unclosed '['"))?;
+                let inner = r[..end].trim();
+                steps.push(match inner {
+                    "*" => Step::Wildcard,
+                    _ if inner.len() >= 2
+                        && (inner.starts_with('\'')
+                            && inner.ends_with('\'')
+                            || inner.starts_with('"')
+                                && inner.ends_with('"')) =>
+                    {
+                        Step::Member(inner[1..inner.len() - 1].to_owned())
+                    }
+                    _ => Step::Index(
+                        inner.parse().map_err(|_| invalid("invalid index"))?,
+                    ),
+                });
+                rest = &r[end + 1..];
+            } else {
+                let r = match rest.strip_prefix('.') {
+                    Some(r) => r,
+                    None if bare => rest,
+                    None => return Err(invalid("expected '.' or '['")),
+                };
+                let end = r.find(&['.', '['][..]).unwrap_or(r.len());
+                let name = &r[..end];
+                steps.push(match name {
+                    "" => return Err(invalid("empty member")),
+                    _ if name.contains(']') => {
+                        return Err(invalid("unmatched ']'"))
+                    }
+                    "*" => Step::Wildcard,
+                    "length()" => Step::Length,
+                    _ => Step::Member(name.to_owned()),
+                });
+                rest = &r[end..];
+            }
+            bare = false;
+        }
+        Ok(Self { steps })
+    }
+}
+
+impl JsonPath {
+    /// Retrieve all values matching the path, which are none if a step
+    /// does not exist
+    pub fn query(&self, json: &Value) -> Vec<Value> {
+        let mut values = vec![json.clone()];
+        for step in &self.steps {
+            values = values
+                .iter()
+                .flat_map(|value| match (step, value) {
+                    (Step::Member(name), Value::Object(o)) => {
+                        o.get(name).cloned().into_iter().collect()
+                    }
+                    (Step::Index(i), Value::Array(a)) => {
+                        let index =
+                            if *i < 0 { a.len() as i64 + i } else { *i };
+                        a.get(index as usize).cloned().into_iter().collect()
+                    }
+                    (Step::Wildcard, Value::Array(a)) => a.clone(),
+                    (Step::Wildcard, Value::Object(o)) => {
+                        o.values().cloned().collect()
+                    }
+                    (Step::Length, Value::Array(a)) => vec![a.len().into()],
+                    (Step::Length, Value::Object(o)) => vec![o.len().into()],
+                    (Step::Length, Value::String(s)) => {
+                        vec![s.chars().count().into()]
+                    }
+                    _ => vec![],
+                })
+                .collect();
+        }
+        values
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn query(path: &str, json: &Value) -> Vec<Value> {
+        path.parse::<JsonPath>().unwrap().query(json)
+    }
+
+    #[test]
+    fn paths() {
+        let json = json!({
+            "queue": {"depth": 42, "name": "builds"},
+            "deploys": [
+                {"service": "api", "status": "done"},
+                {"service": "web", "status": "failed"}
+            ],
+            "odd key": true
+        });
+        assert_eq!(query("$", &json), vec![json.clone()]);
+        assert_eq!(query("$.queue.depth", &json), vec![json!(42)]);
+        assert_eq!(query("queue.name", &json), vec![json!("builds")]);
+        assert_eq!(query("$['odd key']", &json), vec![json!(true)]);
+        assert_eq!(query("$[\"queue\"].depth", &json), vec![json!(42)]);
+        assert_eq!(query("$.deploys[1].status", &json), vec![json!("failed")]);
+        assert_eq!(query("$.deploys[-2].service", &json), vec![json!("api")]);
+        assert_eq!(
+            query("$.deploys[*].service", &json),
+            vec![json!("api"), json!("web")]
+        );
+        assert_eq!(query("$.queue.*", &json).len(), 2);
+        assert_eq!(query("$.deploys.length()", &json), vec![json!(2)]);
+        assert_eq!(query("$.queue.name.length()", &json), vec![json!(6)]);
+    }
+
+    #[test]
+    fn missing() {
+        let json = json!({"a": [1, 2], "b": "text"});
+        assert!(query("$.c", &json).is_empty());
+        assert!(query("$.a[2]", &json).is_empty());
+        assert!(query("$.a[-3]", &json).is_empty());
+        assert!(query("$.b.c", &json).is_empty());
+        assert!(query("$.b[0]", &json).is_empty());
+    }
+
+    #[test]
+    fn invalid() {
+        for path in &[
+            "$.",
+            "$..a",
+            "$[",
+            "$[x]",
+            "$a",
+            "$.a.length().b",
+            "$[]",
+            "a]",
+        ] {
+            assert!(path.parse::<JsonPath>().is_err(), "{}", path);
+        }
+    }
+}
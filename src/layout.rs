@@ -0,0 +1,196 @@
+//! Layout definitions shared by all renderers
+
+/// The layout of the bar
+pub struct Layout;
+
+impl Layout {
+    /// The width of the bar in pixels
+    pub const fn width() -> u32 {
+        1000
+    }
+
+    /// The height of the bar in pixels
+    pub const fn height() -> u32 {
+        20
+    }
+
+    /// The horizontal center of a workspace relative to the left
+    pub fn workspace_x(num: i32) -> f32 {
+        (20 * (num - 1) + 10) as f32
+    }
+
+    /// The width of a single workspace
+    pub const fn workspace_width() -> f32 {
+        20.
+    }
+
+    /// The horizontal center and width of the disconnected workspace
+    pub const fn disconnected() -> (f32, f32) {
+        (70., 140.)
+    }
+
+    /// The maximum amount of lines within a popup
+    pub const fn popup_lines() -> usize {
+        10
+    }
+
+    /// The width of a popup in pixels
+    pub const fn popup_width() -> f32 {
+        400.
+    }
+
+    /// The horizontal center of a popup belonging to the segment centered at
+    /// `x` relative to the right, which keeps the popup within the bar
+    pub fn popup_x(x: f32) -> f32 {
+        x.min(-(Self::popup_width() / 2. + 5.))
+    }
+
+    /// The vertical center of a popup line relative to the top
+    pub fn popup_y(line: usize) -> f32 {
+        -(Self::height() as f32) * (line as f32 + 1.) - 10.
+    }
+
+    /// The approximate width of a single character of the bar font
+    pub const fn char_width() -> f32 {
+        8.5
+    }
+
+    /// Retrieve the visible part of characters exceeding the provided width,
+    /// which scrolled by the provided amount of characters. The characters
+    /// may carry their style, whereas the padding separates the end of the
+    /// text from its restarting beginning.
+    pub fn scroll<T>(
+        chars: &[T],
+        padding: T,
+        width: u16,
+        offset: usize,
+    ) -> Vec<T>
+    where
+        T: Clone,
+    {
+        let visible = (f32::from(width) / Self::char_width()) as usize;
+        if chars.len() <= visible {
+            return chars.to_vec();
+        }
+
+        // Separate the end of the text from its restarting beginning
+        let chars: Vec<T> =
+            chars.iter().cloned().chain(vec![padding; 3]).collect();
+        chars
+            .iter()
+            .cycle()
+            .skip(offset % chars.len())
+            .take(visible)
+            .cloned()
+            .collect()
+    }
+
+    /// The maximum amount of differently styled runs a text is drawn with
+    pub const fn text_runs() -> usize {
+        8
+    }
+
+    /// The size of a square icon within a segment in pixels
+    pub const fn icon_size() -> f32 {
+        16.
+    }
+
+    /// The horizontal center of the icon with the provided index, where the
+    /// `count` icons are centered at `x` with a gap in between
+    pub fn icon_x(x: f32, index: usize, count: usize) -> f32 {
+        let spacing = Self::icon_size() + 4.;
+        x + (index as f32 - (count as f32 - 1.) / 2.) * spacing
+    }
+
+    /// The horizontal center of the icon with the provided index, where the
+    /// icons are aligned to the left of a segment centered at `x`
+    pub fn leading_icon_x(x: f32, width: u16, index: usize) -> f32 {
+        let spacing = Self::icon_size() + 4.;
+        x - f32::from(width) / 2.
+            + 4.
+            + Self::icon_size() / 2.
+            + index as f32 * spacing
+    }
+
+    /// The horizontal offset of a text following `count` leading icons
+    pub fn leading_text_x(count: usize) -> f32 {
+        count as f32 * (Self::icon_size() + 4.) / 2.
+    }
+
+    /// Retrieve the horizontal centers of segments with the provided widths
+    /// and their separators relative to the right, ordered from left to right
+    pub fn segments(widths: &[u16]) -> Vec<(f32, f32)> {
+        let mut right = 5.;
+        let mut positions: Vec<(f32, f32)> = widths
+            .iter()
+            .rev()
+            .map(|width| {
+                let width = f32::from(*width);
+                let position = (-(right + width / 2.), -(right + width + 10.));
+                right += width + 20.;
+                position
+            })
+            .collect();
+        positions.reverse();
+        positions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn marquee(text: &str, width: u16, offset: usize) -> String {
+        let chars: Vec<char> = text.chars().collect();
+        Layout::scroll(&chars, ' ', width, offset)
+            .into_iter()
+            .collect()
+    }
+
+    #[test]
+    fn scroll() {
+        assert_eq!(marquee("short", 100, 3), "short");
+
+        let text = "a rather long notification";
+        assert_eq!(marquee(text, 85, 0), "a rather l");
+        assert_eq!(marquee(text, 85, 2), "rather lon");
+        assert_eq!(marquee(text, 85, 25), "n   a rath");
+        assert_eq!(marquee(text, 85, 29), "a rather l");
+
+        // Styles scroll along with their characters
+        let chars: Vec<(char, bool)> =
+            "bold text".chars().map(|c| (c, c != ' ')).collect();
+        assert_eq!(
+            Layout::scroll(&chars, (' ', false), 51, 5),
+            vec![
+                ('t', true),
+                ('e', true),
+                ('x', true),
+                ('t', true),
+                (' ', false),
+                (' ', false)
+            ]
+        );
+    }
+
+    #[test]
+    fn popup() {
+        assert_eq!(Layout::popup_x(-50.), -205.);
+        assert_eq!(Layout::popup_x(-500.), -500.);
+        assert_eq!(Layout::popup_y(0), -30.);
+        assert_eq!(Layout::popup_y(1), -50.);
+    }
+
+    #[test]
+    fn icons() {
+        assert_eq!(Layout::icon_x(-50., 0, 1), -50.);
+        assert_eq!(Layout::icon_x(-50., 0, 2), -60.);
+        assert_eq!(Layout::icon_x(-50., 1, 2), -40.);
+        assert_eq!(Layout::icon_x(-50., 2, 3), -30.);
+
+        assert_eq!(Layout::leading_icon_x(-50., 100, 0), -88.);
+        assert_eq!(Layout::leading_icon_x(-50., 100, 1), -68.);
+        assert_eq!(Layout::leading_text_x(0), 0.);
+        assert_eq!(Layout::leading_text_x(1), 10.);
+    }
+}
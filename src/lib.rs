@@ -1,12 +1,47 @@
 //! The main library interface
+//!
+//! The segments are independent from any renderer and produce a structured
+//! output, which can be printed for i3bar via the JSON protocol. The GPU
+//! accelerated bar is available via the `gui` feature.
 #![deny(missing_docs)]
 
+#[cfg(any(feature = "dbus", feature = "gui"))]
+mod assets;
+#[cfg(feature = "gui")]
 mod bar;
+#[cfg(feature = "gui")]
 mod bundle;
-mod color;
-mod segment;
+#[cfg(feature = "dbus")]
+mod bus;
+pub mod color;
+pub mod config;
+mod control;
+#[cfg(feature = "gui")]
+mod font;
+#[cfg(feature = "gui")]
+mod headless;
+mod http;
+#[cfg(feature = "gui")]
+mod i3;
+mod i3bar;
+pub mod ipc;
+mod jsonpath;
+#[cfg(feature = "gui")]
+mod layout;
+#[cfg(test)]
+mod mock;
+pub mod segment;
+mod source;
+#[cfg(feature = "gui")]
 mod state;
+mod stop;
+#[cfg(feature = "gui")]
 mod system;
+mod template;
+#[cfg(feature = "gui")]
 mod workspace;
 
-pub use crate::bar::Bar;
+pub use crate::{i3bar::I3bar, source::SysSource};
+
+#[cfg(feature = "gui")]
+pub use crate::{bar::Bar, headless::Headless};
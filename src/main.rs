@@ -1,7 +1,12 @@
-use clap::{crate_version, load_yaml, App};
-use failure::Fallible;
+use clap::{crate_version, load_yaml, App, ArgMatches};
+use failure::{err_msg, format_err, Fallible};
 use log::LevelFilter;
-use unibar::Bar;
+use std::path::{Path, PathBuf};
+use unibar::{
+    config::SegmentLoader,
+    ipc::{Ipc, Request},
+    I3bar, SysSource,
+};
 
 fn main() -> Fallible<()> {
     // Load the CLI parameters from YAML
@@ -17,8 +22,89 @@ fn main() -> Fallible<()> {
         4 | _ => LevelFilter::Trace,
     };
 
+    // The control socket of the bar
+    let socket = matches
+        .value_of("socket")
+        .map(PathBuf::from)
+        .unwrap_or_else(Ipc::default_path);
+
+    // Send a message to a running bar
+    if let Some(matches) = matches.subcommand_matches("msg") {
+        return send_message(matches, &socket);
+    }
+
+    // The segments are created from the configuration and the system data
+    let loader = SegmentLoader::new(
+        matches.value_of("config").map(PathBuf::from),
+        SysSource::new(matches.value_of("sysroot").unwrap_or("/")),
+    );
+
     // Init and start the bar
-    Bar::run(level_filter)?;
+    match matches.value_of("output") {
+        Some("i3bar") => I3bar::run(level_filter, loader, Ipc::start(&socket)?),
+        _ => run_gui(&matches, level_filter, loader, &socket),
+    }
+}
+
+fn send_message(matches: &ArgMatches, socket: &Path) -> Fallible<()> {
+    let segment = || {
+        matches
+            .value_of("segment")
+            .map(str::to_owned)
+            .ok_or_else(|| format_err!("no segment provided"))
+    };
+    let request = match matches.value_of("action") {
+        Some("set") => Request::Set {
+            segment: segment()?,
+            text: matches.value_of("text").unwrap_or_default().to_owned(),
+        },
+        Some("show") => Request::Show {
+            segment: segment()?,
+        },
+        Some("hide") => Request::Hide {
+            segment: segment()?,
+        },
+        Some("toggle") => Request::Toggle,
+        Some("reload") => Request::Reload,
+        _ => Request::State,
+    };
 
+    let response = Ipc::send(socket, &request)?;
+    if let Some(error) = response.error {
+        return Err(err_msg(error));
+    }
+    if let Some(state) = response.state {
+        println!("{}", serde_json::to_string_pretty(&state)?);
+    }
     Ok(())
 }
+
+#[cfg(feature = "gui")]
+fn run_gui(
+    matches: &ArgMatches,
+    level_filter: LevelFilter,
+    loader: SegmentLoader,
+    socket: &Path,
+) -> Fallible<()> {
+    use clap::value_t;
+    use unibar::{Bar, Headless};
+
+    if let Some(dir) = matches.value_of("headless") {
+        let frames = value_t!(matches, "frames", u32)?;
+        Headless::run(level_filter, Path::new(dir), frames, loader)
+    } else {
+        Bar::run(level_filter, loader, Ipc::start(socket)?)
+    }
+}
+
+#[cfg(not(feature = "gui"))]
+fn run_gui(
+    _: &ArgMatches,
+    _: LevelFilter,
+    _: SegmentLoader,
+    _: &Path,
+) -> Fallible<()> {
+    Err(err_msg(
+        "unibar has been built without the gui feature, use '--output i3bar'",
+    ))
+}
@@ -0,0 +1,91 @@
+//! A stub sound server
+
+use crate::segment::{AudioBackend, Sink};
+use failure::{format_err, Fallible};
+use std::sync::{mpsc::Sender, Arc, Mutex};
+
+#[derive(Default)]
+struct State {
+    sink: Sink,
+    queries: usize,
+    failing: bool,
+    events: Option<Sender<()>>,
+}
+
+/// A backend whose clones share the same sink
+#[derive(Clone, Default)]
+pub struct Backend {
+    state: Arc<Mutex<State>>,
+}
+
+impl Backend {
+    /// Create a new backend providing the sink
+    pub fn new(sink: Sink) -> Self {
+        let backend = Self::default();
+        backend.state.lock().unwrap().sink = sink;
+        backend
+    }
+
+    /// Retrieve the current sink without counting it as query
+    pub fn sink(&self) -> Sink {
+        self.state.lock().unwrap().sink
+    }
+
+    /// Retrieve the amount of sink queries
+    pub fn queries(&self) -> usize {
+        self.state.lock().unwrap().queries
+    }
+
+    /// Change the sink without sending an event
+    pub fn change<F>(&self, f: F)
+    where
+        F: FnOnce(&mut Sink),
+    {
+        f(&mut self.state.lock().unwrap().sink)
+    }
+
+    /// Send an event to the subscriber
+    pub fn notify(&self) {
+        if let Some(events) = &self.state.lock().unwrap().events {
+            events.send(()).ok();
+        }
+    }
+
+    /// Let all requests fail as if no server is running
+    pub fn fail(&self, failing: bool) {
+        self.state.lock().unwrap().failing = failing;
+    }
+
+    fn with_state<F, T>(&self, f: F) -> Fallible<T>
+    where
+        F: FnOnce(&mut State) -> T,
+    {
+        let mut state = self.state.lock().unwrap();
+        if state.failing {
+            return Err(format_err!("connection refused"));
+        }
+        Ok(f(&mut state))
+    }
+}
+
+impl AudioBackend for Backend {
+    fn sink(&mut self) -> Fallible<Sink> {
+        self.with_state(|s| {
+            s.queries += 1;
+            s.sink
+        })
+    }
+
+    fn set_volume(&mut self, volume: u32) -> Fallible<()> {
+        self.with_state(|s| s.sink.volume = volume)
+    }
+
+    fn set_muted(&mut self, muted: bool) -> Fallible<()> {
+        self.with_state(|s| s.sink.muted = muted)
+    }
+
+    fn subscribe(&mut self, events: Sender<()>) -> Fallible<()> {
+        self.state.lock().unwrap().events = Some(events);
+        Ok(())
+    }
+}
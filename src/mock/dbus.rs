@@ -0,0 +1,46 @@
+//! A private D-Bus daemon
+
+use std::{
+    io::{prelude::*, BufReader},
+    process::{Child, Command, Stdio},
+};
+
+/// A private session bus, which stops on drop
+pub struct Bus {
+    daemon: Child,
+    address: String,
+}
+
+impl Bus {
+    /// Start a new dbus-daemon
+    pub fn start() -> Self {
+        let mut daemon = Command::new("dbus-daemon")
+            .arg("--session")
+            .arg("--nofork")
+            .arg("--print-address")
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("unable to start dbus-daemon");
+
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.as_mut().unwrap())
+            .read_line(&mut address)
+            .unwrap();
+        Self {
+            daemon,
+            address: address.trim().to_owned(),
+        }
+    }
+
+    /// Retrieve the address to connect to
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+}
+
+impl Drop for Bus {
+    fn drop(&mut self) {
+        self.daemon.kill().ok();
+        self.daemon.wait().ok();
+    }
+}
@@ -0,0 +1,138 @@
+//! A local HTTP server answering requests with configured responses
+
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// The responses as `(status, body)` per path including the query
+type Responses = Arc<Mutex<HashMap<String, (u16, String)>>>;
+
+/// A server listening on a random local port until dropped
+pub struct Server {
+    port: u16,
+    responses: Responses,
+    requests: Arc<Mutex<Vec<String>>>,
+    headers: Arc<Mutex<Vec<String>>>,
+    stopped: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Server {
+    /// Start a new server, which answers unknown paths with a 404 status
+    pub fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let responses: Responses = Arc::new(Mutex::new(HashMap::new()));
+        let requests = Arc::new(Mutex::new(vec![]));
+        let headers = Arc::new(Mutex::new(vec![]));
+        let stopped = Arc::new(AtomicBool::new(false));
+
+        let (r, q, h, stop) = (
+            responses.clone(),
+            requests.clone(),
+            headers.clone(),
+            stopped.clone(),
+        );
+        let handle = thread::spawn(move || {
+            while !stop.load(Ordering::SeqCst) {
+                match listener.accept() {
+                    Ok((stream, _)) => Self::serve(stream, &r, &q, &h),
+                    Err(_) => thread::sleep(Duration::from_millis(10)),
+                }
+            }
+        });
+
+        Self {
+            port,
+            responses,
+            requests,
+            headers,
+            stopped,
+            handle: Some(handle),
+        }
+    }
+
+    /// Retrieve the URL of a path on the server
+    pub fn url(&self, path: &str) -> String {
+        format!("http://127.0.0.1:{}{}", self.port, path)
+    }
+
+    /// Answer requests of a path, including its query, with the provided
+    /// status and body
+    pub fn respond(&self, path: &str, status: u16, body: &str) {
+        self.responses
+            .lock()
+            .unwrap()
+            .insert(path.to_owned(), (status, body.to_owned()));
+    }
+
+    /// Retrieve the paths of all requests
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Retrieve the lowercase header lines of the last request
+    pub fn headers(&self) -> Vec<String> {
+        self.headers.lock().unwrap().clone()
+    }
+
+    fn serve(
+        stream: TcpStream,
+        responses: &Responses,
+        requests: &Mutex<Vec<String>>,
+        headers: &Mutex<Vec<String>>,
+    ) {
+        stream.set_nonblocking(false).unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut request = String::new();
+        reader.read_line(&mut request).unwrap();
+        let path = request.split(' ').nth(1).unwrap_or_default().to_owned();
+
+        // Requests of the client have no body
+        let mut lines = vec![];
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap() > 2 {
+            lines.push(line.trim().to_lowercase());
+            line.clear();
+        }
+        *headers.lock().unwrap() = lines;
+
+        let (status, body) = responses
+            .lock()
+            .unwrap()
+            .get(&path)
+            .cloned()
+            .unwrap_or_else(|| (404, String::new()));
+        requests.lock().unwrap().push(path);
+        write!(
+            reader.get_mut(),
+            "HTTP/1.1 {} Status\r\n\
+             Content-Type: application/json\r\n\
+             Content-Length: {}\r\n\
+             Connection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        )
+        .unwrap();
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        if let Some(handle) = self.handle.take() {
+            handle.join().ok();
+        }
+    }
+}
@@ -0,0 +1,235 @@
+//! A mock i3 IPC server speaking the binary i3 protocol, which is shared
+//! with Sway
+
+// Only the Sway parts are used without the bar
+#![cfg_attr(not(feature = "gui"), allow(dead_code))]
+
+use serde_json::{json, Value};
+use std::{
+    env, fs,
+    io::{prelude::*, ErrorKind},
+    net::Shutdown,
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
+    thread,
+};
+use uuid::Uuid;
+
+/// The magic string which prefixes every message
+const MAGIC: &[u8] = b"i3-ipc";
+
+/// Message type to run a command
+const RUN_COMMAND: u32 = 0;
+
+/// Message type to retrieve all workspaces
+const GET_WORKSPACES: u32 = 1;
+
+/// Message type to subscribe to events
+const SUBSCRIBE: u32 = 2;
+
+/// Message type to retrieve all Sway inputs
+const GET_INPUTS: u32 = 100;
+
+/// Event type for workspace changes
+const WORKSPACE_EVENT: u32 = 0x8000_0000;
+
+/// Event type for Sway input changes
+const INPUT_EVENT: u32 = 0x8000_0015;
+
+/// Serializes all tests which modify the `I3SOCK` environment variable
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// Acquire exclusive access to the `I3SOCK` environment variable
+pub fn lock() -> MutexGuard<'static, ()> {
+    LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Point all new i3 connections to the provided socket path
+pub fn set_socket_path(path: &Path) {
+    env::set_var("I3SOCK", path);
+}
+
+/// Retrieve a unique socket path which does not exist yet
+pub fn socket_path() -> PathBuf {
+    env::temp_dir().join(format!("unibar-i3-{}.sock", Uuid::new_v4()))
+}
+
+#[derive(Default)]
+struct State {
+    stopped: bool,
+    workspaces: Vec<Value>,
+    inputs: Vec<Value>,
+    commands: Vec<String>,
+    connections: Vec<UnixStream>,
+    subscribers: Vec<UnixStream>,
+}
+
+/// The mock server, which stops listening if dropped
+pub struct Server {
+    path: PathBuf,
+    state: Arc<Mutex<State>>,
+}
+
+impl Server {
+    /// Start a new server on the provided socket path
+    pub fn start(path: &Path) -> Self {
+        let listener = UnixListener::bind(path).expect("bind i3 socket");
+        let state = Arc::new(Mutex::new(State::default()));
+
+        let accept_state = state.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(s) => s,
+                    Err(_) => return,
+                };
+
+                let mut state = accept_state.lock().unwrap();
+                if state.stopped {
+                    return;
+                }
+                state.connections.push(stream.try_clone().unwrap());
+                drop(state);
+
+                let handler_state = accept_state.clone();
+                thread::spawn(move || Self::handle(stream, &handler_state));
+            }
+        });
+
+        Self {
+            path: path.to_owned(),
+            state,
+        }
+    }
+
+    /// Set the workspaces as tuples of `(num, name, visible, focused)`
+    pub fn set_workspaces(&self, workspaces: &[(i32, &str, bool, bool)]) {
+        self.state.lock().unwrap().workspaces = workspaces
+            .iter()
+            .map(|(num, name, visible, focused)| {
+                json!({
+                    "num": num,
+                    "name": name,
+                    "visible": visible,
+                    "focused": focused,
+                    "urgent": false,
+                    "rect": {"x": 0, "y": 0, "width": 1920, "height": 1080},
+                    "output": "eDP-1",
+                })
+            })
+            .collect();
+    }
+
+    /// Set a single Sway keyboard with the provided layouts
+    pub fn set_keyboard(&self, layouts: &[&str], active: usize) {
+        self.state.lock().unwrap().inputs = vec![
+            json!({"identifier": "power-button", "type": "switch"}),
+            json!({
+                "identifier": "1:1:AT_Translated_Set_2_keyboard",
+                "type": "keyboard",
+                "xkb_layout_names": layouts,
+                "xkb_active_layout_index": active,
+            }),
+        ];
+    }
+
+    /// Retrieve all commands which have been run so far
+    pub fn commands(&self) -> Vec<String> {
+        self.state.lock().unwrap().commands.clone()
+    }
+
+    /// Send a workspace event with the provided change to all subscribers
+    pub fn send_workspace_event(&self, change: &str) {
+        let payload = json!({"change": change, "current": null, "old": null});
+        let mut state = self.state.lock().unwrap();
+        state
+            .subscribers
+            .retain(|s| write_message(s, WORKSPACE_EVENT, &payload).is_ok());
+    }
+
+    /// Send a Sway input event with the provided change to all subscribers
+    pub fn send_input_event(&self, change: &str) {
+        let payload = json!({"change": change, "input": null});
+        let mut state = self.state.lock().unwrap();
+        state
+            .subscribers
+            .retain(|s| write_message(s, INPUT_EVENT, &payload).is_ok());
+    }
+
+    /// Close all connections like an `i3-msg restart` does
+    pub fn restart(&self) {
+        let mut state = self.state.lock().unwrap();
+        for stream in state.connections.drain(..) {
+            stream.shutdown(Shutdown::Both).ok();
+        }
+        state.subscribers.clear();
+    }
+
+    fn handle(mut stream: UnixStream, state: &Mutex<State>) {
+        while let Ok((message_type, payload)) = read_message(&mut stream) {
+            let mut state = state.lock().unwrap();
+            let reply = match message_type {
+                RUN_COMMAND => {
+                    state
+                        .commands
+                        .push(String::from_utf8_lossy(&payload).into_owned());
+                    json!([{"success": true}])
+                }
+                GET_WORKSPACES => Value::Array(state.workspaces.clone()),
+                GET_INPUTS => Value::Array(state.inputs.clone()),
+                SUBSCRIBE => {
+                    if let Ok(s) = stream.try_clone() {
+                        state.subscribers.push(s);
+                    }
+                    json!({"success": true})
+                }
+                _ => json!({"success": false}),
+            };
+            if write_message(&stream, message_type, &reply).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.state.lock().unwrap().stopped = true;
+        self.restart();
+
+        // Wake up the accept loop so that it notices the stopped server
+        UnixStream::connect(&self.path).ok();
+        fs::remove_file(&self.path).ok();
+    }
+}
+
+fn read_message(stream: &mut UnixStream) -> std::io::Result<(u32, Vec<u8>)> {
+    let mut header = [0; 14];
+    stream.read_exact(&mut header)?;
+    if &header[..6] != MAGIC {
+        return Err(ErrorKind::InvalidData.into());
+    }
+
+    let mut length = [0; 4];
+    let mut message_type = [0; 4];
+    length.copy_from_slice(&header[6..10]);
+    message_type.copy_from_slice(&header[10..14]);
+
+    let mut payload = vec![0; u32::from_le_bytes(length) as usize];
+    stream.read_exact(&mut payload)?;
+    Ok((u32::from_le_bytes(message_type), payload))
+}
+
+fn write_message(
+    mut stream: &UnixStream,
+    message_type: u32,
+    payload: &Value,
+) -> std::io::Result<()> {
+    let payload = payload.to_string();
+    let mut message = MAGIC.to_vec();
+    message.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    message.extend_from_slice(&message_type.to_le_bytes());
+    message.extend_from_slice(payload.as_bytes());
+    stream.write_all(&message)
+}
@@ -0,0 +1,114 @@
+//! A stub logind session setting the brightness of fixture devices
+
+use crate::{mock::dbus::Bus, source::SysSource};
+use dbus::{
+    blocking::Connection,
+    channel::{Channel, MatchingReceiver, Sender},
+    message::MatchRule,
+    strings::ErrorName,
+    Message,
+};
+use std::{
+    ffi::CString,
+    fs,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// A brightness change as `(subsystem, device, brightness)`
+pub type Call = (String, String, u32);
+
+/// A session owning `org.freedesktop.login1` until dropped
+pub struct Logind {
+    calls: Arc<Mutex<Vec<Call>>>,
+    stopped: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Logind {
+    /// Start a new session writing the brightness below the source
+    pub fn start(bus: &Bus, source: SysSource) -> Self {
+        let calls = Arc::new(Mutex::new(vec![]));
+        let stopped = Arc::new(AtomicBool::new(false));
+
+        let (tx, rx) = mpsc::channel();
+        let address = bus.address().to_owned();
+        let (c, stop) = (calls.clone(), stopped.clone());
+        let handle = thread::spawn(move || {
+            let mut channel = Channel::open_private(&address).unwrap();
+            channel.register().unwrap();
+            let connection = Connection::from(channel);
+            connection
+                .request_name("org.freedesktop.login1", false, false, true)
+                .unwrap();
+
+            connection.start_receive(
+                MatchRule::new_method_call(),
+                Box::new(move |msg, conn| {
+                    conn.send(Self::reply(&msg, &source, &c)).ok();
+                    true
+                }),
+            );
+            tx.send(()).unwrap();
+
+            while !stop.load(Ordering::SeqCst) {
+                connection.process(Duration::from_millis(10)).unwrap();
+            }
+        });
+        rx.recv().unwrap();
+
+        Self {
+            calls,
+            stopped,
+            handle: Some(handle),
+        }
+    }
+
+    /// Retrieve all brightness changes
+    pub fn calls(&self) -> Vec<Call> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    fn reply(
+        msg: &Message,
+        source: &SysSource,
+        calls: &Mutex<Vec<Call>>,
+    ) -> Message {
+        let path = msg.path().map(|p| p.to_string()).unwrap_or_default();
+        let member = msg.member().map(|m| m.to_string()).unwrap_or_default();
+        if path != "/org/freedesktop/login1/session/auto"
+            || member != "SetBrightness"
+        {
+            return msg.error(
+                &ErrorName::from("org.freedesktop.DBus.Error.UnknownMethod"),
+                &CString::new("unknown method").unwrap(),
+            );
+        }
+
+        let (subsystem, device, brightness): (String, String, u32) =
+            msg.read3().unwrap();
+        fs::write(
+            source.path(&format!(
+                "/sys/class/{}/{}/brightness",
+                subsystem, device
+            )),
+            brightness.to_string(),
+        )
+        .unwrap();
+        calls.lock().unwrap().push((subsystem, device, brightness));
+        msg.method_return()
+    }
+}
+
+impl Drop for Logind {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        if let Some(handle) = self.handle.take() {
+            handle.join().ok();
+        }
+    }
+}
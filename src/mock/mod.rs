@@ -0,0 +1,16 @@
+//! Mocks of external services used for testing
+
+pub mod audio;
+#[cfg(feature = "dbus")]
+pub mod dbus;
+pub mod http;
+pub mod i3;
+#[cfg(feature = "dbus")]
+pub mod logind;
+#[cfg(feature = "dbus")]
+pub mod mpris;
+pub mod segment;
+pub mod sys;
+#[cfg(feature = "dbus")]
+pub mod tray;
+pub mod wait;
@@ -0,0 +1,208 @@
+//! A stub media player
+
+use crate::mock::dbus::Bus;
+use dbus::{
+    arg::{PropMap, RefArg, Variant},
+    blocking::{
+        stdintf::org_freedesktop_dbus::PropertiesPropertiesChanged, Connection,
+    },
+    channel::{Channel, MatchingReceiver, Sender},
+    message::{MatchRule, SignalArgs},
+    strings::{ErrorName, Path},
+    Message,
+};
+use std::{
+    ffi::CString,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+const PATH: &str = "/org/mpris/MediaPlayer2";
+const PLAYER: &str = "org.mpris.MediaPlayer2.Player";
+const PROPERTIES: &str = "org.freedesktop.DBus.Properties";
+
+/// The state of a stub media player
+#[derive(Clone, Debug, Default)]
+pub struct PlayerState {
+    pub status: String,
+    pub artist: String,
+    pub title: String,
+    pub volume: f64,
+
+    /// The names of all called methods and set properties
+    pub calls: Vec<String>,
+}
+
+/// A media player owning the bus name until dropped
+pub struct Player {
+    state: Arc<Mutex<PlayerState>>,
+    changed: Arc<AtomicBool>,
+    stopped: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Player {
+    /// Start a new player with the provided bus name suffix
+    pub fn start(bus: &Bus, name: &str, state: PlayerState) -> Self {
+        let state = Arc::new(Mutex::new(state));
+        let changed = Arc::new(AtomicBool::new(false));
+        let stopped = Arc::new(AtomicBool::new(false));
+
+        let (tx, rx) = mpsc::channel();
+        let (address, name) = (bus.address().to_owned(), name.to_owned());
+        let (s, c, stop) = (state.clone(), changed.clone(), stopped.clone());
+        let handle = thread::spawn(move || {
+            let mut channel = Channel::open_private(&address).unwrap();
+            channel.register().unwrap();
+            let connection = Connection::from(channel);
+            connection
+                .request_name(
+                    format!("org.mpris.MediaPlayer2.{}", name),
+                    false,
+                    false,
+                    true,
+                )
+                .unwrap();
+
+            let (state, changed) = (s.clone(), c.clone());
+            connection.start_receive(
+                MatchRule::new_method_call(),
+                Box::new(move |msg, conn| {
+                    conn.send(Self::reply(&msg, &state, &changed)).ok();
+                    true
+                }),
+            );
+            tx.send(()).unwrap();
+
+            while !stop.load(Ordering::SeqCst) {
+                if c.swap(false, Ordering::SeqCst) {
+                    let signal = PropertiesPropertiesChanged {
+                        interface_name: PLAYER.into(),
+                        changed_properties: Self::properties(
+                            &s.lock().unwrap(),
+                        ),
+                        invalidated_properties: vec![],
+                    };
+                    connection
+                        .send(signal.to_emit_message(&Path::from(PATH)))
+                        .ok();
+                }
+                connection.process(Duration::from_millis(10)).unwrap();
+            }
+        });
+        rx.recv().unwrap();
+
+        Self {
+            state,
+            changed,
+            stopped,
+            handle: Some(handle),
+        }
+    }
+
+    /// Retrieve the current state
+    pub fn state(&self) -> PlayerState {
+        self.state.lock().unwrap().clone()
+    }
+
+    /// Change the state and notify about the changed properties
+    pub fn change<F>(&self, f: F)
+    where
+        F: FnOnce(&mut PlayerState),
+    {
+        f(&mut self.state.lock().unwrap());
+        self.changed.store(true, Ordering::SeqCst);
+    }
+
+    /// Create the reply to a method call
+    fn reply(
+        msg: &Message,
+        state: &Mutex<PlayerState>,
+        changed: &AtomicBool,
+    ) -> Message {
+        let interface = msg.interface().map(|i| i.to_string());
+        let member = msg.member().map(|m| m.to_string()).unwrap_or_default();
+        let mut state = state.lock().unwrap();
+
+        match (interface.as_deref(), member.as_str()) {
+            (Some(PROPERTIES), "Get") => {
+                let (_, name): (&str, &str) = msg.read2().unwrap();
+                match Self::properties(&state).remove(name) {
+                    Some(value) => msg.method_return().append1(value),
+                    None => Self::error(msg, "unknown property"),
+                }
+            }
+            (Some(PROPERTIES), "GetAll") => {
+                msg.method_return().append1(Self::properties(&state))
+            }
+            (Some(PROPERTIES), "Set") => {
+                let (_, name, volume): (&str, &str, Variant<f64>) =
+                    msg.read3().unwrap();
+                state.volume = volume.0;
+                state.calls.push(name.to_owned());
+                changed.store(true, Ordering::SeqCst);
+                msg.method_return()
+            }
+            (Some(PLAYER), method) => {
+                if method == "PlayPause" {
+                    state.status = match state.status.as_str() {
+                        "Playing" => "Paused",
+                        _ => "Playing",
+                    }
+                    .to_owned();
+                }
+                state.calls.push(method.to_owned());
+                changed.store(true, Ordering::SeqCst);
+                msg.method_return()
+            }
+            _ => Self::error(msg, "unknown method"),
+        }
+    }
+
+    fn error(msg: &Message, text: &str) -> Message {
+        msg.error(
+            &ErrorName::from("org.freedesktop.DBus.Error.UnknownMethod"),
+            &CString::new(text).unwrap(),
+        )
+    }
+
+    /// Retrieve all properties of the player interface
+    fn properties(state: &PlayerState) -> PropMap {
+        let mut metadata = PropMap::new();
+        if !state.artist.is_empty() {
+            metadata.insert(
+                "xesam:artist".into(),
+                Variant(Box::new(vec![state.artist.clone()])),
+            );
+        }
+        metadata.insert(
+            "xesam:title".into(),
+            Variant(Box::new(state.title.clone())),
+        );
+
+        let mut properties = PropMap::new();
+        properties.insert(
+            "PlaybackStatus".into(),
+            Variant(Box::new(state.status.clone())),
+        );
+        properties.insert(
+            "Metadata".into(),
+            Variant(Box::new(metadata) as Box<dyn RefArg>),
+        );
+        properties.insert("Volume".into(), Variant(Box::new(state.volume)));
+        properties
+    }
+}
+
+impl Drop for Player {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        if let Some(handle) = self.handle.take() {
+            handle.join().ok();
+        }
+    }
+}
@@ -0,0 +1,64 @@
+//! A segment counting its clicks
+
+use crate::segment::{Button, Segment, SegmentOutput};
+use failure::Fallible;
+
+/// A segment displaying the number of clicks it received
+pub struct Counter {
+    id: String,
+    clicks: u64,
+    urgent_after: Option<u64>,
+    popup: bool,
+}
+
+impl Counter {
+    /// Create a new counter with the provided identifier
+    pub fn new(id: &str) -> Self {
+        Self {
+            id: id.to_owned(),
+            clicks: 0,
+            urgent_after: None,
+            popup: false,
+        }
+    }
+
+    /// Mark the output as urgent once the amount of clicks is reached
+    pub fn with_urgent_after(mut self, clicks: u64) -> Self {
+        self.urgent_after = Some(clicks);
+        self
+    }
+
+    /// Open a popup showing the clicks after every odd click
+    pub fn with_popup(mut self) -> Self {
+        self.popup = true;
+        self
+    }
+}
+
+impl Segment for Counter {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn name(&self) -> &str {
+        "counter"
+    }
+
+    fn update(&mut self) -> Fallible<SegmentOutput> {
+        Ok(SegmentOutput::new(self.clicks.to_string())
+            .with_urgent(self.urgent_after.iter().any(|u| self.clicks >= *u))
+            .with_value(self.clicks as f64))
+    }
+
+    fn click(&mut self, _: Button) {
+        self.clicks += 1;
+    }
+
+    fn popup(&mut self) -> Option<Vec<SegmentOutput>> {
+        if self.popup && self.clicks % 2 == 1 {
+            Some(vec![SegmentOutput::new(format!("{} clicks", self.clicks))])
+        } else {
+            None
+        }
+    }
+}
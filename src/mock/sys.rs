@@ -0,0 +1,40 @@
+//! A temporary system root for fixture based tests
+
+use crate::source::SysSource;
+use std::{env, fs, path::PathBuf};
+use uuid::Uuid;
+
+/// A temporary root directory, which gets removed if dropped
+pub struct Root {
+    path: PathBuf,
+}
+
+impl Root {
+    /// Create a new empty root directory
+    pub fn new() -> Self {
+        let path = env::temp_dir().join(format!("unibar-{}", Uuid::new_v4()));
+        fs::create_dir_all(&path).expect("create root directory");
+        Self { path }
+    }
+
+    /// Write the content of an absolute system path like `/proc/stat`
+    pub fn write(&self, path: &str, content: &str) -> &Self {
+        let path = self.path.join(path.trim_start_matches('/'));
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).expect("create fixture directory");
+        }
+        fs::write(path, content).expect("write fixture");
+        self
+    }
+
+    /// Retrieve a source for the root
+    pub fn source(&self) -> SysSource {
+        SysSource::new(&self.path)
+    }
+}
+
+impl Drop for Root {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.path).ok();
+    }
+}
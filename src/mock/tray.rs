@@ -0,0 +1,173 @@
+//! A stub StatusNotifierItem
+
+use crate::mock::dbus::Bus;
+use dbus::{
+    arg::Variant,
+    blocking::Connection,
+    channel::{Channel, MatchingReceiver, Sender},
+    message::MatchRule,
+    strings::ErrorName,
+    Message,
+};
+use std::{
+    ffi::CString,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+const PATH: &str = "/StatusNotifierItem";
+const ITEM: &str = "org.kde.StatusNotifierItem";
+const PROPERTIES: &str = "org.freedesktop.DBus.Properties";
+
+/// The state of a stub item
+#[derive(Clone, Debug)]
+pub struct ItemState {
+    pub title: String,
+    pub status: String,
+    pub icon_name: String,
+    pub theme_path: String,
+    pub pixmap: Vec<(i32, i32, Vec<u8>)>,
+
+    /// The names and arguments of all called methods
+    pub calls: Vec<String>,
+}
+
+impl Default for ItemState {
+    fn default() -> Self {
+        Self {
+            title: "item".into(),
+            status: "Active".into(),
+            icon_name: String::new(),
+            theme_path: String::new(),
+            pixmap: vec![],
+            calls: vec![],
+        }
+    }
+}
+
+/// An item owning the bus name until dropped
+pub struct Item {
+    state: Arc<Mutex<ItemState>>,
+    changed: Arc<AtomicBool>,
+    stopped: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Item {
+    /// Start a new item with the provided bus name
+    pub fn start(bus: &Bus, name: &str, state: ItemState) -> Self {
+        let state = Arc::new(Mutex::new(state));
+        let changed = Arc::new(AtomicBool::new(false));
+        let stopped = Arc::new(AtomicBool::new(false));
+
+        let (tx, rx) = mpsc::channel();
+        let (address, name) = (bus.address().to_owned(), name.to_owned());
+        let (s, c, stop) = (state.clone(), changed.clone(), stopped.clone());
+        let handle = thread::spawn(move || {
+            let mut channel = Channel::open_private(&address).unwrap();
+            channel.register().unwrap();
+            let connection = Connection::from(channel);
+            connection.request_name(name, false, false, true).unwrap();
+
+            let state = s.clone();
+            connection.start_receive(
+                MatchRule::new_method_call(),
+                Box::new(move |msg, conn| {
+                    conn.send(Self::reply(&msg, &state)).ok();
+                    true
+                }),
+            );
+            tx.send(()).unwrap();
+
+            while !stop.load(Ordering::SeqCst) {
+                if c.swap(false, Ordering::SeqCst) {
+                    let signal =
+                        Message::new_signal(PATH, ITEM, "NewStatus").unwrap();
+                    connection.send(signal).ok();
+                }
+                connection.process(Duration::from_millis(10)).unwrap();
+            }
+        });
+        rx.recv().unwrap();
+
+        Self {
+            state,
+            changed,
+            stopped,
+            handle: Some(handle),
+        }
+    }
+
+    /// Retrieve the current state
+    pub fn state(&self) -> ItemState {
+        self.state.lock().unwrap().clone()
+    }
+
+    /// Change the state and signal the change
+    pub fn change<F>(&self, f: F)
+    where
+        F: FnOnce(&mut ItemState),
+    {
+        f(&mut self.state.lock().unwrap());
+        self.changed.store(true, Ordering::SeqCst);
+    }
+
+    /// Create the reply to a method call
+    fn reply(msg: &Message, state: &Mutex<ItemState>) -> Message {
+        let interface = msg.interface().map(|i| i.to_string());
+        let member = msg.member().map(|m| m.to_string()).unwrap_or_default();
+        let mut state = state.lock().unwrap();
+
+        match (interface.as_deref(), member.as_str()) {
+            (Some(PROPERTIES), "Get") => {
+                let (_, name): (&str, &str) = msg.read2().unwrap();
+                let reply = msg.method_return();
+                match name {
+                    "Title" => reply.append1(Variant(state.title.clone())),
+                    "Status" => reply.append1(Variant(state.status.clone())),
+                    "IconName" if !state.icon_name.is_empty() => {
+                        reply.append1(Variant(state.icon_name.clone()))
+                    }
+                    "IconThemePath" => {
+                        reply.append1(Variant(state.theme_path.clone()))
+                    }
+                    "IconPixmap" if !state.pixmap.is_empty() => {
+                        reply.append1(Variant(state.pixmap.clone()))
+                    }
+                    _ => Self::error(msg, "unknown property"),
+                }
+            }
+            (Some(ITEM), "Scroll") => {
+                let (delta, _): (i32, &str) = msg.read2().unwrap();
+                state.calls.push(format!("Scroll {}", delta));
+                msg.method_return()
+            }
+            (Some(ITEM), method) => {
+                let (x, y): (i32, i32) = msg.read2().unwrap();
+                state.calls.push(format!("{} {} {}", method, x, y));
+                msg.method_return()
+            }
+            _ => Self::error(msg, "unknown method"),
+        }
+    }
+
+    fn error(msg: &Message, text: &str) -> Message {
+        msg.error(
+            &ErrorName::from("org.freedesktop.DBus.Error.UnknownMethod"),
+            &CString::new(text).unwrap(),
+        )
+    }
+}
+
+impl Drop for Item {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        if let Some(handle) = self.handle.take() {
+            handle.join().ok();
+        }
+    }
+}
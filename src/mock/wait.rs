@@ -0,0 +1,43 @@
+//! Polling of asynchronously changing state with a timeout
+
+use crate::segment::{Segment, SegmentOutput};
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+/// The maximum duration to wait for
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Run the check until it succeeds, which panics if the timeout exceeds
+pub fn wait_until<F>(mut check: F)
+where
+    F: FnMut() -> bool,
+{
+    let timeout = Instant::now() + TIMEOUT;
+    while !check() {
+        if Instant::now() > timeout {
+            panic!("timeout while waiting")
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Update the segment until the output matches, which panics if the
+/// timeout exceeds
+pub fn wait_for<F>(segment: &mut dyn Segment, matches: F) -> SegmentOutput
+where
+    F: Fn(&SegmentOutput) -> bool,
+{
+    let mut output = SegmentOutput::default();
+    wait_until(|| {
+        output = segment.update().unwrap();
+        matches(&output)
+    });
+    output
+}
+
+/// Update the segment until it shows the text
+pub fn wait_for_text(segment: &mut dyn Segment, text: &str) -> SegmentOutput {
+    wait_for(segment, |o| !o.hidden && o.text == text)
+}
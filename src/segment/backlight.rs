@@ -0,0 +1,527 @@
+//! A segment showing the brightness of a backlight device
+
+use crate::{
+    segment::{Button, Segment, SegmentOutput},
+    source::SysSource,
+    stop::Stop,
+    template::Template,
+};
+use failure::{format_err, Fallible};
+use log::{debug, warn};
+use serde_derive::Deserialize;
+use std::{
+    ffi::CString,
+    fs::{self, File},
+    io::prelude::*,
+    os::unix::{
+        ffi::OsStrExt,
+        io::{AsRawFd, FromRawFd},
+    },
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+use uuid::Uuid;
+
+/// The placeholders available within the format
+const PLACEHOLDERS: &[&str] = &["brightness", "device"];
+
+/// The directory containing all backlight devices
+const BACKLIGHT_DIR: &str = "/sys/class/backlight";
+
+/// The lowest brightness reachable by scrolling in percent, which keeps the
+/// display readable
+const MIN_PERCENT: u64 = 1;
+
+/// The configuration of a backlight segment
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct BacklightConfig {
+    /// The name to address the segment with
+    #[serde(default = "BacklightConfig::default_name")]
+    pub name: String,
+
+    /// The displayed text, where the placeholders `brightness` in percent
+    /// and `device` are available
+    #[serde(default = "BacklightConfig::default_format")]
+    pub format: String,
+
+    /// The device below `/sys/class/backlight`, the first one if not set
+    #[serde(default)]
+    pub device: Option<String>,
+
+    /// The brightness change per scroll step in percent
+    #[serde(default = "BacklightConfig::default_step")]
+    pub step: u8,
+
+    /// The D-Bus address of logind, the system bus is used if not set
+    #[serde(default)]
+    pub address: Option<String>,
+
+    /// The minimum width of the segment in pixels
+    #[serde(default = "BacklightConfig::default_width")]
+    pub width: u16,
+}
+
+impl Default for BacklightConfig {
+    fn default() -> Self {
+        Self {
+            name: Self::default_name(),
+            format: Self::default_format(),
+            device: None,
+            step: Self::default_step(),
+            address: None,
+            width: Self::default_width(),
+        }
+    }
+}
+
+impl BacklightConfig {
+    fn default_name() -> String {
+        "backlight".to_owned()
+    }
+
+    fn default_format() -> String {
+        "\u{f185} {brightness}%".to_owned()
+    }
+
+    fn default_step() -> u8 {
+        5
+    }
+
+    fn default_width() -> u16 {
+        70
+    }
+}
+
+/// A segment showing the brightness in percent, which is only read again if
+/// the brightness file got modified
+pub struct Backlight {
+    id: String,
+    config: BacklightConfig,
+    template: Template,
+    source: SysSource,
+    device: String,
+    changes: Mutex<Receiver<()>>,
+    requests: Mutex<Sender<u64>>,
+    stop: Arc<Stop>,
+    watcher: Option<JoinHandle<()>>,
+    brightness: Option<(u64, u64)>,
+}
+
+impl Backlight {
+    /// Create a new segment for the configured or the first available device
+    pub fn new(config: BacklightConfig, source: SysSource) -> Fallible<Self> {
+        let template: Template = config.format.parse()?;
+        template.validate(PLACEHOLDERS)?;
+        let device = match &config.device {
+            Some(device) => device.clone(),
+            None => {
+                let mut devices: Vec<String> =
+                    fs::read_dir(source.path(BACKLIGHT_DIR))?
+                        .filter_map(|e| e.ok())
+                        .map(|e| e.file_name().to_string_lossy().into_owned())
+                        .collect();
+                devices.sort();
+                devices
+                    .into_iter()
+                    .next()
+                    .ok_or_else(|| format_err!("no backlight device found"))?
+            }
+        };
+
+        let (tx, rx) = mpsc::channel();
+        let stop = Arc::new(Stop::new()?);
+        let watcher = Self::watch(&source, &device, tx, stop.clone())?;
+        let requests = Self::work(config.address.clone(), device.clone());
+        Ok(Self {
+            id: Uuid::new_v4().to_string(),
+            config,
+            template,
+            source,
+            device,
+            changes: Mutex::new(rx),
+            requests: Mutex::new(requests),
+            stop,
+            watcher: Some(watcher),
+            brightness: None,
+        })
+    }
+
+    fn path(device: &str, file: &str) -> String {
+        format!("{}/{}/{}", BACKLIGHT_DIR, device, file)
+    }
+
+    /// Send an event on every modification of the brightness via inotify
+    /// until stopped
+    fn watch(
+        source: &SysSource,
+        device: &str,
+        changes: Sender<()>,
+        stop: Arc<Stop>,
+    ) -> Fallible<JoinHandle<()>> {
+        let path = source.path(&Self::path(device, "brightness"));
+        let c_path = CString::new(path.as_os_str().as_bytes())?;
+
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+        if fd < 0
+            || unsafe {
+                libc::inotify_add_watch(
+                    fd,
+                    c_path.as_ptr(),
+                    libc::IN_MODIFY | libc::IN_CLOSE_WRITE,
+                )
+            } < 0
+        {
+            let error = std::io::Error::last_os_error();
+            if fd >= 0 {
+                unsafe { libc::close(fd) };
+            }
+            return Err(format_err!(
+                "unable to watch {}: {}",
+                path.display(),
+                error
+            ));
+        }
+
+        // The file closes the descriptor once the thread ends
+        let mut inotify = unsafe { File::from_raw_fd(fd) };
+        Ok(thread::spawn(move || {
+            let mut buffer = [0u8; 4096];
+            while stop.wait(inotify.as_raw_fd()) {
+                match inotify.read(&mut buffer) {
+                    Ok(read) if read > 0 && changes.send(()).is_ok() => {}
+                    _ => break,
+                }
+            }
+            debug!("Stopped watching the backlight");
+        }))
+    }
+
+    /// Set the requested brightnesses on a worker thread, which keeps slow
+    /// D-Bus calls off the bar and ends with the segment
+    fn work(address: Option<String>, device: String) -> Sender<u64> {
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for brightness in rx {
+                if let Err(e) =
+                    Self::set(address.as_deref(), &device, brightness)
+                {
+                    warn!("Unable to change the brightness: {}", e);
+                }
+            }
+        });
+        tx
+    }
+
+    /// Read the current and maximum brightness
+    fn read(&self) -> Fallible<(u64, u64)> {
+        let read = |file| -> Fallible<u64> {
+            let path = Self::path(&self.device, file);
+            let content = self.source.read_to_string(&path)?;
+            content
+                .trim()
+                .parse()
+                .map_err(|e| format_err!("invalid content of {}: {}", path, e))
+        };
+        Ok((read("brightness")?, read("max_brightness")?))
+    }
+
+    /// Convert a raw brightness into percent
+    fn percent(brightness: u64, max: u64) -> u64 {
+        if max == 0 {
+            return 0;
+        }
+        (brightness.min(max) * 100 + max / 2) / max
+    }
+
+    /// Convert a percentage into a raw brightness
+    fn raw(percent: u64, max: u64) -> u64 {
+        (percent.min(100) * max + 50) / 100
+    }
+
+    /// Retrieve the raw brightness after scrolling by the provided steps
+    fn scrolled(&self, steps: i64) -> Fallible<u64> {
+        let (brightness, max) = match self.brightness {
+            Some(brightness) => brightness,
+            None => self.read()?,
+        };
+        let percent = Self::percent(brightness, max) as i64
+            + steps * i64::from(self.config.step);
+        let percent = percent.clamp(MIN_PERCENT as i64, 100) as u64;
+        Ok(Self::raw(percent, max).max(1.min(max)))
+    }
+
+    /// Set the raw brightness via logind, which needs no root permissions
+    #[cfg(feature = "dbus")]
+    fn set(
+        address: Option<&str>,
+        device: &str,
+        brightness: u64,
+    ) -> Fallible<()> {
+        crate::bus::connect_system(address)?
+            .with_proxy(
+                "org.freedesktop.login1",
+                "/org/freedesktop/login1/session/auto",
+                std::time::Duration::from_secs(1),
+            )
+            .method_call::<(), _, _, _>(
+                "org.freedesktop.login1.Session",
+                "SetBrightness",
+                ("backlight", device, brightness as u32),
+            )?;
+        Ok(())
+    }
+
+    #[cfg(not(feature = "dbus"))]
+    fn set(_: Option<&str>, _: &str, _: u64) -> Fallible<()> {
+        Err(format_err!("setting the brightness requires D-Bus support"))
+    }
+}
+
+impl Segment for Backlight {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn update(&mut self) -> Fallible<SegmentOutput> {
+        let changed = self.changes.lock().unwrap().try_iter().count() > 0;
+        let (brightness, max) = match self.brightness {
+            Some(brightness) if !changed => brightness,
+            _ => {
+                let brightness = self.read()?;
+                self.brightness = Some(brightness);
+                brightness
+            }
+        };
+
+        let percent = Self::percent(brightness, max);
+        Ok(self
+            .template
+            .output(&[
+                ("brightness", percent.into()),
+                ("device", self.device.as_str().into()),
+            ])
+            .with_value(percent as f64))
+    }
+
+    fn click(&mut self, button: Button) {
+        let steps = match button {
+            Button::ScrollUp => 1,
+            Button::ScrollDown => -1,
+            _ => return,
+        };
+        let brightness = match self.scrolled(steps) {
+            Ok(brightness) => brightness,
+            Err(e) => {
+                warn!("Unable to change the brightness: {}", e);
+                return;
+            }
+        };
+
+        // Further steps continue from the requested brightness
+        if let Some((_, max)) = self.brightness {
+            self.brightness = Some((brightness, max));
+        }
+        self.requests.lock().unwrap().send(brightness).ok();
+    }
+
+    fn width(&self) -> u16 {
+        self.config.width
+    }
+}
+
+impl Drop for Backlight {
+    fn drop(&mut self) {
+        self.stop.stop();
+        if let Some(watcher) = self.watcher.take() {
+            watcher.join().ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{sys::Root, wait::wait_for_text};
+
+    fn fixture(brightness: u64, max: u64) -> Root {
+        let root = Root::new();
+        root.write(
+            "/sys/class/backlight/intel_backlight/brightness",
+            &format!("{}\n", brightness),
+        )
+        .write(
+            "/sys/class/backlight/intel_backlight/max_brightness",
+            &format!("{}\n", max),
+        );
+        root
+    }
+
+    #[test]
+    fn percentage() {
+        assert_eq!(Backlight::percent(0, 937), 0);
+        assert_eq!(Backlight::percent(468, 937), 50);
+        assert_eq!(Backlight::percent(937, 937), 100);
+        assert_eq!(Backlight::percent(1000, 937), 100);
+        assert_eq!(Backlight::percent(3, 0), 0);
+        assert_eq!(Backlight::percent(1, 7), 14);
+
+        assert_eq!(Backlight::raw(50, 937), 469);
+        assert_eq!(Backlight::raw(100, 937), 937);
+        assert_eq!(Backlight::raw(150, 7), 7);
+        assert_eq!(Backlight::raw(1, 7), 0);
+    }
+
+    #[test]
+    fn watch_changes() {
+        let root = fixture(468, 937);
+        let mut segment =
+            Backlight::new(BacklightConfig::default(), root.source()).unwrap();
+        let output = segment.update().unwrap();
+        assert_eq!(output.text, "\u{f185} 50%");
+        assert_eq!(output.value, Some(50.));
+
+        root.write("/sys/class/backlight/intel_backlight/brightness", "937");
+        wait_for_text(&mut segment, "\u{f185} 100%");
+    }
+
+    #[test]
+    fn stop_watching() {
+        let root = fixture(468, 937);
+        let (tx, rx) = mpsc::channel();
+        let stop = Arc::new(Stop::new().unwrap());
+        let watcher = Backlight::watch(
+            &root.source(),
+            "intel_backlight",
+            tx,
+            stop.clone(),
+        )
+        .unwrap();
+        root.write("/sys/class/backlight/intel_backlight/brightness", "1");
+        rx.recv().unwrap();
+
+        // Stopping ends the thread, which would block in a read otherwise
+        stop.stop();
+        watcher.join().unwrap();
+    }
+
+    #[test]
+    fn scroll_steps() {
+        let root = fixture(4800, 48000);
+        let mut segment =
+            Backlight::new(BacklightConfig::default(), root.source()).unwrap();
+        segment.update().unwrap();
+        assert_eq!(segment.scrolled(1).unwrap(), 7200);
+        assert_eq!(segment.scrolled(-1).unwrap(), 2400);
+        assert_eq!(segment.scrolled(-3).unwrap(), 480);
+        assert_eq!(segment.scrolled(30).unwrap(), 48000);
+
+        // Devices with few levels never get turned off
+        let root = fixture(1, 7);
+        let segment = Backlight::new(
+            BacklightConfig {
+                step: 10,
+                ..BacklightConfig::default()
+            },
+            root.source(),
+        )
+        .unwrap();
+        assert_eq!(segment.scrolled(-1).unwrap(), 1);
+    }
+
+    #[test]
+    fn devices() {
+        let root = fixture(1, 2);
+        root.write("/sys/class/backlight/acpi_video0/brightness", "3")
+            .write("/sys/class/backlight/acpi_video0/max_brightness", "4");
+        let mut segment =
+            Backlight::new(BacklightConfig::default(), root.source()).unwrap();
+        assert_eq!(segment.update().unwrap().text, "\u{f185} 75%");
+
+        let mut segment = Backlight::new(
+            BacklightConfig {
+                device: Some("intel_backlight".into()),
+                width: 120,
+                ..BacklightConfig::default()
+            },
+            root.source(),
+        )
+        .unwrap();
+        assert_eq!(segment.update().unwrap().text, "\u{f185} 50%");
+        assert_eq!(segment.width(), 120);
+
+        let mut segment = Backlight::new(
+            BacklightConfig {
+                format: "{device}: {brightness:>3}%".into(),
+                ..BacklightConfig::default()
+            },
+            root.source(),
+        )
+        .unwrap();
+        assert_eq!(segment.update().unwrap().text, "acpi_video0:  75%");
+
+        assert!(Backlight::new(
+            BacklightConfig {
+                format: "{level}".into(),
+                ..BacklightConfig::default()
+            },
+            root.source(),
+        )
+        .is_err());
+        assert!(Backlight::new(
+            BacklightConfig {
+                device: Some("missing".into()),
+                ..BacklightConfig::default()
+            },
+            root.source(),
+        )
+        .is_err());
+        assert!(Backlight::new(
+            BacklightConfig::default(),
+            Root::new().source()
+        )
+        .is_err());
+    }
+
+    #[cfg(feature = "dbus")]
+    #[test]
+    fn logind() {
+        use crate::mock::{dbus::Bus, logind::Logind, wait::wait_until};
+
+        let root = fixture(468, 937);
+        let bus = Bus::start();
+        let logind = Logind::start(&bus, root.source());
+        let mut segment = Backlight::new(
+            BacklightConfig {
+                address: Some(bus.address().to_owned()),
+                ..BacklightConfig::default()
+            },
+            root.source(),
+        )
+        .unwrap();
+        segment.update().unwrap();
+
+        // The requested brightness is shown until logind changed it
+        segment.click(Button::ScrollUp);
+        assert_eq!(segment.update().unwrap().text, "\u{f185} 55%");
+        wait_until(|| logind.calls().len() == 1);
+        wait_for_text(&mut segment, "\u{f185} 55%");
+        segment.click(Button::Left);
+        segment.click(Button::ScrollDown);
+        wait_until(|| logind.calls().len() == 2);
+        wait_for_text(&mut segment, "\u{f185} 50%");
+        assert_eq!(
+            logind.calls(),
+            vec![
+                ("backlight".to_owned(), "intel_backlight".to_owned(), 515),
+                ("backlight".to_owned(), "intel_backlight".to_owned(), 469),
+            ]
+        );
+    }
+}
@@ -0,0 +1,308 @@
+//! A segment showing the output of an external command
+
+use crate::segment::{Button, Segment, SegmentOutput};
+use failure::{format_err, Fallible};
+use log::{debug, warn};
+use serde_derive::Deserialize;
+use std::{
+    io::{prelude::*, BufReader},
+    process::{self, Child, ChildStdin, Stdio},
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+use uuid::Uuid;
+
+/// The exit code of a command indicating that the output is urgent
+const URGENT_EXIT_CODE: i32 = 33;
+
+/// The configuration of a command segment
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct CommandConfig {
+    /// The shell command to be executed
+    pub command: String,
+
+    /// The update interval in seconds, the command runs persistently and
+    /// every line of its output is an update if not set
+    #[serde(default)]
+    pub interval: Option<u64>,
+
+    /// The name passed to the command via `BLOCK_NAME`
+    #[serde(default = "CommandConfig::default_name")]
+    pub name: String,
+
+    /// The minimum width of the segment in pixels
+    #[serde(default = "CommandConfig::default_width")]
+    pub width: u16,
+}
+
+impl CommandConfig {
+    fn default_name() -> String {
+        "command".to_owned()
+    }
+
+    fn default_width() -> u16 {
+        100
+    }
+}
+
+/// A segment running an i3blocks compatible command
+pub struct Command {
+    id: String,
+    config: CommandConfig,
+    output: Arc<Mutex<SegmentOutput>>,
+    clicks: Sender<Button>,
+    child: Option<Mutex<Child>>,
+}
+
+impl Command {
+    /// Create a new command segment and start executing the command
+    pub fn new(config: CommandConfig) -> Fallible<Self> {
+        let id = Uuid::new_v4().to_string();
+        let output = Arc::new(Mutex::new(SegmentOutput::new("")));
+        let (tx, rx) = mpsc::channel();
+
+        let child = match config.interval {
+            Some(interval) => {
+                let (id, config, output) =
+                    (id.clone(), config.clone(), output.clone());
+                thread::spawn(move || {
+                    Self::run_interval(&id, &config, interval, &output, &rx)
+                });
+                None
+            }
+            None => Some(Mutex::new(Self::run_persistent(
+                &id, &config, &output, rx,
+            )?)),
+        };
+
+        Ok(Self {
+            id,
+            config,
+            output,
+            clicks: tx,
+            child,
+        })
+    }
+
+    /// Run the command once per interval or directly after a click
+    fn run_interval(
+        id: &str,
+        config: &CommandConfig,
+        interval: u64,
+        output: &Mutex<SegmentOutput>,
+        clicks: &Receiver<Button>,
+    ) {
+        let mut button = None;
+        loop {
+            match Self::execute(id, config, button) {
+                Ok(o) => *output.lock().unwrap() = o,
+                Err(e) => warn!("Unable to run '{}': {}", config.command, e),
+            }
+
+            button = match clicks.recv_timeout(Duration::from_secs(interval)) {
+                Ok(b) => Some(b),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+    }
+
+    /// Execute the command and parse its output
+    fn execute(
+        id: &str,
+        config: &CommandConfig,
+        button: Option<Button>,
+    ) -> Fallible<SegmentOutput> {
+        let mut command = Self::command(id, config);
+        if let Some(button) = button {
+            command.env("BLOCK_BUTTON", button.number().to_string());
+        }
+        let result = command.stdin(Stdio::null()).output()?;
+
+        let urgent = match result.status.code() {
+            Some(0) => false,
+            Some(URGENT_EXIT_CODE) => true,
+            _ => {
+                return Err(format_err!(
+                    "{}: {}",
+                    result.status,
+                    String::from_utf8_lossy(&result.stderr).trim()
+                ))
+            }
+        };
+        let output =
+            SegmentOutput::parse(&String::from_utf8_lossy(&result.stdout))?;
+        Ok(if urgent {
+            output.with_urgent(true)
+        } else {
+            output
+        })
+    }
+
+    /// Spawn the command and update the output on every printed line
+    fn run_persistent(
+        id: &str,
+        config: &CommandConfig,
+        output: &Arc<Mutex<SegmentOutput>>,
+        clicks: Receiver<Button>,
+    ) -> Fallible<Child> {
+        let mut child = Self::command(id, config)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| format_err!("no stdout available"))?;
+        let (command, output) = (config.command.clone(), output.clone());
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().scan((), |_, x| x.ok()) {
+                match SegmentOutput::parse(&line) {
+                    Ok(o) => *output.lock().unwrap() = o,
+                    Err(e) => warn!("Unable to parse '{}': {}", line, e),
+                }
+            }
+            debug!("Command '{}' closed its output", command);
+        });
+
+        let stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| format_err!("no stdin available"))?;
+        let (id, name) = (id.to_owned(), config.name.clone());
+        thread::spawn(move || Self::forward_clicks(&id, &name, stdin, &clicks));
+
+        Ok(child)
+    }
+
+    /// Write clicks as i3bar click events to the stdin of the command
+    fn forward_clicks(
+        id: &str,
+        name: &str,
+        mut stdin: ChildStdin,
+        clicks: &Receiver<Button>,
+    ) {
+        for button in clicks.iter() {
+            let event = serde_json::json!({
+                "name": name,
+                "instance": id,
+                "button": button.number(),
+            });
+            if let Err(e) = writeln!(stdin, "{}", event) {
+                debug!("Unable to forward click: {}", e);
+                return;
+            }
+        }
+    }
+
+    /// Create a shell command which runs with the i3blocks environment
+    fn command(id: &str, config: &CommandConfig) -> process::Command {
+        let mut command = process::Command::new("sh");
+        command
+            .arg("-c")
+            .arg(&config.command)
+            .env("BLOCK_NAME", &config.name)
+            .env("BLOCK_INSTANCE", id)
+            .env_remove("BLOCK_BUTTON");
+        command
+    }
+}
+
+impl Segment for Command {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn update(&mut self) -> Fallible<SegmentOutput> {
+        Ok(self.output.lock().unwrap().clone())
+    }
+
+    fn click(&mut self, button: Button) {
+        if self.clicks.send(button).is_err() {
+            warn!("Command '{}' is not running", self.config.command);
+        }
+    }
+
+    fn width(&self) -> u16 {
+        self.config.width
+    }
+}
+
+impl Drop for Command {
+    fn drop(&mut self) {
+        if let Some(child) = &self.child {
+            let mut child = child.lock().unwrap();
+            child.kill().ok();
+            child.wait().ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::wait::wait_for_text;
+
+    fn config(command: &str, interval: Option<u64>) -> CommandConfig {
+        CommandConfig {
+            command: command.to_owned(),
+            interval,
+            name: CommandConfig::default_name(),
+            width: CommandConfig::default_width(),
+        }
+    }
+
+    #[test]
+    fn interval() {
+        let mut command =
+            Command::new(config("echo hello; echo; echo '#0000ff'", Some(60)))
+                .unwrap();
+        let output = wait_for_text(&mut command, "hello");
+        assert_eq!(output.color, Some([0., 0., 1., 1.]));
+        assert_eq!(command.name(), "command");
+        assert_eq!(command.width(), 100);
+    }
+
+    #[test]
+    fn interval_click() {
+        let mut command = Command::new(config(
+            "echo \"$BLOCK_NAME:${BLOCK_BUTTON:-none}\"",
+            Some(60),
+        ))
+        .unwrap();
+        wait_for_text(&mut command, "command:none");
+
+        command.click(Button::Right);
+        wait_for_text(&mut command, "command:3");
+    }
+
+    #[test]
+    fn interval_urgent() {
+        let mut command =
+            Command::new(config("echo alarm; exit 33", Some(60))).unwrap();
+        assert!(wait_for_text(&mut command, "alarm").urgent);
+    }
+
+    #[test]
+    fn persistent() {
+        let mut command = Command::new(config(
+            "echo first; while read click; do \
+             echo \"$click\" | grep -o '\"button\":[0-9]'; done",
+            None,
+        ))
+        .unwrap();
+        wait_for_text(&mut command, "first");
+
+        command.click(Button::ScrollUp);
+        wait_for_text(&mut command, "\"button\":4");
+    }
+}
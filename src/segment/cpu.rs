@@ -1,72 +1,163 @@
-use crate::segment::Segment;
-use amethyst::ui::UiText;
-use failure::{format_err, Fallible};
-use std::{
-    fs::File,
-    io::{prelude::*, BufReader},
+use crate::{
+    segment::{Button, Segment, SegmentOutput},
+    source::SysSource,
+    template::Template,
 };
+use failure::{format_err, Fallible};
+use serde_derive::Deserialize;
+use std::{collections::HashMap, fs};
 use uuid::Uuid;
 
-#[derive(Default)]
+/// The placeholders available within the format
+const PLACEHOLDERS: &[&str] = &["usage", "frequency"];
+
+/// The amount of processes listed in the popup
+const TOP_PROCESSES: usize = 5;
+
+/// The configuration of a cpu segment
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct CpuConfig {
+    /// The name to address the segment with
+    #[serde(default = "CpuConfig::default_name")]
+    pub name: String,
+
+    /// The displayed text, where the placeholders `usage` in percent and
+    /// `frequency` in GHz are available
+    #[serde(default = "CpuConfig::default_format")]
+    pub format: String,
+
+    /// The minimum width of the segment in pixels
+    #[serde(default = "CpuConfig::default_width")]
+    pub width: u16,
+}
+
+impl Default for CpuConfig {
+    fn default() -> Self {
+        Self {
+            name: Self::default_name(),
+            format: Self::default_format(),
+            width: Self::default_width(),
+        }
+    }
+}
+
+impl CpuConfig {
+    fn default_name() -> String {
+        "cpu".to_owned()
+    }
+
+    fn default_format() -> String {
+        "\u{f21e} {usage:>3}% {frequency:.1}GHz".to_owned()
+    }
+
+    fn default_width() -> u16 {
+        100
+    }
+}
+
+/// The cpu utilization and frequency
 pub struct Cpu {
     id: String,
+    config: CpuConfig,
+    template: Template,
+    source: SysSource,
     idle: u64,
     non_idle: u64,
+    popup: Option<Samples>,
+}
+
+/// The previous CPU times of all processes, used to calculate their deltas
+#[derive(Default)]
+struct Samples {
+    total: u64,
+    processes: HashMap<u32, u64>,
+}
+
+/// The resource usage of a single process
+#[derive(Debug, PartialEq)]
+struct Process {
+    pid: u32,
+    name: String,
+    cpu: f64,
+    memory: f64,
 }
 
 impl Segment for Cpu {
-    type Item = UiText;
+    fn id(&self) -> &str {
+        &self.id
+    }
 
-    fn new() -> Self {
-        Cpu {
-            id: Uuid::new_v4().to_string(),
-            idle: 0,
-            non_idle: 0,
-        }
+    fn name(&self) -> &str {
+        &self.config.name
     }
 
-    fn update(&mut self, t: &mut UiText) {
-        if let Ok(text) = self.load() {
-            t.text = text;
+    fn update(&mut self) -> Fallible<SegmentOutput> {
+        let (utilization, frequency) = self.load()?;
+        Ok(self
+            .template
+            .output(&[
+                ("usage", utilization.into()),
+                ("frequency", f64::from(frequency).into()),
+            ])
+            .with_value(utilization as f64))
+    }
+
+    fn width(&self) -> u16 {
+        self.config.width
+    }
+
+    fn click(&mut self, button: Button) {
+        if let Button::Left = button {
+            self.popup = match self.popup {
+                Some(_) => None,
+                None => Some(Samples::default()),
+            };
         }
     }
 
-    fn id(&self) -> &str {
-        &self.id
+    fn popup(&mut self) -> Option<Vec<SegmentOutput>> {
+        self.popup.as_ref()?;
+        Some(match self.processes() {
+            Ok(processes) => processes
+                .iter()
+                .map(|p| {
+                    SegmentOutput::new(format!(
+                        "{:>6} {:<15} {:>5.1}% {:>5.1}%",
+                        p.pid, p.name, p.cpu, p.memory
+                    ))
+                    .with_value(p.cpu)
+                })
+                .collect(),
+            Err(e) => vec![SegmentOutput::new(format!(
+                "Unable to list the processes: {}",
+                e
+            ))],
+        })
     }
 }
 
 impl Cpu {
-    fn load(&mut self) -> Fallible<String> {
-        // Calculate the frequency
-        let mut frequency = 0.;
-        let mut cores = 0;
-        for line in BufReader::new(File::open("/proc/cpuinfo")?)
-            .lines()
-            .scan((), |_, x| x.ok())
-        {
-            if line.starts_with("cpu MHz") {
-                cores += 1;
-                let words = line.split(' ');
-                let last = words
-                    .last()
-                    .ok_or_else(|| format_err!("Unable to parse last word"))?;
-                frequency += last.parse::<f32>()?;
-            }
-        }
-        frequency = frequency / (cores as f32) / 1000.;
+    /// Create a new instance which reads from the provided source
+    pub fn new(config: CpuConfig, source: SysSource) -> Fallible<Self> {
+        let template: Template = config.format.parse()?;
+        template.validate(PLACEHOLDERS)?;
+        Ok(Cpu {
+            id: Uuid::new_v4().to_string(),
+            config,
+            template,
+            source,
+            idle: 0,
+            non_idle: 0,
+            popup: None,
+        })
+    }
 
-        // Calculate the utilization
-        let mut utilization = 0;
-        for line in BufReader::new(File::open("/proc/stat")?)
-            .lines()
-            .scan((), |_, x| x.ok())
-        {
+    /// Retrieve the idle and non idle time of all cores from `/proc/stat`
+    fn times(&self) -> Fallible<Option<(u64, u64)>> {
+        for line in self.source.lines("/proc/stat")? {
             if line.starts_with("cpu ") {
-                let data: Vec<u64> = (&line)
+                let data: Vec<u64> = line
                     .split(' ')
-                    .collect::<Vec<_>>()
-                    .iter()
                     .skip(2)
                     .filter_map(|x| x.parse().ok())
                     .collect();
@@ -79,27 +170,356 @@ impl Cpu {
                         + data[5]
                         + data[6]
                         + data[7];
+                    return Ok(Some((idle, non_idle)));
+                }
+            }
+        }
+        Ok(None)
+    }
 
-                    let prev_total = self.idle + self.non_idle;
-                    let total = idle + non_idle;
+    /// Retrieve the frequency of every core in MHz
+    fn frequencies(source: &SysSource) -> Fallible<Vec<f32>> {
+        let mut frequencies = vec![];
+        for line in source.lines("/proc/cpuinfo")? {
+            if line.starts_with("cpu MHz") {
+                let words = line.split(' ');
+                let last = words
+                    .last()
+                    .ok_or_else(|| format_err!("Unable to parse last word"))?;
+                frequencies.push(last.parse::<f32>()?);
+            }
+        }
+        Ok(frequencies)
+    }
 
-                    let (total_delta, idle_delta) =
-                        if prev_total < total && self.idle <= idle {
-                            (total - prev_total, idle - self.idle)
-                        } else {
-                            (1, 1)
-                        };
+    /// Retrieve the current utilization in percent and frequency in GHz
+    fn load(&mut self) -> Fallible<(u64, f32)> {
+        // Calculate the frequency
+        let frequencies = Self::frequencies(&self.source)?;
+        let frequency = frequencies.iter().sum::<f32>()
+            / (frequencies.len() as f32)
+            / 1000.;
 
-                    utilization = (((total_delta - idle_delta) as f64
-                        / total_delta as f64)
-                        * 100.) as u64;
+        // Calculate the utilization
+        let mut utilization = 0;
+        if let Some((idle, non_idle)) = self.times()? {
+            let prev_total = self.idle + self.non_idle;
+            let total = idle + non_idle;
 
-                    self.idle = idle;
-                    self.non_idle = non_idle;
-                }
+            let (total_delta, idle_delta) =
+                if prev_total < total && self.idle <= idle {
+                    (total - prev_total, idle - self.idle)
+                } else {
+                    (1, 1)
+                };
+
+            utilization = (((total_delta - idle_delta) as f64
+                / total_delta as f64)
+                * 100.) as u64;
+
+            self.idle = idle;
+            self.non_idle = non_idle;
+        }
+
+        Ok((utilization, frequency))
+    }
+
+    /// Retrieve the processes with the highest CPU and memory usage, where
+    /// the CPU usage is the share of the time passed since the last sample
+    fn processes(&mut self) -> Fallible<Vec<Process>> {
+        let total = self
+            .times()?
+            .map(|(idle, non_idle)| idle + non_idle)
+            .ok_or_else(|| format_err!("no cpu times in /proc/stat"))?;
+        let memory = self.memory()?;
+
+        let mut times = HashMap::new();
+        let mut processes = vec![];
+        for entry in fs::read_dir(self.source.path("/proc"))? {
+            let pid = match entry?.file_name().to_string_lossy().parse() {
+                Ok(pid) => pid,
+                Err(_) => continue,
+            };
+            // Processes may exit at any time, which makes them vanish
+            if let Ok((name, time, rss)) = self.process(pid) {
+                times.insert(pid, time);
+                processes.push((pid, name, time, rss));
             }
         }
 
-        Ok(format!(" {:>3}% {:.*}GHz", utilization, 1, frequency))
+        let samples = self.popup.get_or_insert_with(Samples::default);
+        let total_delta = total.saturating_sub(samples.total).max(1);
+        let mut processes: Vec<Process> = processes
+            .into_iter()
+            .map(|(pid, name, time, rss)| {
+                let previous = samples.processes.get(&pid).cloned();
+                let delta = time.saturating_sub(previous.unwrap_or(0));
+                Process {
+                    pid,
+                    name,
+                    cpu: (delta as f64 / total_delta as f64 * 100.).min(100.),
+                    memory: rss as f64 / memory.max(1) as f64 * 100.,
+                }
+            })
+            .collect();
+        samples.total = total;
+        samples.processes = times;
+
+        processes.sort_by(|a, b| {
+            b.cpu
+                .partial_cmp(&a.cpu)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then(
+                    b.memory
+                        .partial_cmp(&a.memory)
+                        .unwrap_or(std::cmp::Ordering::Equal),
+                )
+                .then(a.pid.cmp(&b.pid))
+        });
+        processes.truncate(TOP_PROCESSES);
+        Ok(processes)
+    }
+
+    /// Retrieve the name, the spent user and system time and the resident
+    /// memory in kB of a process
+    fn process(&self, pid: u32) -> Fallible<(String, u64, u64)> {
+        let path = format!("/proc/{}/stat", pid);
+        let stat = self.source.read_to_string(&path)?;
+        let invalid = || format_err!("invalid content of {}", path);
+
+        // The name may contain spaces and parentheses itself
+        let start = stat.find('(').ok_or_else(invalid)?;
+        let end = stat.rfind(')').ok_or_else(invalid)?;
+        let name = stat.get(start + 1..end).ok_or_else(invalid)?.to_owned();
+        let fields: Vec<&str> = stat[end + 1..].split_whitespace().collect();
+        let field = |i: usize| -> Fallible<u64> {
+            fields
+                .get(i)
+                .and_then(|f| f.parse().ok())
+                .ok_or_else(invalid)
+        };
+        // The fields `utime` and `stime` are the 14th and 15th of the line
+        let time = field(11)? + field(12)?;
+
+        // Kernel threads have no resident memory
+        let rss = self
+            .source
+            .lines(&format!("/proc/{}/status", pid))?
+            .find(|l| l.starts_with("VmRSS:"))
+            .and_then(|l| {
+                l.split_whitespace().nth(1).and_then(|r| r.parse().ok())
+            })
+            .unwrap_or(0);
+        Ok((name, time, rss))
+    }
+
+    /// Retrieve the total memory in kB
+    fn memory(&self) -> Fallible<u64> {
+        self.source
+            .lines("/proc/meminfo")?
+            .find(|l| l.starts_with("MemTotal:"))
+            .and_then(|l| {
+                l.split_whitespace().nth(1).and_then(|t| t.parse().ok())
+            })
+            .ok_or_else(|| format_err!("no total memory in /proc/meminfo"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::sys::Root;
+
+    const CPUINFO: &str = "processor\t: 0\n\
+                           cpu MHz\t\t: 2000.000\n\
+                           \n\
+                           processor\t: 1\n\
+                           cpu MHz\t\t: 3000.000\n";
+
+    fn stat(user: u64, system: u64, idle: u64, iowait: u64) -> String {
+        format!(
+            "cpu  {} 0 {} {} {} 0 0 0 0 0\n\
+             cpu0 0 0 0 0 0 0 0 0 0 0\n\
+             intr 0\n",
+            user, system, idle, iowait
+        )
+    }
+
+    #[test]
+    fn load_initial() {
+        let root = Root::new();
+        root.write("/proc/cpuinfo", CPUINFO)
+            .write("/proc/stat", &stat(100, 100, 700, 100));
+
+        let mut cpu = Cpu::new(CpuConfig::default(), root.source()).unwrap();
+        let output = cpu.update().unwrap();
+        assert_eq!(output.text, "\u{f21e}  20% 2.5GHz");
+        assert_eq!(output.value, Some(20.));
+    }
+
+    #[test]
+    fn load_delta() {
+        let root = Root::new();
+        root.write("/proc/cpuinfo", CPUINFO)
+            .write("/proc/stat", &stat(100, 100, 700, 100));
+
+        let mut cpu = Cpu::new(CpuConfig::default(), root.source()).unwrap();
+        cpu.update().unwrap();
+
+        root.write("/proc/stat", &stat(400, 200, 1000, 200));
+        assert_eq!(cpu.update().unwrap().text, "\u{f21e}  50% 2.5GHz");
+
+        root.write("/proc/stat", &stat(400, 200, 1400, 200));
+        assert_eq!(cpu.update().unwrap().text, "\u{f21e}   0% 2.5GHz");
+
+        root.write("/proc/stat", &stat(500, 300, 1400, 200));
+        assert_eq!(cpu.update().unwrap().text, "\u{f21e} 100% 2.5GHz");
+    }
+
+    #[test]
+    fn load_wraparound() {
+        let root = Root::new();
+        root.write("/proc/cpuinfo", CPUINFO)
+            .write("/proc/stat", &stat(400, 200, 1000, 200));
+
+        let mut cpu = Cpu::new(CpuConfig::default(), root.source()).unwrap();
+        cpu.update().unwrap();
+
+        // Counters went backwards, which falls back to no utilization
+        root.write("/proc/stat", &stat(10, 10, 70, 10));
+        assert_eq!(cpu.update().unwrap().text, "\u{f21e}   0% 2.5GHz");
+
+        // The new counters are the base for the next delta
+        root.write("/proc/stat", &stat(40, 40, 100, 20));
+        assert_eq!(cpu.update().unwrap().text, "\u{f21e}  60% 2.5GHz");
+    }
+
+    #[test]
+    fn load_idle_wraparound() {
+        let root = Root::new();
+        root.write("/proc/cpuinfo", CPUINFO)
+            .write("/proc/stat", &stat(100, 100, 700, 100));
+
+        let mut cpu = Cpu::new(CpuConfig::default(), root.source()).unwrap();
+        cpu.update().unwrap();
+
+        // The total increases but the idle time went backwards
+        root.write("/proc/stat", &stat(1000, 100, 10, 10));
+        assert_eq!(cpu.update().unwrap().text, "\u{f21e}   0% 2.5GHz");
+    }
+
+    fn process(root: &Root, pid: u32, name: &str, time: u64, rss: Option<u64>) {
+        root.write(
+            &format!("/proc/{}/stat", pid),
+            &format!(
+                "{} ({}) S 1 {} {} 0 -1 4194560 100 0 0 0 {} {} 0 0 20 0 1 0 \
+                 42 12345 {} 18446744073709551615\n",
+                pid,
+                name,
+                pid,
+                pid,
+                time / 2,
+                time - time / 2,
+                rss.unwrap_or(0) / 4
+            ),
+        );
+        let mut status = format!("Name:\t{}\nState:\tS (sleeping)\n", name);
+        if let Some(rss) = rss {
+            status.push_str(&format!("VmRSS:\t{:>8} kB\n", rss));
+        }
+        root.write(&format!("/proc/{}/status", pid), &status);
+    }
+
+    fn processes(root: &Root) {
+        root.write("/proc/cpuinfo", CPUINFO)
+            .write("/proc/stat", &stat(100, 100, 700, 100))
+            .write("/proc/meminfo", "MemTotal:        1000000 kB\n")
+            .write("/proc/self/stat", "0")
+            .write("/proc/12a/stat", "0");
+        process(root, 1, "systemd", 10, Some(10000));
+        process(root, 2, "kthreadd", 0, None);
+        process(root, 42, "Web Content (x)", 100, Some(250000));
+        process(root, 99, "cargo", 20, Some(50000));
+    }
+
+    #[test]
+    fn popup_toggle() {
+        let root = Root::new();
+        processes(&root);
+        let mut cpu = Cpu::new(CpuConfig::default(), root.source()).unwrap();
+        assert!(cpu.popup().is_none());
+
+        cpu.click(Button::Right);
+        assert!(cpu.popup().is_none());
+
+        cpu.click(Button::Left);
+        let popup = cpu.popup().unwrap();
+        assert_eq!(popup.len(), 4);
+        assert_eq!(popup[0].text, "    42 Web Content (x)  10.0%  25.0%");
+        assert_eq!(popup[1].text, "    99 cargo             2.0%   5.0%");
+        assert_eq!(popup[2].text, "     1 systemd           1.0%   1.0%");
+        assert_eq!(popup[3].text, "     2 kthreadd          0.0%   0.0%");
+        assert_eq!(popup[0].value, Some(10.));
+
+        cpu.click(Button::Left);
+        assert!(cpu.popup().is_none());
+    }
+
+    #[test]
+    fn popup_delta() {
+        let root = Root::new();
+        processes(&root);
+        let mut cpu = Cpu::new(CpuConfig::default(), root.source()).unwrap();
+        cpu.click(Button::Left);
+        cpu.popup().unwrap();
+
+        // Exited processes vanish and new ones count their whole time
+        root.write("/proc/stat", &stat(200, 200, 800, 100));
+        process(&root, 1, "systemd", 70, Some(10000));
+        process(&root, 99, "cargo", 140, Some(50000));
+        fs::remove_file(root.source().path("/proc/42/stat")).unwrap();
+        for pid in 100..105 {
+            process(&root, pid, "sh", 3, Some(1000));
+        }
+
+        let processes = cpu.processes().unwrap();
+        assert_eq!(processes.len(), TOP_PROCESSES);
+        assert_eq!(
+            processes[0],
+            Process {
+                pid: 99,
+                name: "cargo".into(),
+                cpu: 40.,
+                memory: 5.,
+            }
+        );
+        assert_eq!(processes[1].pid, 1);
+        assert_eq!(processes[1].cpu, 20.);
+        assert_eq!(processes[2].pid, 100);
+        assert_eq!(processes[2].cpu, 1.);
+        assert!(processes.iter().all(|p| p.pid != 42));
+
+        // Reopening the popup starts with new samples
+        cpu.click(Button::Left);
+        cpu.click(Button::Left);
+        assert_eq!(cpu.processes().unwrap()[0].cpu, 140. / 1300. * 100.);
+    }
+
+    #[test]
+    fn popup_missing_source() {
+        let root = Root::new();
+        root.write("/proc/stat", &stat(100, 100, 700, 100));
+        let mut cpu = Cpu::new(CpuConfig::default(), root.source()).unwrap();
+        cpu.click(Button::Left);
+        let popup = cpu.popup().unwrap();
+        assert_eq!(popup.len(), 1);
+        assert!(popup[0].text.starts_with("Unable to list the processes"));
+    }
+
+    #[test]
+    fn load_missing_source() {
+        let root = Root::new();
+        let mut cpu = Cpu::new(CpuConfig::default(), root.source()).unwrap();
+        assert!(cpu.update().is_err());
     }
 }
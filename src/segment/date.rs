@@ -1,27 +1,159 @@
-use crate::segment::Segment;
-use amethyst::ui::UiText;
-use chrono::Local;
+use crate::{
+    segment::{Segment, SegmentOutput},
+    template::Template,
+};
+use chrono::{
+    format::{Item, StrftimeItems},
+    Local,
+};
+use failure::{format_err, Fallible};
+use serde_derive::Deserialize;
 use uuid::Uuid;
 
-#[derive(Default)]
+/// The placeholders available within the format
+const PLACEHOLDERS: &[&str] = &["time", "date"];
+
+/// The configuration of a date segment
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct DateConfig {
+    /// The name to address the segment with
+    #[serde(default = "DateConfig::default_name")]
+    pub name: String,
+
+    /// The displayed text, where the placeholders `time` and `date` are
+    /// available
+    #[serde(default = "DateConfig::default_format")]
+    pub format: String,
+
+    /// The strftime format of the `time` placeholder
+    #[serde(default = "DateConfig::default_time_format")]
+    pub time_format: String,
+
+    /// The strftime format of the `date` placeholder
+    #[serde(default = "DateConfig::default_date_format")]
+    pub date_format: String,
+
+    /// The minimum width of the segment in pixels
+    #[serde(default = "DateConfig::default_width")]
+    pub width: u16,
+}
+
+impl Default for DateConfig {
+    fn default() -> Self {
+        Self {
+            name: Self::default_name(),
+            format: Self::default_format(),
+            time_format: Self::default_time_format(),
+            date_format: Self::default_date_format(),
+            width: Self::default_width(),
+        }
+    }
+}
+
+impl DateConfig {
+    fn default_name() -> String {
+        "date".to_owned()
+    }
+
+    fn default_format() -> String {
+        "\u{f017} {time} \u{f073} {date}".to_owned()
+    }
+
+    fn default_time_format() -> String {
+        "%T".to_owned()
+    }
+
+    fn default_date_format() -> String {
+        "%a %e %b W%V".to_owned()
+    }
+
+    fn default_width() -> u16 {
+        190
+    }
+}
+
+/// The current date and time
 pub struct Date {
     id: String,
+    config: DateConfig,
+    template: Template,
 }
 
 impl Segment for Date {
-    type Item = UiText;
+    fn id(&self) -> &str {
+        &self.id
+    }
 
-    fn new() -> Self {
-        Date {
-            id: Uuid::new_v4().to_string(),
-        }
+    fn name(&self) -> &str {
+        &self.config.name
     }
 
-    fn update(&mut self, t: &mut UiText) {
-        t.text = Local::now().format(" %T  %a %e %b W%V").to_string();
+    fn update(&mut self) -> Fallible<SegmentOutput> {
+        let now = Local::now();
+        Ok(self.template.output(&[
+            (
+                "time",
+                now.format(&self.config.time_format).to_string().into(),
+            ),
+            (
+                "date",
+                now.format(&self.config.date_format).to_string().into(),
+            ),
+        ]))
     }
 
-    fn id(&self) -> &str {
-        &self.id
+    fn width(&self) -> u16 {
+        self.config.width
+    }
+}
+
+impl Date {
+    /// Create a new date segment
+    pub fn new(config: DateConfig) -> Fallible<Self> {
+        let template: Template = config.format.parse()?;
+        template.validate(PLACEHOLDERS)?;
+
+        // Invalid specifiers would panic while formatting
+        for format in &[&config.time_format, &config.date_format] {
+            if StrftimeItems::new(format).any(|i| i == Item::Error) {
+                return Err(format_err!("invalid date format '{}'", format));
+            }
+        }
+        Ok(Date {
+            id: Uuid::new_v4().to_string(),
+            config,
+            template,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format() {
+        let mut date = Date::new(DateConfig {
+            format: "{date}|{time}".into(),
+            time_format: "%H".into(),
+            date_format: "%Y".into(),
+            ..DateConfig::default()
+        })
+        .unwrap();
+        let text = date.update().unwrap().text;
+        assert_eq!(text.len(), 7);
+        assert_eq!(&text[4..5], "|");
+
+        assert!(Date::new(DateConfig::default()).is_ok());
+        assert!(Date::new(DateConfig {
+            format: "{weekday}".into(),
+            ..DateConfig::default()
+        })
+        .is_err());
+        assert!(Date::new(DateConfig {
+            time_format: "%Q".into(),
+            ..DateConfig::default()
+        })
+        .is_err());
     }
 }
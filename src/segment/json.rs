@@ -0,0 +1,447 @@
+//! A segment showing values of a polled JSON document
+
+use crate::{
+    color::ColorScheme,
+    http,
+    jsonpath::JsonPath,
+    segment::{Segment, SegmentOutput},
+    template::{Template, Value},
+};
+use failure::{format_err, Fallible};
+use log::warn;
+use serde_derive::Deserialize;
+use serde_json::Value as JsonValue;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{mpsc::Receiver, Mutex},
+    time::Duration,
+};
+use uuid::Uuid;
+
+/// The configuration of a JSON segment
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct JsonConfig {
+    /// The name to address the segment with
+    #[serde(default = "JsonConfig::default_name")]
+    pub name: String,
+
+    /// The URL of the JSON document
+    pub url: String,
+
+    /// The seconds between two requests
+    #[serde(default = "JsonConfig::default_interval")]
+    pub interval: u64,
+
+    /// Additional request headers like `Authorization`
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+
+    /// The placeholder names and the paths of their values, like
+    /// `depth: $.queues[0].depth`
+    pub fields: BTreeMap<String, String>,
+
+    /// The displayed text containing the fields as placeholders
+    pub format: String,
+
+    /// The field whose number is the value the `thresholds` apply to
+    #[serde(default)]
+    pub value: Option<String>,
+
+    /// The rules coloring the output by texts, where the first matching one
+    /// applies
+    #[serde(default)]
+    pub colors: Vec<ColorRule>,
+
+    /// The minimum width of the segment in pixels
+    #[serde(default = "JsonConfig::default_width")]
+    pub width: u16,
+}
+
+impl JsonConfig {
+    fn default_name() -> String {
+        "json".to_owned()
+    }
+
+    fn default_interval() -> u64 {
+        60
+    }
+
+    fn default_width() -> u16 {
+        100
+    }
+}
+
+/// A rule matching the value of a field by its text
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct ColorRule {
+    /// The field whose value gets compared
+    pub field: String,
+
+    /// Matches texts or numbers equal to this value
+    pub equals: String,
+
+    /// The text color by name like `red` or as `#rrggbb`
+    #[serde(default)]
+    pub color: Option<String>,
+
+    /// The background color by name or as `#rrggbb`
+    #[serde(default)]
+    pub background: Option<String>,
+
+    /// Mark the output as urgent
+    #[serde(default)]
+    pub urgent: bool,
+}
+
+impl ColorRule {
+    /// Returns true if the value equals the text of the rule
+    fn matches(&self, value: Option<&Value>) -> bool {
+        match value {
+            Some(Value::Number(n)) => n.to_string() == self.equals,
+            Some(Value::Text(t)) => *t == self.equals,
+            None => false,
+        }
+    }
+
+    fn apply(&self, output: SegmentOutput) -> SegmentOutput {
+        let mut output = output.with_urgent(self.urgent);
+        if let Some(color) =
+            self.color.as_deref().and_then(ColorScheme::from_name)
+        {
+            output = output.with_color(color);
+        }
+        if let Some(color) =
+            self.background.as_deref().and_then(ColorScheme::from_name)
+        {
+            output = output.with_background(color);
+        }
+        output
+    }
+}
+
+/// Values of a JSON document requested periodically on a background thread
+pub struct Json {
+    id: String,
+    config: JsonConfig,
+    template: Template,
+    fields: Vec<(String, JsonPath)>,
+    updates: Mutex<Receiver<Result<JsonValue, String>>>,
+    document: Option<JsonValue>,
+    error: Option<String>,
+}
+
+impl Json {
+    /// Create a new instance, which requests the document immediately
+    pub fn new(config: JsonConfig) -> Fallible<Self> {
+        let mut fields = vec![];
+        for (name, path) in &config.fields {
+            fields.push((name.clone(), path.parse::<JsonPath>()?));
+        }
+        let names: Vec<&str> =
+            config.fields.keys().map(String::as_str).collect();
+        let template: Template = config.format.parse()?;
+        template.validate(&names)?;
+
+        if let Some(field) = &config.value {
+            if !config.fields.contains_key(field) {
+                return Err(format_err!("unknown value field '{}'", field));
+            }
+        }
+        for rule in &config.colors {
+            if !config.fields.contains_key(&rule.field) {
+                return Err(format_err!(
+                    "unknown field '{}' in color rule",
+                    rule.field
+                ));
+            }
+            for color in rule.color.iter().chain(&rule.background) {
+                ColorScheme::from_name(color)
+                    .ok_or_else(|| format_err!("invalid color '{}'", color))?;
+            }
+        }
+
+        let (url, headers): (String, Vec<(String, String)>) = (
+            config.url.clone(),
+            config.headers.clone().into_iter().collect(),
+        );
+        let updates = http::poll(
+            Duration::from_secs(config.interval.max(1)),
+            move || {
+                Ok(http::get(&url, &headers)
+                    .and_then(|body| Ok(serde_json::from_str(&body)?))
+                    .map_err(|e| {
+                        warn!("Unable to retrieve {}: {}", url, e);
+                        e.to_string()
+                    }))
+            },
+        );
+
+        Ok(Self {
+            id: Uuid::new_v4().to_string(),
+            config,
+            template,
+            fields,
+            updates: Mutex::new(updates),
+            document: None,
+            error: None,
+        })
+    }
+
+    /// Convert the values matching a path, where multiple ones get joined
+    fn value(values: Vec<JsonValue>) -> Option<Value> {
+        let text = |value: &JsonValue| match value {
+            JsonValue::String(s) => s.clone(),
+            JsonValue::Null => String::new(),
+            v => v.to_string(),
+        };
+        match values.as_slice() {
+            [] => None,
+            [JsonValue::Number(n)] => n.as_f64().map(Value::Number),
+            [value] => Some(Value::Text(text(value))),
+            values => Some(Value::Text(
+                values.iter().map(text).collect::<Vec<_>>().join(", "),
+            )),
+        }
+    }
+}
+
+impl Segment for Json {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn update(&mut self) -> Fallible<SegmentOutput> {
+        for update in self.updates.lock().unwrap().try_iter() {
+            match update {
+                Ok(document) => {
+                    self.document = Some(document);
+                    self.error = None;
+                }
+                Err(e) => self.error = Some(e),
+            }
+        }
+
+        let document = match (&self.document, &self.error) {
+            (Some(document), _) => document,
+            (None, Some(_)) => {
+                return Ok(SegmentOutput::new("unavailable")
+                    .with_color(ColorScheme::red()))
+            }
+            (None, None) => {
+                return Ok(SegmentOutput::default().with_hidden(true))
+            }
+        };
+
+        let values: Vec<(&str, Value)> = self
+            .fields
+            .iter()
+            .filter_map(|(name, path)| {
+                Self::value(path.query(document)).map(|v| (name.as_str(), v))
+            })
+            .collect();
+        let mut output = self.template.output(&values);
+        let value = values
+            .iter()
+            .find(|(n, _)| Some(*n) == self.config.value.as_deref())
+            .and_then(|(_, v)| match v {
+                Value::Number(n) => Some(*n),
+                Value::Text(t) => t.trim().parse().ok(),
+            });
+        if let Some(value) = value {
+            output = output.with_value(value);
+        }
+        let text = |field: &str| {
+            values.iter().find(|(n, _)| *n == field).map(|(_, v)| v)
+        };
+        if let Some(rule) = self
+            .config
+            .colors
+            .iter()
+            .find(|r| r.matches(text(&r.field)))
+        {
+            output = rule.apply(output);
+        }
+
+        // Outdated values are dimmed
+        Ok(match self.error {
+            Some(_) => output.with_color(ColorScheme::selection()),
+            None => output,
+        })
+    }
+
+    fn width(&self) -> u16 {
+        self.config.width
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        mock::{
+            http::Server,
+            wait::{wait_for, wait_for_text},
+        },
+        segment::{Band, BandStyle, ThresholdConfig, Thresholds},
+    };
+
+    fn config(server: &Server) -> JsonConfig {
+        let fields = [
+            ("depth", "$.queues[0].depth"),
+            ("status", "$.deploy.status"),
+            ("services", "$.deploy.services[*]"),
+        ];
+        JsonConfig {
+            name: JsonConfig::default_name(),
+            url: server.url("/status"),
+            interval: 1,
+            headers: HashMap::new(),
+            fields: fields
+                .iter()
+                .map(|(n, p)| ((*n).to_owned(), (*p).to_owned()))
+                .collect(),
+            format: "Q {depth} {status} ({services})".into(),
+            value: Some("depth".into()),
+            colors: vec![ColorRule {
+                field: "status".into(),
+                equals: "failed".into(),
+                color: None,
+                background: Some("red".into()),
+                urgent: true,
+            }],
+            width: JsonConfig::default_width(),
+        }
+    }
+
+    fn document(depth: u64, status: &str) -> String {
+        format!(
+            r#"{{"queues": [{{"depth": {}}}],
+                "deploy": {{"status": "{}", "services": ["api", "web"]}}}}"#,
+            depth, status
+        )
+    }
+
+    #[test]
+    fn polling() {
+        let server = Server::start();
+        server.respond("/status", 200, &document(12, "done"));
+        let mut segment = Json::new(config(&server)).unwrap();
+
+        let output = wait_for_text(&mut segment, "Q 12 done (api, web)");
+        assert_eq!(output.color, None);
+        assert_eq!(output.background, None);
+        assert!(!output.urgent);
+        assert_eq!(output.value, Some(12.));
+
+        // Texts are colored by rules
+        server.respond("/status", 200, &document(500, "failed"));
+        let output = wait_for_text(&mut segment, "Q 500 failed (api, web)");
+        assert_eq!(output.value, Some(500.));
+        assert_eq!(output.background, Some(ColorScheme::red()));
+        assert!(output.urgent);
+
+        // Failures keep the previous values
+        server.respond("/status", 500, "");
+        let output = wait_for(&mut segment, |o| o.color.is_some());
+        assert_eq!(output.text, "Q 500 failed (api, web)");
+        assert_eq!(output.color, Some(ColorScheme::selection()));
+    }
+
+    #[test]
+    fn thresholds() {
+        let server = Server::start();
+        server.respond("/status", 200, &document(100, "done"));
+        let mut config = ThresholdConfig {
+            degraded: 100.,
+            critical: 500.,
+            styles: BTreeMap::new(),
+        };
+        config.styles.insert(
+            Band::Critical,
+            BandStyle {
+                background: Some("red".into()),
+                urgent: true,
+                ..BandStyle::default()
+            },
+        );
+        let segment = Box::new(Json::new(self::config(&server)).unwrap());
+        let mut segment = Thresholds::new(segment, config).unwrap();
+
+        let output = wait_for_text(&mut segment, "Q 100 done (api, web)");
+        assert_eq!(output.color, Some(ColorScheme::orange()));
+
+        server.respond("/status", 200, &document(500, "done"));
+        let output = wait_for_text(&mut segment, "Q 500 done (api, web)");
+        assert_eq!(output.background, Some(ColorScheme::red()));
+        assert!(output.urgent);
+
+        // Texts are parsed as numbers
+        let mut config = self::config(&server);
+        config.value = Some("status".into());
+        server.respond("/status", 200, &document(1, " 2.5"));
+        let mut segment = Json::new(config).unwrap();
+        assert_eq!(
+            wait_for_text(&mut segment, "Q 1  2.5 (api, web)").value,
+            Some(2.5)
+        );
+    }
+
+    #[test]
+    fn unavailable() {
+        let server = Server::start();
+        server.respond("/status", 200, "no json");
+        let mut segment = Json::new(config(&server)).unwrap();
+        let output = wait_for_text(&mut segment, "unavailable");
+        assert_eq!(output.color, Some(ColorScheme::red()));
+
+        // Missing values are empty
+        server.respond("/status", 200, r#"{"deploy": {"status": null}}"#);
+        wait_for_text(&mut segment, "Q   ()");
+    }
+
+    #[test]
+    fn rules() {
+        let rule = |equals: &str| ColorRule {
+            field: "a".into(),
+            equals: equals.into(),
+            color: None,
+            background: None,
+            urgent: false,
+        };
+        let number = Value::Number(3.);
+        let text = Value::Text("failed".into());
+        assert!(rule("3").matches(Some(&number)));
+        assert!(!rule("3.0").matches(Some(&number)));
+        assert!(rule("failed").matches(Some(&text)));
+        assert!(!rule("fail").matches(Some(&text)));
+        assert!(!rule("failed").matches(None));
+    }
+
+    #[test]
+    fn invalid() {
+        let server = Server::start();
+        let mut config = config(&server);
+        config.format = "{missing}".into();
+        assert!(Json::new(config).is_err());
+
+        let mut config = self::config(&server);
+        config.fields.insert("x".into(), "$.[".into());
+        assert!(Json::new(config).is_err());
+
+        let mut config = self::config(&server);
+        config.value = Some("missing".into());
+        assert!(Json::new(config).is_err());
+
+        let mut config = self::config(&server);
+        config.colors[0].field = "missing".into();
+        assert!(Json::new(config).is_err());
+
+        let mut config = self::config(&server);
+        config.colors[0].color = Some("magenta".into());
+        assert!(Json::new(config).is_err());
+    }
+}
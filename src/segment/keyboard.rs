@@ -0,0 +1,826 @@
+//! A segment showing the active keyboard layout
+
+use crate::{
+    segment::{Button, Segment, SegmentOutput},
+    stop::Stop,
+    template::Template,
+};
+use failure::{format_err, Fallible};
+use log::{debug, warn};
+use serde_derive::Deserialize;
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    env,
+    ffi::{CStr, CString},
+    io::prelude::*,
+    mem,
+    net::Shutdown,
+    os::{
+        raw::{c_char, c_int, c_uchar, c_uint, c_ulong, c_ushort, c_void},
+        unix::net::UnixStream,
+    },
+    path::{Path, PathBuf},
+    ptr,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+};
+use uuid::Uuid;
+
+/// The placeholders available within the format
+const PLACEHOLDERS: &[&str] = &["layout", "name"];
+
+/// The configuration of a keyboard layout segment
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct KeyboardConfig {
+    /// The name to address the segment with
+    #[serde(default = "KeyboardConfig::default_name")]
+    pub name: String,
+
+    /// The displayed text, where the placeholders `layout` for the label
+    /// and `name` for the name of the layout are available
+    #[serde(default = "KeyboardConfig::default_format")]
+    pub format: String,
+
+    /// Labels to display instead of the layout names, like `German: DE`
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+
+    /// The minimum width of the segment in pixels
+    #[serde(default = "KeyboardConfig::default_width")]
+    pub width: u16,
+}
+
+impl Default for KeyboardConfig {
+    fn default() -> Self {
+        Self {
+            name: Self::default_name(),
+            format: Self::default_format(),
+            labels: HashMap::new(),
+            width: Self::default_width(),
+        }
+    }
+}
+
+impl KeyboardConfig {
+    fn default_name() -> String {
+        "keyboard".to_owned()
+    }
+
+    fn default_format() -> String {
+        "\u{f11c} {layout}".to_owned()
+    }
+
+    fn default_width() -> u16 {
+        80
+    }
+}
+
+/// The configured layouts of a keyboard
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Layouts {
+    /// The names of all layouts ordered by their group index
+    pub names: Vec<String>,
+
+    /// The index of the active layout
+    pub active: usize,
+}
+
+/// A source of the keyboard layouts, like the X server
+pub trait KeyboardBackend: Send {
+    /// Retrieve the current layouts
+    fn layouts(&mut self) -> Fallible<Layouts>;
+
+    /// Activate the layout with the provided index
+    fn set_layout(&mut self, index: usize) -> Fallible<()>;
+
+    /// Send an event on every change of the layouts
+    fn subscribe(&mut self, events: Sender<()>) -> Fallible<()>;
+}
+
+/// The device specification of the core keyboard
+const XKB_USE_CORE_KBD: c_uint = 0x100;
+
+/// The event type of XKB state changes
+const XKB_STATE_NOTIFY: c_uint = 2;
+
+/// The XKB state component of the locked group
+const XKB_GROUP_STATE_MASK: c_ulong = 1 << 4;
+
+/// The XKB events for new keyboards and changed names
+const XKB_NEW_KEYBOARD_NOTIFY_MASK: c_ulong = 1;
+const XKB_NAMES_NOTIFY_MASK: c_ulong = 1 << 6;
+
+/// The XKB name component of the symbols
+const XKB_SYMBOLS_NAME_MASK: c_uint = 1 << 2;
+
+/// Symbols of the XKB rules which are no layouts
+const NON_LAYOUTS: &[&str] = &[
+    "altwin",
+    "capslock",
+    "compose",
+    "ctrl",
+    "eurosign",
+    "evdev",
+    "group",
+    "inet",
+    "keypad",
+    "kpdl",
+    "level3",
+    "level5",
+    "nbsp",
+    "pc",
+    "shift",
+    "srvr_ctrl",
+    "terminate",
+];
+
+#[repr(C)]
+struct XkbState {
+    group: c_uchar,
+    locked_group: c_uchar,
+    base_group: c_ushort,
+    latched_group: c_ushort,
+    mods: c_uchar,
+    base_mods: c_uchar,
+    latched_mods: c_uchar,
+    locked_mods: c_uchar,
+    compat_state: c_uchar,
+    grab_mods: c_uchar,
+    compat_grab_mods: c_uchar,
+    lookup_mods: c_uchar,
+    compat_lookup_mods: c_uchar,
+    ptr_buttons: c_ushort,
+}
+
+/// The leading fields of `XkbNamesRec`
+#[repr(C)]
+struct XkbNames {
+    keycodes: c_ulong,
+    geometry: c_ulong,
+    symbols: c_ulong,
+}
+
+/// The leading fields of `XkbDescRec`
+#[repr(C)]
+struct XkbDesc {
+    display: *mut c_void,
+    flags: c_ushort,
+    device_spec: c_ushort,
+    min_key_code: c_uchar,
+    max_key_code: c_uchar,
+    controls: *mut c_void,
+    server: *mut c_void,
+    map: *mut c_void,
+    indicators: *mut c_void,
+    names: *mut XkbNames,
+}
+
+/// The XKB functions of libX11, which gets loaded at runtime to work without
+/// X11 as well
+struct Xlib {
+    open_display: unsafe extern "C" fn(
+        *const c_char,
+        *mut c_int,
+        *mut c_int,
+        *mut c_int,
+        *mut c_int,
+        *mut c_int,
+    ) -> *mut c_void,
+    close_display: unsafe extern "C" fn(*mut c_void) -> c_int,
+    flush: unsafe extern "C" fn(*mut c_void) -> c_int,
+    connection_number: unsafe extern "C" fn(*mut c_void) -> c_int,
+    pending: unsafe extern "C" fn(*mut c_void) -> c_int,
+    next_event: unsafe extern "C" fn(*mut c_void, *mut c_void) -> c_int,
+    get_atom_name: unsafe extern "C" fn(*mut c_void, c_ulong) -> *mut c_char,
+    free: unsafe extern "C" fn(*mut c_void) -> c_int,
+    get_state:
+        unsafe extern "C" fn(*mut c_void, c_uint, *mut XkbState) -> c_int,
+    alloc_keyboard: unsafe extern "C" fn() -> *mut XkbDesc,
+    free_keyboard: unsafe extern "C" fn(*mut XkbDesc, c_uint, c_int),
+    get_names: unsafe extern "C" fn(*mut c_void, c_uint, *mut XkbDesc) -> c_int,
+    lock_group: unsafe extern "C" fn(*mut c_void, c_uint, c_uint) -> c_int,
+    select_events:
+        unsafe extern "C" fn(*mut c_void, c_uint, c_ulong, c_ulong) -> c_int,
+    select_event_details: unsafe extern "C" fn(
+        *mut c_void,
+        c_uint,
+        c_uint,
+        c_ulong,
+        c_ulong,
+    ) -> c_int,
+}
+
+impl Xlib {
+    /// Load libX11 and resolve all required functions
+    fn load() -> Fallible<Self> {
+        let name = CString::new("libX11.so.6")?;
+        let handle = unsafe {
+            libc::dlopen(name.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL)
+        };
+        if handle.is_null() {
+            return Err(format_err!("unable to load libX11"));
+        }
+
+        unsafe {
+            Ok(Self {
+                open_display: Self::symbol(handle, "XkbOpenDisplay")?,
+                close_display: Self::symbol(handle, "XCloseDisplay")?,
+                flush: Self::symbol(handle, "XFlush")?,
+                connection_number: Self::symbol(handle, "XConnectionNumber")?,
+                pending: Self::symbol(handle, "XPending")?,
+                next_event: Self::symbol(handle, "XNextEvent")?,
+                get_atom_name: Self::symbol(handle, "XGetAtomName")?,
+                free: Self::symbol(handle, "XFree")?,
+                get_state: Self::symbol(handle, "XkbGetState")?,
+                alloc_keyboard: Self::symbol(handle, "XkbAllocKeyboard")?,
+                free_keyboard: Self::symbol(handle, "XkbFreeKeyboard")?,
+                get_names: Self::symbol(handle, "XkbGetNames")?,
+                lock_group: Self::symbol(handle, "XkbLockGroup")?,
+                select_events: Self::symbol(handle, "XkbSelectEvents")?,
+                select_event_details: Self::symbol(
+                    handle,
+                    "XkbSelectEventDetails",
+                )?,
+            })
+        }
+    }
+
+    /// Resolve a function, where `T` has to be its pointer type
+    unsafe fn symbol<T>(handle: *mut c_void, name: &str) -> Fallible<T> {
+        let c_name = CString::new(name)?;
+        let symbol = libc::dlsym(handle, c_name.as_ptr());
+        if symbol.is_null() {
+            return Err(format_err!("libX11 does not provide {}", name));
+        }
+        Ok(mem::transmute_copy(&symbol))
+    }
+
+    /// Open the default display with XKB support
+    fn open(&self) -> Fallible<*mut c_void> {
+        let (mut event, mut error, mut reason) = (0, 0, 0);
+        let (mut major, mut minor) = (1, 0);
+        let display = unsafe {
+            (self.open_display)(
+                ptr::null(),
+                &mut event,
+                &mut error,
+                &mut major,
+                &mut minor,
+                &mut reason,
+            )
+        };
+        if display.is_null() {
+            return Err(format_err!(
+                "unable to open X display with XKB support (reason {})",
+                reason
+            ));
+        }
+        Ok(display)
+    }
+}
+
+/// A backend using the XKB extension of the X server
+pub struct Xkb {
+    xlib: Arc<Xlib>,
+    display: *mut c_void,
+    stop: Option<Arc<Stop>>,
+}
+
+// The display is only used by the owning segment
+unsafe impl Send for Xkb {}
+
+impl Xkb {
+    /// Connect to the X server
+    pub fn new() -> Fallible<Self> {
+        let xlib = Xlib::load()?;
+        let display = xlib.open()?;
+        Ok(Self {
+            xlib: Arc::new(xlib),
+            display,
+            stop: None,
+        })
+    }
+
+    /// Retrieve the rules symbols like `pc+us+de:2+inet(evdev)`
+    fn symbols(&self) -> Fallible<String> {
+        let xlib = &self.xlib;
+        unsafe {
+            let keyboard = (xlib.alloc_keyboard)();
+            if keyboard.is_null() {
+                return Err(format_err!("unable to allocate XKB keyboard"));
+            }
+            let result = if (xlib.get_names)(
+                self.display,
+                XKB_SYMBOLS_NAME_MASK,
+                keyboard,
+            ) != 0
+                || (*keyboard).names.is_null()
+            {
+                Err(format_err!("unable to retrieve XKB names"))
+            } else {
+                let name = (xlib.get_atom_name)(
+                    self.display,
+                    (*(*keyboard).names).symbols,
+                );
+                if name.is_null() {
+                    Err(format_err!("unable to retrieve XKB symbols"))
+                } else {
+                    let symbols = CStr::from_ptr(name).to_string_lossy().into();
+                    (xlib.free)(name as *mut c_void);
+                    Ok(symbols)
+                }
+            };
+            (xlib.free_keyboard)(keyboard, 0, 1);
+            result
+        }
+    }
+
+    /// Parse the layout names out of the rules symbols
+    fn parse_symbols(symbols: &str) -> Vec<String> {
+        let mut layouts: Vec<(usize, String)> = vec![];
+        for symbol in symbols.split('+') {
+            let (name, group) = match symbol.find(':') {
+                Some(i) => (&symbol[..i], symbol[i + 1..].parse().unwrap_or(1)),
+                None => (symbol, layouts.len() + 1),
+            };
+            let name = name.split('(').next().unwrap_or_default();
+            if !name.is_empty() && !NON_LAYOUTS.contains(&name) {
+                layouts.push((group, name.to_owned()));
+            }
+        }
+        layouts.sort_by_key(|(group, _)| *group);
+        layouts.into_iter().map(|(_, name)| name).collect()
+    }
+}
+
+impl KeyboardBackend for Xkb {
+    fn layouts(&mut self) -> Fallible<Layouts> {
+        let mut state: XkbState = unsafe { mem::zeroed() };
+        if unsafe {
+            (self.xlib.get_state)(self.display, XKB_USE_CORE_KBD, &mut state)
+        } != 0
+        {
+            return Err(format_err!("unable to retrieve XKB state"));
+        }
+        Ok(Layouts {
+            names: Self::parse_symbols(&self.symbols()?),
+            active: usize::from(state.group),
+        })
+    }
+
+    fn set_layout(&mut self, index: usize) -> Fallible<()> {
+        unsafe {
+            if (self.xlib.lock_group)(
+                self.display,
+                XKB_USE_CORE_KBD,
+                index as c_uint,
+            ) == 0
+            {
+                return Err(format_err!("unable to lock XKB group {}", index));
+            }
+            (self.xlib.flush)(self.display);
+        }
+        Ok(())
+    }
+
+    fn subscribe(&mut self, events: Sender<()>) -> Fallible<()> {
+        // Events are read from a dedicated display, which blocks
+        let xlib = self.xlib.clone();
+        let display = xlib.open()?;
+        unsafe {
+            let mask = XKB_NEW_KEYBOARD_NOTIFY_MASK | XKB_NAMES_NOTIFY_MASK;
+            (xlib.select_events)(display, XKB_USE_CORE_KBD, mask, mask);
+            (xlib.select_event_details)(
+                display,
+                XKB_USE_CORE_KBD,
+                XKB_STATE_NOTIFY,
+                XKB_GROUP_STATE_MASK,
+                XKB_GROUP_STATE_MASK,
+            );
+            (xlib.flush)(display);
+        }
+
+        let stop = Arc::new(Stop::new()?);
+        self.stop = Some(stop.clone());
+        let display = display as usize;
+        thread::spawn(move || {
+            let display = display as *mut c_void;
+            let fd = unsafe { (xlib.connection_number)(display) };
+            // Large enough for the `XEvent` union
+            let mut event = [0 as c_ulong; 24];
+
+            // Only queued events are read, which never blocks stopping
+            'watch: while stop.wait(fd) {
+                while unsafe { (xlib.pending)(display) } > 0 {
+                    unsafe {
+                        (xlib.next_event)(
+                            display,
+                            event.as_mut_ptr() as *mut c_void,
+                        )
+                    };
+                    if events.send(()).is_err() {
+                        break 'watch;
+                    }
+                }
+            }
+            debug!("Stopped watching XKB events");
+            unsafe { (xlib.close_display)(display) };
+        });
+        Ok(())
+    }
+}
+
+impl Drop for Xkb {
+    fn drop(&mut self) {
+        if let Some(stop) = &self.stop {
+            stop.stop();
+        }
+        unsafe { (self.xlib.close_display)(self.display) };
+    }
+}
+
+/// The magic string which prefixes every Sway IPC message
+const SWAY_MAGIC: &[u8] = b"i3-ipc";
+
+/// Sway IPC message types
+const SWAY_RUN_COMMAND: u32 = 0;
+const SWAY_SUBSCRIBE: u32 = 2;
+const SWAY_GET_INPUTS: u32 = 100;
+
+/// A backend using the input IPC of the Sway compositor
+pub struct Sway {
+    path: PathBuf,
+    subscription: Option<UnixStream>,
+}
+
+impl Sway {
+    /// Create a new backend connecting to the socket at the provided path
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_owned(),
+            subscription: None,
+        }
+    }
+
+    /// Send a message and retrieve the JSON reply
+    fn request(
+        stream: &mut UnixStream,
+        message_type: u32,
+        payload: &str,
+    ) -> Fallible<Value> {
+        let mut message = SWAY_MAGIC.to_vec();
+        message.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        message.extend_from_slice(&message_type.to_le_bytes());
+        message.extend_from_slice(payload.as_bytes());
+        stream.write_all(&message)?;
+        Ok(Self::read(stream)?.1)
+    }
+
+    /// Read the next message and its type
+    fn read(stream: &mut UnixStream) -> Fallible<(u32, Value)> {
+        let mut header = [0; 14];
+        stream.read_exact(&mut header)?;
+        if &header[..6] != SWAY_MAGIC {
+            return Err(format_err!("invalid Sway IPC message"));
+        }
+
+        let mut length = [0; 4];
+        let mut message_type = [0; 4];
+        length.copy_from_slice(&header[6..10]);
+        message_type.copy_from_slice(&header[10..14]);
+
+        let mut payload = vec![0; u32::from_le_bytes(length) as usize];
+        stream.read_exact(&mut payload)?;
+        Ok((
+            u32::from_le_bytes(message_type),
+            serde_json::from_slice(&payload)?,
+        ))
+    }
+
+    fn connect(&self) -> Fallible<UnixStream> {
+        UnixStream::connect(&self.path).map_err(|e| {
+            format_err!("unable to connect to {}: {}", self.path.display(), e)
+        })
+    }
+}
+
+impl KeyboardBackend for Sway {
+    fn layouts(&mut self) -> Fallible<Layouts> {
+        let inputs = Self::request(&mut self.connect()?, SWAY_GET_INPUTS, "")?;
+        inputs
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter(|i| i["type"] == "keyboard")
+            .find_map(|i| {
+                let names: Vec<String> = i["xkb_layout_names"]
+                    .as_array()?
+                    .iter()
+                    .filter_map(|n| n.as_str().map(str::to_owned))
+                    .collect();
+                if names.is_empty() {
+                    return None;
+                }
+                Some(Layouts {
+                    names,
+                    active: i["xkb_active_layout_index"].as_u64().unwrap_or(0)
+                        as usize,
+                })
+            })
+            .ok_or_else(|| format_err!("no keyboard with layouts found"))
+    }
+
+    fn set_layout(&mut self, index: usize) -> Fallible<()> {
+        let reply = Self::request(
+            &mut self.connect()?,
+            SWAY_RUN_COMMAND,
+            &format!("input type:keyboard xkb_switch_layout {}", index),
+        )?;
+        if reply[0]["success"] != true {
+            return Err(format_err!(
+                "unable to switch layout: {}",
+                reply[0]["error"].as_str().unwrap_or("unknown error")
+            ));
+        }
+        Ok(())
+    }
+
+    fn subscribe(&mut self, events: Sender<()>) -> Fallible<()> {
+        let mut stream = self.connect()?;
+        let reply = Self::request(
+            &mut stream,
+            SWAY_SUBSCRIBE,
+            &json!(["input"]).to_string(),
+        )?;
+        if reply["success"] != true {
+            return Err(format_err!("unable to subscribe to input events"));
+        }
+
+        self.subscription = Some(stream.try_clone()?);
+        thread::spawn(move || {
+            while let Ok((message_type, _)) = Self::read(&mut stream) {
+                // Only events have the most significant bit set
+                if message_type & 0x8000_0000 != 0 && events.send(()).is_err() {
+                    break;
+                }
+            }
+            debug!("Stopped watching Sway input events");
+        });
+        Ok(())
+    }
+}
+
+impl Drop for Sway {
+    fn drop(&mut self) {
+        // Unblock the reading thread
+        if let Some(stream) = &self.subscription {
+            stream.shutdown(Shutdown::Both).ok();
+        }
+    }
+}
+
+/// A segment showing the active keyboard layout, which only queries the
+/// backend on layout change events
+pub struct Keyboard {
+    id: String,
+    config: KeyboardConfig,
+    template: Template,
+    backend: Mutex<Box<dyn KeyboardBackend>>,
+    events: Mutex<Receiver<()>>,
+    layouts: Option<Layouts>,
+}
+
+impl Keyboard {
+    /// Create a new segment using Sway if running, the X server otherwise
+    pub fn new(config: KeyboardConfig) -> Fallible<Self> {
+        let backend: Box<dyn KeyboardBackend> = match env::var_os("SWAYSOCK") {
+            Some(path) => Box::new(Sway::new(Path::new(&path))),
+            None => Box::new(Xkb::new()?),
+        };
+        Self::with_backend(config, backend)
+    }
+
+    /// Create a new segment using the provided backend
+    pub fn with_backend(
+        config: KeyboardConfig,
+        mut backend: Box<dyn KeyboardBackend>,
+    ) -> Fallible<Self> {
+        let template: Template = config.format.parse()?;
+        template.validate(PLACEHOLDERS)?;
+        let (tx, rx) = mpsc::channel();
+        backend.subscribe(tx)?;
+        Ok(Self {
+            id: Uuid::new_v4().to_string(),
+            config,
+            template,
+            backend: Mutex::new(backend),
+            events: Mutex::new(rx),
+            layouts: None,
+        })
+    }
+
+    /// Activate the layout with the provided offset to the active one
+    fn cycle(&mut self, offset: isize) -> Fallible<()> {
+        let mut backend = self.backend.lock().unwrap();
+        let layouts = match self.layouts.take() {
+            Some(layouts) => layouts,
+            None => backend.layouts()?,
+        };
+        let count = layouts.names.len() as isize;
+        if count == 0 {
+            return Ok(());
+        }
+
+        let active =
+            ((layouts.active as isize + offset) % count + count) % count;
+        backend.set_layout(active as usize)?;
+        self.layouts = Some(Layouts {
+            active: active as usize,
+            ..layouts
+        });
+        Ok(())
+    }
+}
+
+impl Segment for Keyboard {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn update(&mut self) -> Fallible<SegmentOutput> {
+        let changed = self.events.lock().unwrap().try_iter().count() > 0;
+        if changed || self.layouts.is_none() {
+            self.layouts = Some(self.backend.lock().unwrap().layouts()?);
+        }
+
+        let name = self
+            .layouts
+            .as_ref()
+            .and_then(|l| l.names.get(l.active))
+            .map(String::as_str)
+            .unwrap_or_default();
+        let label = match self.config.labels.get(name) {
+            Some(label) => label.clone(),
+            None => name.to_uppercase(),
+        };
+        Ok(self
+            .template
+            .output(&[("layout", label.into()), ("name", name.into())]))
+    }
+
+    fn click(&mut self, button: Button) {
+        let result = match button {
+            Button::Left | Button::ScrollUp => self.cycle(1),
+            Button::Right | Button::ScrollDown => self.cycle(-1),
+            Button::Middle => Ok(()),
+        };
+        if let Err(e) = result {
+            warn!("Unable to switch the keyboard layout: {}", e);
+        }
+    }
+
+    fn width(&self) -> u16 {
+        self.config.width
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{
+        i3::{self, Server},
+        wait::{wait_for_text, wait_until},
+    };
+
+    fn keyboard(server_path: &Path, config: KeyboardConfig) -> Keyboard {
+        Keyboard::with_backend(config, Box::new(Sway::new(server_path)))
+            .unwrap()
+    }
+
+    /// Wait until the server received the provided amount of commands
+    fn wait_for_commands(server: &Server, count: usize) -> Vec<String> {
+        wait_until(|| server.commands().len() >= count);
+        server.commands()
+    }
+
+    #[test]
+    fn sway_layouts() {
+        let path = i3::socket_path();
+        let server = Server::start(&path);
+        server.set_keyboard(&["English (US)", "German"], 0);
+
+        let mut config = KeyboardConfig::default();
+        config.labels.insert("German".into(), "DE".into());
+        let mut segment = keyboard(&path, config);
+        assert_eq!(segment.name(), "keyboard");
+        assert_eq!(segment.update().unwrap().text, "\u{f11c} ENGLISH (US)");
+
+        // The layouts are only retrieved again on events
+        server.set_keyboard(&["English (US)", "German"], 1);
+        assert_eq!(segment.update().unwrap().text, "\u{f11c} ENGLISH (US)");
+        server.send_input_event("xkb_layout");
+        wait_for_text(&mut segment, "\u{f11c} DE");
+    }
+
+    #[test]
+    fn sway_cycle() {
+        let path = i3::socket_path();
+        let server = Server::start(&path);
+        server.set_keyboard(&["us", "de", "fr"], 2);
+
+        let mut segment = keyboard(&path, KeyboardConfig::default());
+        segment.click(Button::Left);
+        assert_eq!(segment.update().unwrap().text, "\u{f11c} US");
+        segment.click(Button::ScrollDown);
+        assert_eq!(segment.update().unwrap().text, "\u{f11c} FR");
+        segment.click(Button::Middle);
+
+        assert_eq!(
+            wait_for_commands(&server, 2),
+            vec![
+                "input type:keyboard xkb_switch_layout 0",
+                "input type:keyboard xkb_switch_layout 2",
+            ]
+        );
+    }
+
+    #[test]
+    fn sway_format() {
+        let path = i3::socket_path();
+        let server = Server::start(&path);
+        server.set_keyboard(&["German"], 0);
+
+        let mut config = KeyboardConfig {
+            format: "{layout} ({name})".into(),
+            ..KeyboardConfig::default()
+        };
+        config.labels.insert("German".into(), "DE".into());
+        config.width = 120;
+        let mut segment = keyboard(&path, config);
+        assert_eq!(segment.update().unwrap().text, "DE (German)");
+        assert_eq!(segment.width(), 120);
+
+        let config = KeyboardConfig {
+            format: "{group}".into(),
+            ..KeyboardConfig::default()
+        };
+        assert!(
+            Keyboard::with_backend(config, Box::new(Sway::new(&path))).is_err()
+        );
+    }
+
+    #[test]
+    fn sway_unavailable() {
+        let path = i3::socket_path();
+        assert!(Keyboard::with_backend(
+            KeyboardConfig::default(),
+            Box::new(Sway::new(&path))
+        )
+        .is_err());
+
+        let server = Server::start(&path);
+        let mut segment = keyboard(&path, KeyboardConfig::default());
+        assert!(segment.update().is_err());
+        server.set_keyboard(&["us"], 0);
+        assert_eq!(segment.update().unwrap().text, "\u{f11c} US");
+    }
+
+    #[test]
+    fn sway_stop() {
+        let path = i3::socket_path();
+        let _server = Server::start(&path);
+        let (tx, rx) = mpsc::channel();
+        let mut backend = Sway::new(&path);
+        backend.subscribe(tx).unwrap();
+
+        // Dropping the backend ends the event thread
+        drop(backend);
+        assert_eq!(
+            rx.recv_timeout(std::time::Duration::from_secs(5)),
+            Err(mpsc::RecvTimeoutError::Disconnected)
+        );
+    }
+
+    #[test]
+    fn xkb_symbols() {
+        assert_eq!(
+            Xkb::parse_symbols(
+                "pc+us+de:2+inet(evdev)+group(alt_shift_toggle)"
+            ),
+            vec!["us", "de"]
+        );
+        assert_eq!(
+            Xkb::parse_symbols("pc+de(nodeadkeys)+us(intl):3+fr:2+inet(evdev)"),
+            vec!["de", "fr", "us"]
+        );
+        assert!(Xkb::parse_symbols("pc+inet(evdev)").is_empty());
+    }
+}
@@ -0,0 +1,266 @@
+//! A segment showing the system load average and uptime
+
+use crate::{
+    segment::{Segment, SegmentOutput},
+    source::SysSource,
+    template::{self, Template, Value},
+};
+use failure::{format_err, Fallible};
+use serde_derive::Deserialize;
+use uuid::Uuid;
+
+/// The placeholders available within the format
+const PLACEHOLDERS: &[&str] = &[
+    "load1", "load5", "load15", "running", "tasks", "cores", "uptime",
+];
+
+/// The configuration of a load segment
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct LoadConfig {
+    /// The name to address the segment with
+    #[serde(default = "LoadConfig::default_name")]
+    pub name: String,
+
+    /// The displayed text, where the placeholders `load1`, `load5`,
+    /// `load15`, `running`, `tasks`, `cores` and `uptime` are available
+    #[serde(default = "LoadConfig::default_format")]
+    pub format: String,
+
+    /// The minimum width of the segment in pixels
+    #[serde(default = "LoadConfig::default_width")]
+    pub width: u16,
+}
+
+impl Default for LoadConfig {
+    fn default() -> Self {
+        Self {
+            name: Self::default_name(),
+            format: Self::default_format(),
+            width: Self::default_width(),
+        }
+    }
+}
+
+impl LoadConfig {
+    fn default_name() -> String {
+        "load".to_owned()
+    }
+
+    fn default_format() -> String {
+        "\u{f0e4} {load1:.2} {load5:.2} {load15:.2}".to_owned()
+    }
+
+    fn default_width() -> u16 {
+        150
+    }
+}
+
+/// The load averages and uptime
+pub struct Load {
+    id: String,
+    config: LoadConfig,
+    template: Template,
+    source: SysSource,
+}
+
+impl Load {
+    /// Create a new instance which reads from the provided source
+    pub fn new(config: LoadConfig, source: SysSource) -> Fallible<Self> {
+        let template: Template = config.format.parse()?;
+        template.validate(PLACEHOLDERS)?;
+        Ok(Self {
+            id: Uuid::new_v4().to_string(),
+            config,
+            template,
+            source,
+        })
+    }
+
+    /// Parse `/proc/loadavg`, like `0.52 0.58 0.59 2/1234 5678`, into the
+    /// load averages and the amount of running and total tasks
+    fn loadavg(&self) -> Fallible<([f64; 3], u64, u64)> {
+        let content = self.source.read_to_string("/proc/loadavg")?;
+        let invalid =
+            || format_err!("invalid load average '{}'", content.trim());
+        let fields: Vec<&str> = content.split_whitespace().collect();
+        if fields.len() < 4 {
+            return Err(invalid());
+        }
+
+        let mut loads = [0.; 3];
+        for (load, field) in loads.iter_mut().zip(&fields) {
+            *load = field.parse().map_err(|_| invalid())?;
+        }
+        let mut tasks = fields[3].splitn(2, '/');
+        let mut next = || -> Fallible<u64> {
+            tasks
+                .next()
+                .and_then(|t| t.parse().ok())
+                .ok_or_else(invalid)
+        };
+        Ok((loads, next()?, next()?))
+    }
+
+    /// Count the processors of `/proc/cpuinfo`, which lists them on every
+    /// architecture unlike their frequencies
+    fn cores(&self) -> Fallible<u64> {
+        let cores = self
+            .source
+            .lines("/proc/cpuinfo")?
+            .filter(|l| l.split(':').next().map(str::trim) == Some("processor"))
+            .count();
+        Ok(cores.max(1) as u64)
+    }
+
+    /// Retrieve the uptime in seconds
+    fn uptime(&self) -> Fallible<u64> {
+        let content = self.source.read_to_string("/proc/uptime")?;
+        content
+            .split_whitespace()
+            .next()
+            .and_then(|u| u.parse::<f64>().ok())
+            .map(|u| u as u64)
+            .ok_or_else(|| format_err!("invalid uptime '{}'", content.trim()))
+    }
+}
+
+impl Segment for Load {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn update(&mut self) -> Fallible<SegmentOutput> {
+        let (loads, running, tasks) = self.loadavg()?;
+        let cores = self.cores()?;
+        let output = self.template.output(&[
+            ("load1", loads[0].into()),
+            ("load5", loads[1].into()),
+            ("load15", loads[2].into()),
+            ("running", running.into()),
+            ("tasks", tasks.into()),
+            ("cores", cores.into()),
+            ("uptime", Value::Text(template::duration(self.uptime()?))),
+        ]);
+
+        // The thresholds apply to the load relative to the amount of cores
+        Ok(output.with_value(loads[0] / cores as f64 * 100.))
+    }
+
+    fn width(&self) -> u16 {
+        self.config.width
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        color::ColorScheme,
+        mock::sys::Root,
+        segment::{ThresholdConfig, Thresholds},
+    };
+    use std::collections::BTreeMap;
+
+    const CPUINFO: &str = "processor\t: 0\n\
+                           cpu MHz\t\t: 2000.000\n\
+                           \n\
+                           processor\t: 1\n\
+                           cpu MHz\t\t: 3000.000\n";
+
+    fn fixture(loadavg: &str) -> Root {
+        let root = Root::new();
+        root.write("/proc/cpuinfo", CPUINFO)
+            .write("/proc/loadavg", loadavg)
+            .write("/proc/uptime", "97380.52 180000.12\n");
+        root
+    }
+
+    #[test]
+    fn default_format() {
+        let root = fixture("0.52 0.58 0.59 2/1234 5678\n");
+        let mut load = Load::new(LoadConfig::default(), root.source()).unwrap();
+        let output = load.update().unwrap();
+        assert_eq!(output.text, "\u{f0e4} 0.52 0.58 0.59");
+        assert_eq!(output.value, Some(26.));
+        assert_eq!(output.color, None);
+    }
+
+    #[test]
+    fn custom_format() {
+        let root = fixture("1.5 0.58 0.59 2/1234 5678\n");
+        let mut load = Load::new(
+            LoadConfig {
+                format: "{load1:>5.1}/{cores} {running}/{tasks} up {uptime}"
+                    .into(),
+                ..LoadConfig::default()
+            },
+            root.source(),
+        )
+        .unwrap();
+        let output = load.update().unwrap();
+        assert_eq!(output.text, "  1.5/2 2/1234 up 1d 3h");
+        assert_eq!(output.value, Some(75.));
+        assert_eq!(output.color, None);
+    }
+
+    #[test]
+    fn thresholds() {
+        let root = fixture("1.5 0.58 0.59 2/1234 5678\n");
+        let load = Load::new(LoadConfig::default(), root.source()).unwrap();
+        let config = ThresholdConfig {
+            degraded: 70.,
+            critical: 100.,
+            styles: BTreeMap::new(),
+        };
+        let mut load = Thresholds::new(Box::new(load), config).unwrap();
+        assert_eq!(load.update().unwrap().color, Some(ColorScheme::orange()));
+
+        root.write("/proc/loadavg", "2.00 0.58 0.59 2/1234 5678\n");
+        assert_eq!(load.update().unwrap().color, Some(ColorScheme::red()));
+    }
+
+    #[test]
+    fn cores_without_frequencies() {
+        // Like on ARM, where no frequencies are listed
+        let root = fixture("1.00 0.58 0.59 2/1234 5678\n");
+        root.write(
+            "/proc/cpuinfo",
+            "processor\t: 0\nBogoMIPS\t: 48.00\n\n\
+             processor\t: 1\nBogoMIPS\t: 48.00\n\n\
+             processor\t: 2\nprocessor\t: 3\nHardware\t: BCM2835\n",
+        );
+        let mut load = Load::new(
+            LoadConfig {
+                format: "{cores}".into(),
+                ..LoadConfig::default()
+            },
+            root.source(),
+        )
+        .unwrap();
+        let output = load.update().unwrap();
+        assert_eq!(output.text, "4");
+        assert_eq!(output.value, Some(25.));
+    }
+
+    #[test]
+    fn invalid() {
+        let root = fixture("0.52 0.58\n");
+        assert!(Load::new(
+            LoadConfig {
+                format: "{load}".into(),
+                ..LoadConfig::default()
+            },
+            root.source()
+        )
+        .is_err());
+
+        let mut load = Load::new(LoadConfig::default(), root.source()).unwrap();
+        assert!(load.update().is_err());
+        root.write("/proc/loadavg", "0.52 0.58 0.59 2-1234 5678\n");
+        assert!(load.update().is_err());
+    }
+}
@@ -1,23 +1,408 @@
 //! All available data segments
 
+mod backlight;
+mod command;
 mod cpu;
 mod date;
+mod json;
+mod keyboard;
+mod load;
+#[cfg(feature = "dbus")]
+mod mpris;
+#[cfg(feature = "dbus")]
+mod notifications;
+mod pressure;
+mod pushed;
+mod text;
+mod thresholds;
+#[cfg(feature = "dbus")]
+mod tray;
+mod volume;
+mod weather;
 
-pub use self::{cpu::Cpu, date::Date};
+pub use self::{
+    backlight::{Backlight, BacklightConfig},
+    command::{Command, CommandConfig},
+    cpu::{Cpu, CpuConfig},
+    date::{Date, DateConfig},
+    json::{ColorRule, Json, JsonConfig},
+    keyboard::{Keyboard, KeyboardBackend, KeyboardConfig, Layouts, Sway, Xkb},
+    load::{Load, LoadConfig},
+    pressure::{Pressure, PressureConfig},
+    pushed::{Pushed, PushedConfig},
+    text::{Text, TextConfig},
+    thresholds::{Band, BandStyle, ThresholdConfig, Thresholds},
+    volume::{AudioBackend, Pactl, Sink, Volume, VolumeConfig},
+    weather::{
+        Condition, Conditions, OpenMeteo, Provider, ProviderConfig, Weather,
+        WeatherConfig, Wttr,
+    },
+};
 
-pub trait Segment {
-    /// The item which has to be updated
-    type Item;
+#[cfg(feature = "dbus")]
+pub use self::{
+    mpris::{Mpris, MprisConfig},
+    notifications::{Notifications, NotificationsConfig},
+    tray::{Tray, TrayConfig},
+};
 
-    /// Create a new instance
-    fn new() -> Self;
+use crate::{color::ColorScheme, source::SysSource};
+use failure::{format_err, Fallible};
+use serde_derive::Deserialize;
+use std::path::PathBuf;
 
-    /// Update the segment
-    fn update(&mut self, _: &mut Self::Item) {}
+/// A list of segments, ordered from left to right
+pub type Segments = Vec<Box<dyn Segment>>;
 
+/// Retrieve the default segments reading from the provided source
+pub fn default_segments(source: &SysSource) -> Fallible<Segments> {
+    Ok(vec![
+        Box::new(Cpu::new(CpuConfig::default(), source.clone())?),
+        Box::new(Date::new(DateConfig::default())?),
+    ])
+}
+
+/// A renderer independent data segment
+pub trait Segment: Send + Sync {
     /// Retrieve the unique identifier
     fn id(&self) -> &str;
 
+    /// Retrieve the name of the segment type
+    fn name(&self) -> &str;
+
+    /// Update the segment and retrieve its current output
+    fn update(&mut self) -> Fallible<SegmentOutput>;
+
     /// Do something on click
-    fn click(&self, _: &mut Self::Item) {}
+    fn click(&mut self, _: Button) {}
+
+    /// Do something on a click of an icon with the provided index
+    fn click_icon(&mut self, button: Button, _: usize) {
+        self.click(button)
+    }
+
+    /// Do something on a click at the absolute screen position, which is
+    /// where menus should be opened
+    fn click_at(&mut self, button: Button, _: (i32, i32)) {
+        self.click(button)
+    }
+
+    /// Do something on a click of an icon at the absolute screen position
+    fn click_icon_at(&mut self, button: Button, index: usize, _: (i32, i32)) {
+        self.click_icon(button, index)
+    }
+
+    /// The minimum width of the segment in pixels
+    fn width(&self) -> u16 {
+        100
+    }
+
+    /// Replace the displayed text, if supported by the segment
+    fn set_text(&mut self, _: &str) -> Fallible<()> {
+        Err(format_err!(
+            "{} segment does not support setting text",
+            self.name()
+        ))
+    }
+
+    /// The lines of the currently opened popup, if any
+    fn popup(&mut self) -> Option<Vec<SegmentOutput>> {
+        None
+    }
+
+    /// Returns true if changed outputs should slide in and texts exceeding
+    /// the width should scroll, which is up to the renderer
+    fn animated(&self) -> bool {
+        false
+    }
+}
+
+/// The mouse buttons a segment can be clicked with
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Button {
+    /// The left mouse button
+    Left,
+
+    /// The middle mouse button
+    Middle,
+
+    /// The right mouse button
+    Right,
+
+    /// The mouse wheel scrolled up
+    ScrollUp,
+
+    /// The mouse wheel scrolled down
+    ScrollDown,
+}
+
+impl Button {
+    /// Convert an X11 button number into a button
+    pub fn from_number(number: u8) -> Option<Self> {
+        match number {
+            1 => Some(Button::Left),
+            2 => Some(Button::Middle),
+            3 => Some(Button::Right),
+            4 => Some(Button::ScrollUp),
+            5 => Some(Button::ScrollDown),
+            _ => None,
+        }
+    }
+
+    /// Retrieve the X11 button number
+    pub fn number(self) -> u8 {
+        match self {
+            Button::Left => 1,
+            Button::Middle => 2,
+            Button::Right => 3,
+            Button::ScrollUp => 4,
+            Button::ScrollDown => 5,
+        }
+    }
+}
+
+/// The structured output of a segment
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SegmentOutput {
+    /// The text to be displayed
+    pub text: String,
+
+    /// The text color, the renderer decides if not set
+    pub color: Option<[f32; 4]>,
+
+    /// The background color, the renderer decides if not set
+    pub background: Option<[f32; 4]>,
+
+    /// Indicates that the segment needs attention
+    pub urgent: bool,
+
+    /// The numeric value the text is based on, like a percentage
+    pub value: Option<f64>,
+
+    /// Indicates that the segment should not be displayed
+    pub hidden: bool,
+
+    /// The icons ordered from left to right, which renderers supporting
+    /// images display instead of the text
+    pub icons: Vec<Icon>,
+
+    /// Display the icons in front of the text instead of replacing it
+    pub leading_icons: bool,
+
+    /// The text split into styled parts, empty if the text is unstyled
+    pub spans: Vec<Span>,
+}
+
+/// A part of the text with its own style
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Span {
+    /// The text of the part
+    pub text: String,
+
+    /// The text color, the output color is used if not set
+    pub color: Option<[f32; 4]>,
+
+    /// Indicates that the text is displayed in bold
+    pub bold: bool,
+}
+
+/// An image displayed within a segment
+#[derive(Clone, Debug, PartialEq)]
+pub enum Icon {
+    /// Decoded RGBA pixels row by row
+    Rgba {
+        /// The width in pixels
+        width: u32,
+
+        /// The height in pixels
+        height: u32,
+
+        /// The pixel data
+        data: Vec<u8>,
+    },
+
+    /// A PNG image file
+    File(PathBuf),
+
+    /// A square sprite of a horizontal PNG sprite sheet
+    Sprite {
+        /// The path of the sheet relative to the assets
+        sheet: PathBuf,
+
+        /// The position of the sprite from the left
+        index: usize,
+    },
+}
+
+/// A block printed as JSON by external producers
+#[derive(Deserialize)]
+struct Block {
+    full_text: String,
+    color: Option<String>,
+    background: Option<String>,
+    #[serde(default)]
+    urgent: bool,
+}
+
+impl SegmentOutput {
+    /// Create a new output with the provided text
+    pub fn new<T>(text: T) -> Self
+    where
+        T: Into<String>,
+    {
+        Self {
+            text: text.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Set the text color
+    pub fn with_color(mut self, color: [f32; 4]) -> Self {
+        self.color = Some(color);
+        self
+    }
+
+    /// Set the background color
+    pub fn with_background(mut self, background: [f32; 4]) -> Self {
+        self.background = Some(background);
+        self
+    }
+
+    /// Mark the output as urgent
+    pub fn with_urgent(mut self, urgent: bool) -> Self {
+        self.urgent = urgent;
+        self
+    }
+
+    /// Set the numeric value
+    pub fn with_value(mut self, value: f64) -> Self {
+        self.value = Some(value);
+        self
+    }
+
+    /// Hide the segment
+    pub fn with_hidden(mut self, hidden: bool) -> Self {
+        self.hidden = hidden;
+        self
+    }
+
+    /// Set the icons
+    pub fn with_icons(mut self, icons: Vec<Icon>) -> Self {
+        self.icons = icons;
+        self
+    }
+
+    /// Set the icons shown in front of the text
+    pub fn with_leading_icons(mut self, icons: Vec<Icon>) -> Self {
+        self.icons = icons;
+        self.leading_icons = true;
+        self
+    }
+
+    /// Set the styled parts of the text
+    pub fn with_spans(mut self, spans: Vec<Span>) -> Self {
+        self.spans = spans;
+        self
+    }
+
+    /// Parse an i3blocks compatible output, which is either an i3bar JSON
+    /// block or the full text followed by the short text and the color as
+    /// single lines
+    pub fn parse(output: &str) -> Fallible<Self> {
+        let output = output.trim();
+        if output.starts_with('{') {
+            let block: Block = serde_json::from_str(output)?;
+            let mut result =
+                Self::new(block.full_text).with_urgent(block.urgent);
+            if let Some(c) =
+                block.color.as_deref().and_then(ColorScheme::from_hex)
+            {
+                result = result.with_color(c);
+            }
+            if let Some(b) =
+                block.background.as_deref().and_then(ColorScheme::from_hex)
+            {
+                result = result.with_background(b);
+            }
+            return Ok(result);
+        }
+
+        let lines: Vec<&str> = output.lines().collect();
+        let result = Self::new(lines.first().cloned().unwrap_or(""));
+        Ok(match lines.get(2).and_then(|c| ColorScheme::from_hex(c)) {
+            Some(c) => result.with_color(c),
+            None => result,
+        })
+    }
+
+    /// Retrieve the text color, which is red for urgent outputs if not set
+    pub fn text_color(&self, default: [f32; 4]) -> [f32; 4] {
+        match (self.color, self.urgent) {
+            (Some(color), _) => color,
+            (None, true) => ColorScheme::red(),
+            (None, false) => default,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_plain() {
+        let output = SegmentOutput::parse("full\nshort\n#ff0000\n").unwrap();
+        assert_eq!(output.text, "full");
+        assert_eq!(output.color, Some([1., 0., 0., 1.]));
+        assert!(!output.urgent);
+
+        let output = SegmentOutput::parse("only text").unwrap();
+        assert_eq!(output.text, "only text");
+        assert_eq!(output.color, None);
+
+        assert_eq!(SegmentOutput::parse("").unwrap().text, "");
+    }
+
+    #[test]
+    fn parse_json() {
+        let output = SegmentOutput::parse(
+            r##"{"full_text":"json","color":"#00ff00","background":"#0000ff","urgent":true}"##,
+        )
+        .unwrap();
+        assert_eq!(output.text, "json");
+        assert_eq!(output.color, Some([0., 1., 0., 1.]));
+        assert_eq!(output.background, Some([0., 0., 1., 1.]));
+        assert!(output.urgent);
+
+        assert!(SegmentOutput::parse(r##"{"color":"#00ff00"}"##).is_err());
+    }
+
+    #[test]
+    fn text_color() {
+        let output = SegmentOutput::new("text");
+        assert_eq!(output.text_color(ColorScheme::cyan()), ColorScheme::cyan());
+        assert_eq!(
+            output
+                .clone()
+                .with_urgent(true)
+                .text_color(ColorScheme::cyan()),
+            ColorScheme::red()
+        );
+        assert_eq!(
+            output
+                .with_urgent(true)
+                .with_color(ColorScheme::green())
+                .text_color(ColorScheme::cyan()),
+            ColorScheme::green()
+        );
+    }
+
+    #[test]
+    fn button_numbers() {
+        for number in 1..=5 {
+            assert_eq!(Button::from_number(number).unwrap().number(), number);
+        }
+        assert_eq!(Button::from_number(0), None);
+        assert_eq!(Button::from_number(6), None);
+    }
 }
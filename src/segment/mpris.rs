@@ -0,0 +1,476 @@
+//! A segment showing the current track of MPRIS media players
+
+use crate::{
+    bus,
+    segment::{Button, Segment, SegmentOutput},
+    template::Template,
+};
+use dbus::{
+    arg::{PropMap, RefArg},
+    blocking::{stdintf::org_freedesktop_dbus::Properties, Connection},
+    message::MatchRule,
+    Message,
+};
+use failure::{format_err, Fallible};
+use log::{debug, warn};
+use serde_derive::Deserialize;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender, TryRecvError},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+use uuid::Uuid;
+
+/// The placeholders available within the format
+const PLACEHOLDERS: &[&str] =
+    &["icon", "track", "artist", "title", "player", "status"];
+
+/// The bus name prefix of all media players
+const PREFIX: &str = "org.mpris.MediaPlayer2.";
+
+/// The object path of all media players
+const PATH: &str = "/org/mpris/MediaPlayer2";
+
+/// The interface to control the playback
+const PLAYER: &str = "org.mpris.MediaPlayer2.Player";
+
+/// The maximum time to wait for a media player to respond
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The volume change per scroll step
+const VOLUME_STEP: f64 = 0.05;
+
+/// The configuration of a media player segment
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct MprisConfig {
+    /// The name to address the segment with
+    #[serde(default = "MprisConfig::default_name")]
+    pub name: String,
+
+    /// The displayed text, where the placeholders `icon` and `status` of the
+    /// playback, `artist`, `title`, the `player` name and `track` for the
+    /// artist and title or the player name are available
+    #[serde(default = "MprisConfig::default_format")]
+    pub format: String,
+
+    /// Only show the player with this bus name suffix, like `spotify`,
+    /// instead of preferring the playing one
+    #[serde(default)]
+    pub player: Option<String>,
+
+    /// The D-Bus address to connect to, the session bus is used if not set
+    #[serde(default)]
+    pub address: Option<String>,
+
+    /// The minimum width of the segment in pixels
+    #[serde(default = "MprisConfig::default_width")]
+    pub width: u16,
+}
+
+impl Default for MprisConfig {
+    fn default() -> Self {
+        Self {
+            name: Self::default_name(),
+            format: Self::default_format(),
+            player: None,
+            address: None,
+            width: Self::default_width(),
+        }
+    }
+}
+
+impl MprisConfig {
+    fn default_name() -> String {
+        "mpris".to_owned()
+    }
+
+    fn default_format() -> String {
+        "{icon} {track}".to_owned()
+    }
+
+    fn default_width() -> u16 {
+        250
+    }
+}
+
+/// A segment watching the media players on D-Bus, which hides if no player
+/// is available
+pub struct Mpris {
+    id: String,
+    config: MprisConfig,
+    output: Arc<Mutex<SegmentOutput>>,
+    clicks: Sender<Button>,
+}
+
+impl Mpris {
+    /// Create a new media player segment and start watching the bus
+    pub fn new(config: MprisConfig) -> Fallible<Self> {
+        let template: Template = config.format.parse()?;
+        template.validate(PLACEHOLDERS)?;
+        let connection = bus::connect(config.address.as_deref())?;
+        let output =
+            Arc::new(Mutex::new(SegmentOutput::default().with_hidden(true)));
+        let (tx, rx) = mpsc::channel();
+
+        let (player, thread_output) = (config.player.clone(), output.clone());
+        thread::spawn(move || {
+            if let Err(e) = Self::watch(
+                &connection,
+                player.as_deref(),
+                &template,
+                &thread_output,
+                &rx,
+            ) {
+                warn!("Unable to watch media players: {}", e);
+                *thread_output.lock().unwrap() =
+                    SegmentOutput::default().with_hidden(true);
+            }
+        });
+
+        Ok(Self {
+            id: Uuid::new_v4().to_string(),
+            config,
+            output,
+            clicks: tx,
+        })
+    }
+
+    /// Update the output whenever a player changes and forward the clicks
+    /// until the segment gets dropped
+    fn watch(
+        connection: &Connection,
+        player: Option<&str>,
+        template: &Template,
+        output: &Mutex<SegmentOutput>,
+        clicks: &Receiver<Button>,
+    ) -> Fallible<()> {
+        let changed = Arc::new(AtomicBool::new(true));
+
+        let properties_changed = changed.clone();
+        connection.add_match(
+            MatchRule::new_signal(
+                "org.freedesktop.DBus.Properties",
+                "PropertiesChanged",
+            )
+            .with_path(PATH),
+            move |_: (), _: &Connection, _: &Message| {
+                properties_changed.store(true, Ordering::SeqCst);
+                true
+            },
+        )?;
+
+        let owner_changed = changed.clone();
+        connection.add_match(
+            MatchRule::new_signal("org.freedesktop.DBus", "NameOwnerChanged"),
+            move |(name,): (String,), _: &Connection, _: &Message| {
+                if name.starts_with(PREFIX) {
+                    owner_changed.store(true, Ordering::SeqCst);
+                }
+                true
+            },
+        )?;
+
+        loop {
+            loop {
+                match clicks.try_recv() {
+                    Ok(button) => {
+                        if let Err(e) =
+                            Self::control(connection, player, button)
+                        {
+                            warn!("Unable to control media player: {}", e)
+                        }
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        debug!("Stopped watching media players");
+                        return Ok(());
+                    }
+                }
+            }
+
+            if changed.swap(false, Ordering::SeqCst) {
+                match Self::output(connection, player, template) {
+                    Ok(o) => *output.lock().unwrap() = o,
+                    Err(e) => warn!("Unable to query media player: {}", e),
+                }
+            }
+            connection.process(Duration::from_millis(100))?;
+        }
+    }
+
+    /// Find the bus name of the player to be displayed
+    fn player(
+        connection: &Connection,
+        player: Option<&str>,
+    ) -> Fallible<Option<String>> {
+        let (names,): (Vec<String>,) = connection
+            .with_proxy(
+                "org.freedesktop.DBus",
+                "/org/freedesktop/DBus",
+                TIMEOUT,
+            )
+            .method_call("org.freedesktop.DBus", "ListNames", ())?;
+
+        // Players with multiple instances append a suffix to their name
+        let mut players: Vec<String> = names
+            .into_iter()
+            .filter(|n| match (n.strip_prefix(PREFIX), player) {
+                (Some(i), Some(p)) => {
+                    i == p || i.starts_with(&format!("{}.", p))
+                }
+                (Some(_), None) => true,
+                (None, _) => false,
+            })
+            .collect();
+        players.sort();
+
+        if player.is_none() {
+            if let Some(playing) = players.iter().find(|p| {
+                Self::status(connection, p)
+                    .map(|s| s == "Playing")
+                    .unwrap_or(false)
+            }) {
+                return Ok(Some(playing.clone()));
+            }
+        }
+        Ok(players.into_iter().next())
+    }
+
+    /// Retrieve the playback status of a player
+    fn status(connection: &Connection, player: &str) -> Fallible<String> {
+        Ok(connection
+            .with_proxy(player, PATH, TIMEOUT)
+            .get(PLAYER, "PlaybackStatus")?)
+    }
+
+    /// Retrieve the current track and play state of the player
+    fn output(
+        connection: &Connection,
+        player: Option<&str>,
+        template: &Template,
+    ) -> Fallible<SegmentOutput> {
+        let player = match Self::player(connection, player)? {
+            Some(p) => p,
+            None => return Ok(SegmentOutput::default().with_hidden(true)),
+        };
+        let status = Self::status(connection, &player)?;
+        let icon = match status.as_str() {
+            "Playing" => "\u{f04b}",
+            "Paused" => "\u{f04c}",
+            _ => "\u{f04d}",
+        };
+
+        let metadata: PropMap = connection
+            .with_proxy(player.as_str(), PATH, TIMEOUT)
+            .get(PLAYER, "Metadata")?;
+        let artist = metadata
+            .get("xesam:artist")
+            .and_then(|a| a.0.as_iter())
+            .map(|a| {
+                a.filter_map(|x| x.as_str()).collect::<Vec<_>>().join(", ")
+            })
+            .unwrap_or_default();
+        let title = metadata.get("xesam:title").and_then(|t| t.0.as_str());
+
+        let name = player.trim_start_matches(PREFIX);
+        let track = match title {
+            Some(title) if !artist.is_empty() => {
+                format!("{} - {}", artist, title)
+            }
+            Some(title) => title.to_owned(),
+            None => name.to_owned(),
+        };
+        Ok(template.output(&[
+            ("icon", icon.into()),
+            ("track", track.into()),
+            ("artist", artist.into()),
+            ("title", title.unwrap_or_default().into()),
+            ("player", name.into()),
+            ("status", status.into()),
+        ]))
+    }
+
+    /// Control the player depending on the clicked button
+    fn control(
+        connection: &Connection,
+        player: Option<&str>,
+        button: Button,
+    ) -> Fallible<()> {
+        let player = Self::player(connection, player)?
+            .ok_or_else(|| format_err!("no media player available"))?;
+        let proxy = connection.with_proxy(player, PATH, TIMEOUT);
+
+        let method = match button {
+            Button::Left => "PlayPause",
+            Button::Middle => "Previous",
+            Button::Right => "Next",
+            Button::ScrollUp | Button::ScrollDown => {
+                let step = if button == Button::ScrollUp {
+                    VOLUME_STEP
+                } else {
+                    -VOLUME_STEP
+                };
+                let volume: f64 = proxy.get(PLAYER, "Volume")?;
+                proxy.set(PLAYER, "Volume", (volume + step).clamp(0., 1.))?;
+                return Ok(());
+            }
+        };
+        proxy.method_call::<(), _, _, _>(PLAYER, method, ())?;
+        Ok(())
+    }
+}
+
+impl Segment for Mpris {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn update(&mut self) -> Fallible<SegmentOutput> {
+        Ok(self.output.lock().unwrap().clone())
+    }
+
+    fn click(&mut self, button: Button) {
+        if self.clicks.send(button).is_err() {
+            warn!("Media players are not watched any more");
+        }
+    }
+
+    fn width(&self) -> u16 {
+        self.config.width
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{
+        dbus::Bus,
+        mpris::{Player, PlayerState},
+        wait::{wait_for, wait_for_text, wait_until},
+    };
+
+    fn mpris(bus: &Bus, player: Option<&str>) -> Mpris {
+        Mpris::new(MprisConfig {
+            player: player.map(str::to_owned),
+            address: Some(bus.address().to_owned()),
+            ..MprisConfig::default()
+        })
+        .unwrap()
+    }
+
+    fn state(status: &str, title: &str) -> PlayerState {
+        PlayerState {
+            status: status.into(),
+            artist: "Artist".into(),
+            title: title.into(),
+            volume: 0.5,
+            ..PlayerState::default()
+        }
+    }
+
+    #[test]
+    fn appear_and_vanish() {
+        let bus = Bus::start();
+        let mut mpris = mpris(&bus, None);
+        assert_eq!(mpris.name(), "mpris");
+        assert!(mpris.update().unwrap().hidden);
+
+        let player = Player::start(&bus, "stub", state("Playing", "Title"));
+        wait_for_text(&mut mpris, "\u{f04b} Artist - Title");
+
+        player.change(|s| {
+            s.status = "Paused".into();
+            s.artist.clear();
+        });
+        wait_for_text(&mut mpris, "\u{f04c} Title");
+
+        drop(player);
+        wait_for(&mut mpris, |o| o.hidden);
+    }
+
+    #[test]
+    fn prefer_playing() {
+        let bus = Bus::start();
+        let _a = Player::start(&bus, "a", state("Paused", "A"));
+        let b = Player::start(&bus, "b", state("Stopped", "B"));
+
+        let mut mpris_any = mpris(&bus, None);
+        wait_for_text(&mut mpris_any, "\u{f04c} Artist - A");
+
+        b.change(|s| s.status = "Playing".into());
+        wait_for_text(&mut mpris_any, "\u{f04b} Artist - B");
+
+        let _c = Player::start(&bus, "a.instance2", state("Playing", "C"));
+        let mut mpris_a = mpris(&bus, Some("a"));
+        wait_for_text(&mut mpris_a, "\u{f04c} Artist - A");
+    }
+
+    #[test]
+    fn control() {
+        let bus = Bus::start();
+        let player = Player::start(&bus, "stub", state("Paused", "Title"));
+        let mut mpris = mpris(&bus, None);
+        wait_for_text(&mut mpris, "\u{f04c} Artist - Title");
+
+        mpris.click(Button::Left);
+        wait_for_text(&mut mpris, "\u{f04b} Artist - Title");
+
+        for button in &[Button::Right, Button::Middle, Button::ScrollUp] {
+            mpris.click(*button);
+        }
+        mpris.click(Button::ScrollUp);
+        mpris.click(Button::ScrollDown);
+
+        wait_until(|| player.state().calls.len() >= 6);
+        let state = player.state();
+        assert_eq!(
+            state.calls,
+            vec![
+                "PlayPause",
+                "Next",
+                "Previous",
+                "Volume",
+                "Volume",
+                "Volume"
+            ]
+        );
+        assert!((state.volume - 0.55).abs() < 1e-9);
+    }
+
+    #[test]
+    fn format() {
+        let bus = Bus::start();
+        let _player = Player::start(&bus, "stub", state("Paused", "Title"));
+        let mut mpris = Mpris::new(MprisConfig {
+            format: "{player}: {title} ({status})".into(),
+            address: Some(bus.address().to_owned()),
+            ..MprisConfig::default()
+        })
+        .unwrap();
+        wait_for_text(&mut mpris, "stub: Title (Paused)");
+
+        assert!(Mpris::new(MprisConfig {
+            format: "{album}".into(),
+            address: Some(bus.address().to_owned()),
+            ..MprisConfig::default()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn invalid_address() {
+        assert!(Mpris::new(MprisConfig {
+            address: Some("unix:path=/does/not/exist".into()),
+            ..MprisConfig::default()
+        })
+        .is_err());
+    }
+}
@@ -0,0 +1,734 @@
+//! A notification daemon displaying the notifications within the bar
+
+use crate::{
+    bus,
+    color::ColorScheme,
+    segment::{Button, Segment, SegmentOutput},
+    template::Template,
+};
+use chrono::{DateTime, Local};
+use dbus::{
+    arg::{PropMap, RefArg},
+    blocking::{stdintf::org_freedesktop_dbus::RequestNameReply, Connection},
+    channel::{MatchingReceiver, Sender},
+    message::MatchRule,
+    strings::ErrorName,
+    Message,
+};
+use failure::{err_msg, format_err, Fallible};
+use log::{debug, warn};
+use serde_derive::Deserialize;
+use std::{
+    collections::VecDeque,
+    ffi::CString,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+use uuid::Uuid;
+
+/// The placeholders available within the format
+const PLACEHOLDERS: &[&str] =
+    &["text", "summary", "body", "app", "count", "more"];
+
+/// The well known bus name and interface of notification daemons
+const NAME: &str = "org.freedesktop.Notifications";
+
+/// The object path of notification daemons
+const PATH: &str = "/org/freedesktop/Notifications";
+
+/// The reason for closing a notification after its timeout
+const REASON_EXPIRED: u32 = 1;
+
+/// The reason for closing a notification dismissed by the user
+const REASON_DISMISSED: u32 = 2;
+
+/// The reason for closing a notification via `CloseNotification`
+const REASON_CLOSED: u32 = 3;
+
+/// The configuration of a notifications segment
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct NotificationsConfig {
+    /// The name to address the segment with
+    #[serde(default = "NotificationsConfig::default_name")]
+    pub name: String,
+
+    /// The displayed text of the latest notification, where the
+    /// placeholders `text` for the summary and the body, `summary`, `body`,
+    /// `app`, the `count` of active notifications and the `more` hidden
+    /// ones are available, where only `count` is set without notifications
+    #[serde(default = "NotificationsConfig::default_format")]
+    pub format: String,
+
+    /// The D-Bus address to connect to, the session bus is used if not set
+    #[serde(default)]
+    pub address: Option<String>,
+
+    /// The time in seconds after which notifications expire if not
+    /// requested otherwise by the sender
+    #[serde(default = "NotificationsConfig::default_timeout")]
+    pub timeout: u64,
+
+    /// The maximum amount of notifications kept in the history
+    #[serde(default = "NotificationsConfig::default_history")]
+    pub history: usize,
+
+    /// The minimum width of the segment in pixels
+    #[serde(default = "NotificationsConfig::default_width")]
+    pub width: u16,
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        Self {
+            name: Self::default_name(),
+            format: Self::default_format(),
+            address: None,
+            timeout: Self::default_timeout(),
+            history: Self::default_history(),
+            width: Self::default_width(),
+        }
+    }
+}
+
+impl NotificationsConfig {
+    fn default_name() -> String {
+        "notifications".to_owned()
+    }
+
+    fn default_format() -> String {
+        "\u{f0f3}{?text} {text}{/}{?more} (+{more}){/}".to_owned()
+    }
+
+    fn default_timeout() -> u64 {
+        5
+    }
+
+    fn default_history() -> usize {
+        20
+    }
+
+    fn default_width() -> u16 {
+        300
+    }
+}
+
+/// The urgency level of a notification
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Urgency {
+    Low,
+    Normal,
+    Critical,
+}
+
+impl Urgency {
+    /// Retrieve the urgency from the hints of a notification
+    fn from_hints(hints: &PropMap) -> Self {
+        match hints.get("urgency").and_then(|u| u.0.as_u64()) {
+            Some(0) => Urgency::Low,
+            Some(2) => Urgency::Critical,
+            _ => Urgency::Normal,
+        }
+    }
+}
+
+/// A single received notification
+#[derive(Clone, Debug)]
+struct Notification {
+    id: u32,
+    app: String,
+    summary: String,
+    body: String,
+    urgency: Urgency,
+    time: DateTime<Local>,
+    expires: Option<Instant>,
+}
+
+impl Notification {
+    /// The body within a single line
+    fn body(&self) -> String {
+        self.body.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    /// The displayed text consisting of the summary and the body
+    fn text(&self) -> String {
+        let body = self.body();
+        if body.is_empty() {
+            self.summary.clone()
+        } else {
+            format!("{}: {}", self.summary, body)
+        }
+    }
+
+    /// Apply the urgency based colors to an output
+    fn style(&self, output: SegmentOutput) -> SegmentOutput {
+        match self.urgency {
+            Urgency::Low => output.with_color(ColorScheme::cyan()),
+            Urgency::Normal => output,
+            Urgency::Critical => {
+                output.with_color(ColorScheme::red()).with_urgent(true)
+            }
+        }
+    }
+}
+
+/// The active notifications and the history shared with the daemon
+#[derive(Debug, Default)]
+struct Store {
+    active: Vec<Notification>,
+    history: VecDeque<Notification>,
+    history_size: usize,
+    closed: Vec<(u32, u32)>,
+    last_id: u32,
+    popup: bool,
+}
+
+impl Store {
+    /// Add a notification or replace an existing one, returns its id
+    fn notify(&mut self, mut notification: Notification, replaces: u32) -> u32 {
+        let replaced = self.active.iter().position(|n| n.id == replaces);
+        if replaces != 0 && replaced.is_some() {
+            notification.id = replaces;
+        } else {
+            self.last_id += 1;
+            notification.id = self.last_id;
+        }
+
+        let id = notification.id;
+        self.history.retain(|n| n.id != id);
+        self.history.push_front(notification.clone());
+        self.history.truncate(self.history_size);
+
+        match replaced {
+            Some(i) if replaces != 0 => self.active[i] = notification,
+            _ => self.active.push(notification),
+        }
+        id
+    }
+
+    /// Close an active notification, returns false if it does not exist
+    fn close(&mut self, id: u32, reason: u32) -> bool {
+        let count = self.active.len();
+        self.active.retain(|n| n.id != id);
+        if self.active.len() == count {
+            return false;
+        }
+        self.closed.push((id, reason));
+        true
+    }
+
+    /// Close all notifications whose timeout exceeded
+    fn expire(&mut self, now: Instant) {
+        let expired: Vec<u32> = self
+            .active
+            .iter()
+            .filter(|n| n.expires.map(|e| e <= now).unwrap_or(false))
+            .map(|n| n.id)
+            .collect();
+        for id in expired {
+            self.close(id, REASON_EXPIRED);
+        }
+    }
+}
+
+/// A segment implementing the desktop notifications specification, which
+/// displays the latest notification and keeps a history of them
+pub struct Notifications {
+    id: String,
+    config: NotificationsConfig,
+    template: Template,
+    store: Arc<Mutex<Store>>,
+    stopped: Arc<AtomicBool>,
+    daemon: Option<JoinHandle<()>>,
+}
+
+impl Notifications {
+    /// Create a new notifications segment and start serving on the bus
+    pub fn new(config: NotificationsConfig) -> Fallible<Self> {
+        let template: Template = config.format.parse()?;
+        template.validate(PLACEHOLDERS)?;
+        let connection = bus::connect(config.address.as_deref())?;
+
+        // Allow replacing ourself, which is necessary on reload
+        let reply = connection.request_name(NAME, true, true, true)?;
+        if reply != RequestNameReply::PrimaryOwner
+            && reply != RequestNameReply::AlreadyOwner
+        {
+            return Err(format_err!(
+                "another notification daemon is already running"
+            ));
+        }
+
+        let store = Arc::new(Mutex::new(Store {
+            history_size: config.history,
+            ..Store::default()
+        }));
+        let stopped = Arc::new(AtomicBool::new(false));
+
+        let timeout = Duration::from_secs(config.timeout);
+        let (daemon_store, daemon_stopped) = (store.clone(), stopped.clone());
+        let daemon = thread::spawn(move || {
+            if let Err(e) = Self::serve(
+                &connection,
+                timeout,
+                &daemon_store,
+                &daemon_stopped,
+            ) {
+                warn!("Unable to serve notifications: {}", e);
+            }
+            debug!("Stopped serving notifications");
+        });
+
+        Ok(Self {
+            id: Uuid::new_v4().to_string(),
+            config,
+            template,
+            store,
+            stopped,
+            daemon: Some(daemon),
+        })
+    }
+
+    /// Answer method calls and emit the signals of closed notifications
+    /// until the segment gets dropped
+    fn serve(
+        connection: &Connection,
+        timeout: Duration,
+        store: &Arc<Mutex<Store>>,
+        stopped: &AtomicBool,
+    ) -> Fallible<()> {
+        let calls = store.clone();
+        connection.start_receive(
+            MatchRule::new_method_call().with_interface(NAME),
+            Box::new(move |msg, connection| {
+                let reply =
+                    Self::reply(&msg, timeout, &calls).unwrap_or_else(|e| {
+                        msg.error(
+                            &ErrorName::from(
+                                "org.freedesktop.DBus.Error.InvalidArgs",
+                            ),
+                            &CString::new(e.to_string()).unwrap_or_default(),
+                        )
+                    });
+                if connection.send(reply).is_err() {
+                    warn!("Unable to reply to notification request");
+                }
+                true
+            }),
+        );
+
+        while !stopped.load(Ordering::SeqCst) {
+            let closed: Vec<(u32, u32)> = {
+                let mut store = store.lock().unwrap();
+                store.expire(Instant::now());
+                store.closed.drain(..).collect()
+            };
+            for (id, reason) in closed {
+                let signal =
+                    Message::new_signal(PATH, NAME, "NotificationClosed")
+                        .map_err(err_msg)?
+                        .append2(id, reason);
+                if connection.send(signal).is_err() {
+                    warn!("Unable to signal closed notification {}", id);
+                }
+            }
+            connection.process(Duration::from_millis(100))?;
+        }
+        Ok(())
+    }
+
+    /// Create the reply to a method call of the notifications interface
+    fn reply(
+        msg: &Message,
+        timeout: Duration,
+        store: &Mutex<Store>,
+    ) -> Fallible<Message> {
+        let member = msg.member().map(|m| m.to_string()).unwrap_or_default();
+        Ok(match member.as_str() {
+            "Notify" => {
+                let mut args = msg.iter_init();
+                let app: String = args.read()?;
+                let replaces: u32 = args.read()?;
+                let _icon: String = args.read()?;
+                let summary: String = args.read()?;
+                let body: String = args.read()?;
+                let _actions: Vec<String> = args.read()?;
+                let hints: PropMap = args.read()?;
+                let expire_timeout: i32 = args.read()?;
+
+                // Critical notifications do not expire by default
+                let urgency = Urgency::from_hints(&hints);
+                let expires = match expire_timeout {
+                    0 => None,
+                    t if t > 0 => Some(Duration::from_millis(t as u64)),
+                    _ if urgency == Urgency::Critical => None,
+                    _ => Some(timeout),
+                };
+
+                let id = store.lock().unwrap().notify(
+                    Notification {
+                        id: 0,
+                        app,
+                        summary,
+                        body,
+                        urgency,
+                        time: Local::now(),
+                        expires: expires.map(|e| Instant::now() + e),
+                    },
+                    replaces,
+                );
+                msg.method_return().append1(id)
+            }
+            "CloseNotification" => {
+                let id: u32 = msg.read1()?;
+                store.lock().unwrap().close(id, REASON_CLOSED);
+                msg.method_return()
+            }
+            "GetCapabilities" => {
+                msg.method_return().append1(vec!["body", "persistence"])
+            }
+            "GetServerInformation" => msg
+                .method_return()
+                .append2("unibar", "unibar")
+                .append2(env!("CARGO_PKG_VERSION"), "1.2"),
+            _ => return Err(format_err!("unknown method '{}'", member)),
+        })
+    }
+}
+
+impl Segment for Notifications {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn update(&mut self) -> Fallible<SegmentOutput> {
+        let store = self.store.lock().unwrap();
+        let count = store.active.len() as u64;
+        Ok(match store.active.last() {
+            Some(n) => n.style(self.template.output(&[
+                ("text", n.text().into()),
+                ("summary", n.summary.as_str().into()),
+                ("body", n.body().into()),
+                ("app", n.app.as_str().into()),
+                ("count", count.into()),
+                ("more", (count - 1).into()),
+            ])),
+            None => self
+                .template
+                .output(&[("count", count.into())])
+                .with_color(ColorScheme::selection()),
+        })
+    }
+
+    fn click(&mut self, button: Button) {
+        let mut store = self.store.lock().unwrap();
+        match button {
+            Button::Left => store.popup = !store.popup,
+            Button::Middle => {
+                let ids: Vec<u32> = store.active.iter().map(|n| n.id).collect();
+                for id in ids {
+                    store.close(id, REASON_DISMISSED);
+                }
+            }
+            Button::Right => {
+                if let Some(id) = store.active.last().map(|n| n.id) {
+                    store.close(id, REASON_DISMISSED);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn width(&self) -> u16 {
+        self.config.width
+    }
+
+    fn popup(&mut self) -> Option<Vec<SegmentOutput>> {
+        let store = self.store.lock().unwrap();
+        if !store.popup {
+            return None;
+        }
+        if store.history.is_empty() {
+            return Some(vec![SegmentOutput::new("No notifications")]);
+        }
+        Some(
+            store
+                .history
+                .iter()
+                .map(|n| {
+                    n.style(SegmentOutput::new(if n.app.is_empty() {
+                        format!("{} {}", n.time.format("%H:%M"), n.text())
+                    } else {
+                        format!(
+                            "{} {} - {}",
+                            n.time.format("%H:%M"),
+                            n.app,
+                            n.text()
+                        )
+                    }))
+                })
+                .collect(),
+        )
+    }
+
+    fn animated(&self) -> bool {
+        true
+    }
+}
+
+impl Drop for Notifications {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        if let Some(daemon) = self.daemon.take() {
+            daemon.join().ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{dbus::Bus, wait::wait_until};
+    use dbus::arg::Variant;
+
+    type Closed = Arc<Mutex<Vec<(u32, u32)>>>;
+
+    fn notifications(bus: &Bus) -> Notifications {
+        Notifications::new(NotificationsConfig {
+            address: Some(bus.address().to_owned()),
+            ..NotificationsConfig::default()
+        })
+        .unwrap()
+    }
+
+    /// Connect to the bus and record all closed notifications
+    fn client(bus: &Bus) -> (Connection, Closed) {
+        let connection = bus::connect(Some(bus.address())).unwrap();
+        let closed = Arc::new(Mutex::new(vec![]));
+        let signals = closed.clone();
+        connection
+            .add_match(
+                MatchRule::new_signal(NAME, "NotificationClosed"),
+                move |c: (u32, u32), _: &Connection, _: &Message| {
+                    signals.lock().unwrap().push(c);
+                    true
+                },
+            )
+            .unwrap();
+        (connection, closed)
+    }
+
+    fn notify(
+        connection: &Connection,
+        replaces: u32,
+        summary: &str,
+        urgency: u8,
+        timeout: i32,
+    ) -> u32 {
+        let mut hints = PropMap::new();
+        hints.insert("urgency".into(), Variant(Box::new(urgency)));
+        let (id,): (u32,) = connection
+            .with_proxy(NAME, PATH, Duration::from_secs(5))
+            .method_call(
+                NAME,
+                "Notify",
+                (
+                    "app",
+                    replaces,
+                    "",
+                    summary,
+                    "some\nbody",
+                    Vec::<String>::new(),
+                    hints,
+                    timeout,
+                ),
+            )
+            .unwrap();
+        id
+    }
+
+    /// Process incoming signals until the closed notification arrived
+    fn wait_for_closed(
+        connection: &Connection,
+        closed: &Closed,
+        c: (u32, u32),
+    ) {
+        wait_until(|| {
+            connection.process(Duration::from_millis(10)).unwrap();
+            closed.lock().unwrap().contains(&c)
+        });
+    }
+
+    #[test]
+    fn notify_and_dismiss() {
+        let bus = Bus::start();
+        let mut segment = notifications(&bus);
+        let (connection, closed) = client(&bus);
+        assert_eq!(segment.update().unwrap().text, "\u{f0f3}");
+
+        let first = notify(&connection, 0, "first", 1, 0);
+        let output = segment.update().unwrap();
+        assert_eq!(output.text, "\u{f0f3} first: some body");
+        assert_eq!(output.color, None);
+
+        let second = notify(&connection, 0, "second", 1, 0);
+        assert_ne!(first, second);
+        assert_eq!(
+            segment.update().unwrap().text,
+            "\u{f0f3} second: some body (+1)"
+        );
+
+        segment.click(Button::Right);
+        wait_for_closed(&connection, &closed, (second, REASON_DISMISSED));
+        assert_eq!(segment.update().unwrap().text, "\u{f0f3} first: some body");
+
+        connection
+            .with_proxy(NAME, PATH, Duration::from_secs(5))
+            .method_call::<(), _, _, _>(NAME, "CloseNotification", (first,))
+            .unwrap();
+        wait_for_closed(&connection, &closed, (first, REASON_CLOSED));
+        assert_eq!(segment.update().unwrap().text, "\u{f0f3}");
+    }
+
+    #[test]
+    fn format() {
+        let bus = Bus::start();
+        let mut segment = Notifications::new(NotificationsConfig {
+            format: "{app}: {summary} [{count}]".into(),
+            address: Some(bus.address().to_owned()),
+            ..NotificationsConfig::default()
+        })
+        .unwrap();
+        let (connection, _) = client(&bus);
+        assert_eq!(segment.update().unwrap().text, ":  [0]");
+
+        notify(&connection, 0, "first", 1, 0);
+        notify(&connection, 0, "second", 1, 0);
+        assert_eq!(segment.update().unwrap().text, "app: second [2]");
+        drop(segment);
+
+        assert!(Notifications::new(NotificationsConfig {
+            format: "{icon}".into(),
+            address: Some(bus.address().to_owned()),
+            ..NotificationsConfig::default()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn replace() {
+        let bus = Bus::start();
+        let mut segment = notifications(&bus);
+        let (connection, _) = client(&bus);
+
+        let id = notify(&connection, 0, "progress 1", 1, 0);
+        assert_eq!(notify(&connection, id, "progress 2", 1, 0), id);
+        assert_eq!(
+            segment.update().unwrap().text,
+            "\u{f0f3} progress 2: some body"
+        );
+
+        segment.click(Button::Left);
+        assert_eq!(segment.popup().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn expiry_and_urgency() {
+        let bus = Bus::start();
+        let mut segment = notifications(&bus);
+        let (connection, closed) = client(&bus);
+
+        let critical = notify(&connection, 0, "critical", 2, -1);
+        let output = segment.update().unwrap();
+        assert!(output.urgent);
+        assert_eq!(output.color, Some(ColorScheme::red()));
+
+        let low = notify(&connection, 0, "low", 0, 100);
+        assert_eq!(segment.update().unwrap().color, Some(ColorScheme::cyan()));
+        wait_for_closed(&connection, &closed, (low, REASON_EXPIRED));
+
+        // Critical notifications only expire if requested explicitly
+        assert!(segment.update().unwrap().urgent);
+        segment.click(Button::Middle);
+        wait_for_closed(&connection, &closed, (critical, REASON_DISMISSED));
+    }
+
+    #[test]
+    fn history_popup() {
+        let bus = Bus::start();
+        let mut segment = Notifications::new(NotificationsConfig {
+            address: Some(bus.address().to_owned()),
+            history: 2,
+            ..NotificationsConfig::default()
+        })
+        .unwrap();
+        let (connection, _) = client(&bus);
+        assert!(segment.popup().is_none());
+
+        segment.click(Button::Left);
+        assert_eq!(segment.popup().unwrap()[0].text, "No notifications");
+
+        for summary in &["a", "b", "c"] {
+            notify(&connection, 0, summary, 1, 0);
+        }
+        segment.click(Button::Middle);
+        let popup = segment.popup().unwrap();
+        assert_eq!(popup.len(), 2);
+        assert!(popup[0].text.ends_with("app - c: some body"));
+        assert!(popup[1].text.ends_with("app - b: some body"));
+
+        segment.click(Button::Left);
+        assert!(segment.popup().is_none());
+    }
+
+    #[test]
+    fn server_information() {
+        let bus = Bus::start();
+        let _segment = notifications(&bus);
+        let (connection, _) = client(&bus);
+        let proxy = connection.with_proxy(NAME, PATH, Duration::from_secs(5));
+
+        let (name, _, _, version): (String, String, String, String) =
+            proxy.method_call(NAME, "GetServerInformation", ()).unwrap();
+        assert_eq!(name, "unibar");
+        assert_eq!(version, "1.2");
+
+        let (capabilities,): (Vec<String>,) =
+            proxy.method_call(NAME, "GetCapabilities", ()).unwrap();
+        assert!(capabilities.contains(&"body".to_owned()));
+    }
+
+    #[test]
+    fn daemon_running() {
+        let bus = Bus::start();
+        let other = bus::connect(Some(bus.address())).unwrap();
+        other.request_name(NAME, false, false, true).unwrap();
+        assert!(Notifications::new(NotificationsConfig {
+            address: Some(bus.address().to_owned()),
+            ..NotificationsConfig::default()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn reload() {
+        let bus = Bus::start();
+        let first = notifications(&bus);
+        let mut second = notifications(&bus);
+        drop(first);
+
+        let (connection, _) = client(&bus);
+        notify(&connection, 0, "still served", 1, 0);
+        assert_eq!(
+            second.update().unwrap().text,
+            "\u{f0f3} still served: some body"
+        );
+    }
+}
@@ -0,0 +1,291 @@
+//! A segment showing the Pressure Stall Information of the kernel
+
+use crate::{
+    segment::{Segment, SegmentOutput},
+    source::SysSource,
+    template::{Template, Value},
+};
+use failure::{format_err, Fallible};
+use serde_derive::Deserialize;
+use std::io::ErrorKind;
+use uuid::Uuid;
+
+/// The resources the kernel reports pressure for
+const RESOURCES: &[&str] = &["cpu", "memory", "io"];
+
+/// The configuration of a pressure segment
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct PressureConfig {
+    /// The name to address the segment with
+    #[serde(default = "PressureConfig::default_name")]
+    pub name: String,
+
+    /// The displayed text, where the `avg10` percentages are available as
+    /// placeholders like `cpu_some` and `io_full`
+    #[serde(default = "PressureConfig::default_format")]
+    pub format: String,
+
+    /// The `some` percentage from which on the output gets urgent
+    #[serde(default = "PressureConfig::default_some")]
+    pub some: f64,
+
+    /// The `full` percentage from which on the output gets urgent
+    #[serde(default = "PressureConfig::default_full")]
+    pub full: f64,
+
+    /// The minimum width of the segment in pixels
+    #[serde(default = "PressureConfig::default_width")]
+    pub width: u16,
+}
+
+impl Default for PressureConfig {
+    fn default() -> Self {
+        Self {
+            name: Self::default_name(),
+            format: Self::default_format(),
+            some: Self::default_some(),
+            full: Self::default_full(),
+            width: Self::default_width(),
+        }
+    }
+}
+
+impl PressureConfig {
+    fn default_name() -> String {
+        "pressure".to_owned()
+    }
+
+    fn default_format() -> String {
+        "CPU {cpu_some:.0}% MEM {memory_some:.0}/{memory_full:.0}% \
+         IO {io_some:.0}/{io_full:.0}%"
+            .to_owned()
+    }
+
+    fn default_some() -> f64 {
+        40.
+    }
+
+    fn default_full() -> f64 {
+        10.
+    }
+
+    fn default_width() -> u16 {
+        250
+    }
+}
+
+/// The stall percentages of a resource over the last ten seconds
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Stall {
+    some: f64,
+    full: f64,
+}
+
+/// The pressure of CPU, memory and IO, which hides on kernels without PSI
+pub struct Pressure {
+    id: String,
+    config: PressureConfig,
+    template: Template,
+    source: SysSource,
+}
+
+impl Pressure {
+    /// Create a new instance which reads from the provided source
+    pub fn new(config: PressureConfig, source: SysSource) -> Fallible<Self> {
+        let template: Template = config.format.parse()?;
+        let placeholders: Vec<String> = RESOURCES
+            .iter()
+            .flat_map(|r| vec![format!("{}_some", r), format!("{}_full", r)])
+            .collect();
+        template.validate(
+            &placeholders.iter().map(String::as_str).collect::<Vec<_>>(),
+        )?;
+        Ok(Self {
+            id: Uuid::new_v4().to_string(),
+            config,
+            template,
+            source,
+        })
+    }
+
+    /// Read the stall of a resource, which is `None` without PSI support
+    fn stall(&self, resource: &str) -> Fallible<Option<Stall>> {
+        let path = format!("/proc/pressure/{}", resource);
+        let content = match self.source.read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) => {
+                return match e.downcast_ref::<std::io::Error>() {
+                    Some(e)
+                        if e.kind() == ErrorKind::NotFound
+                            || e.raw_os_error() == Some(libc::EOPNOTSUPP) =>
+                    {
+                        Ok(None)
+                    }
+                    _ => Err(e),
+                }
+            }
+        };
+        Ok(Some(Self::parse(&content).map_err(|e| {
+            format_err!("invalid content of {}: {}", path, e)
+        })?))
+    }
+
+    /// Parse the `avg10` values, where `full` is zero if not reported
+    fn parse(content: &str) -> Fallible<Stall> {
+        let mut stall = Stall::default();
+        let mut found = false;
+        for line in content.lines() {
+            let mut words = line.split_whitespace();
+            let kind = words.next();
+            let avg10 = words
+                .find(|w| w.starts_with("avg10="))
+                .map(|w| w.trim_start_matches("avg10=").parse::<f64>())
+                .ok_or_else(|| format_err!("no avg10 in '{}'", line))??;
+            match kind {
+                Some("some") => {
+                    stall.some = avg10;
+                    found = true;
+                }
+                Some("full") => stall.full = avg10,
+                _ => return Err(format_err!("unknown line '{}'", line)),
+            }
+        }
+        if !found {
+            return Err(format_err!("no 'some' line found"));
+        }
+        Ok(stall)
+    }
+}
+
+impl Segment for Pressure {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn update(&mut self) -> Fallible<SegmentOutput> {
+        let mut values: Vec<(String, f64)> = vec![];
+        let mut urgent = false;
+        let mut highest: f64 = 0.;
+        for resource in RESOURCES {
+            let stall = match self.stall(resource)? {
+                Some(stall) => stall,
+                None => return Ok(SegmentOutput::default().with_hidden(true)),
+            };
+            urgent |= stall.some >= self.config.some
+                || stall.full >= self.config.full;
+            highest = highest.max(stall.some);
+            values.push((format!("{}_some", resource), stall.some));
+            values.push((format!("{}_full", resource), stall.full));
+        }
+
+        let values: Vec<(&str, Value)> = values
+            .iter()
+            .map(|(name, value)| (name.as_str(), Value::from(*value)))
+            .collect();
+        Ok(self
+            .template
+            .output(&values)
+            .with_value(highest)
+            .with_urgent(urgent))
+    }
+
+    fn width(&self) -> u16 {
+        self.config.width
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::sys::Root;
+
+    fn psi(some: f64, full: Option<f64>) -> String {
+        let mut content = format!(
+            "some avg10={:.2} avg60=1.00 avg300=0.50 total=12345\n",
+            some
+        );
+        if let Some(full) = full {
+            content.push_str(&format!(
+                "full avg10={:.2} avg60=0.00 avg300=0.00 total=42\n",
+                full
+            ));
+        }
+        content
+    }
+
+    fn fixture() -> Root {
+        let root = Root::new();
+        root.write("/proc/pressure/cpu", &psi(12.34, None))
+            .write("/proc/pressure/memory", &psi(1.5, Some(0.25)))
+            .write("/proc/pressure/io", &psi(3., Some(1.)));
+        root
+    }
+
+    #[test]
+    fn default_format() {
+        let root = fixture();
+        let mut pressure =
+            Pressure::new(PressureConfig::default(), root.source()).unwrap();
+        let output = pressure.update().unwrap();
+        assert_eq!(output.text, "CPU 12% MEM 2/0% IO 3/1%");
+        assert_eq!(output.value, Some(12.34));
+        assert!(!output.urgent);
+        assert!(!output.hidden);
+    }
+
+    #[test]
+    fn thresholds() {
+        let root = fixture();
+        let mut pressure = Pressure::new(
+            PressureConfig {
+                format: "{cpu_some:.2} {cpu_full:.2}".into(),
+                ..PressureConfig::default()
+            },
+            root.source(),
+        )
+        .unwrap();
+        assert_eq!(pressure.update().unwrap().text, "12.34 0.00");
+
+        root.write("/proc/pressure/io", &psi(3., Some(10.)));
+        assert!(pressure.update().unwrap().urgent);
+
+        root.write("/proc/pressure/io", &psi(3., Some(1.)))
+            .write("/proc/pressure/cpu", &psi(40., None));
+        let output = pressure.update().unwrap();
+        assert!(output.urgent);
+        assert_eq!(output.value, Some(40.));
+    }
+
+    #[test]
+    fn unsupported() {
+        let root = Root::new();
+        let mut pressure =
+            Pressure::new(PressureConfig::default(), root.source()).unwrap();
+        assert!(pressure.update().unwrap().hidden);
+
+        root.write("/proc/pressure/cpu", &psi(1., None));
+        assert!(pressure.update().unwrap().hidden);
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(Pressure::new(
+            PressureConfig {
+                format: "{gpu_some}".into(),
+                ..PressureConfig::default()
+            },
+            SysSource::default(),
+        )
+        .is_err());
+
+        assert!(Pressure::parse("").is_err());
+        assert!(Pressure::parse("some avg10=x\n").is_err());
+        assert!(Pressure::parse("some avg60=1.00\n").is_err());
+        assert!(Pressure::parse("full avg10=1.00\n").is_err());
+        assert!(Pressure::parse("other avg10=1.00\n").is_err());
+    }
+}
@@ -0,0 +1,348 @@
+//! A segment showing content pushed by external producers
+
+use crate::{
+    segment::{Segment, SegmentOutput},
+    stop::Stop,
+};
+use failure::{format_err, Fallible};
+use log::{debug, warn};
+use serde_derive::Deserialize;
+use std::{
+    ffi::CString,
+    fs::{self, OpenOptions},
+    io::prelude::*,
+    os::unix::{ffi::OsStrExt, fs::FileTypeExt, io::AsRawFd},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+use uuid::Uuid;
+
+/// The configuration of a pushed segment
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct PushedConfig {
+    /// The name to address the segment with
+    #[serde(default = "PushedConfig::default_name")]
+    pub name: String,
+
+    /// The named pipe to read the content from line by line, which will be
+    /// created if not existing. Empty lines remove the content.
+    #[serde(default)]
+    pub fifo: Option<PathBuf>,
+
+    /// The time in seconds after which pushed content expires
+    #[serde(default)]
+    pub expiry: Option<u64>,
+
+    /// The text displayed without content, the segment hides if not set
+    #[serde(default)]
+    pub placeholder: Option<String>,
+
+    /// The minimum width of the segment in pixels
+    #[serde(default = "PushedConfig::default_width")]
+    pub width: u16,
+}
+
+impl Default for PushedConfig {
+    fn default() -> Self {
+        Self {
+            name: Self::default_name(),
+            fifo: None,
+            expiry: None,
+            placeholder: None,
+            width: Self::default_width(),
+        }
+    }
+}
+
+impl PushedConfig {
+    fn default_name() -> String {
+        "pushed".to_owned()
+    }
+
+    fn default_width() -> u16 {
+        100
+    }
+}
+
+/// The latest pushed content together with its time of arrival
+type Content = Arc<Mutex<Option<(SegmentOutput, Instant)>>>;
+
+/// A segment, whose content is only updated via IPC or a named pipe
+pub struct Pushed {
+    id: String,
+    config: PushedConfig,
+    content: Content,
+    stop: Arc<Stop>,
+    reader: Option<JoinHandle<()>>,
+}
+
+impl Pushed {
+    /// Create a new pushed segment and start reading from the named pipe
+    pub fn new(config: PushedConfig) -> Fallible<Self> {
+        let content = Arc::new(Mutex::new(None));
+        let stop = Arc::new(Stop::new()?);
+
+        let mut reader = None;
+        if let Some(path) = &config.fifo {
+            Self::create_fifo(path)?;
+
+            // Opening the pipe for writing as well prevents blocking until
+            // the first producer connects and ending on its disconnect
+            let fifo = OpenOptions::new().read(true).write(true).open(path)?;
+            let (path, content, stop) =
+                (path.clone(), content.clone(), stop.clone());
+            reader = Some(thread::spawn(move || {
+                Self::read(fifo, &content, &stop);
+                debug!("Stopped reading from {}", path.display());
+            }));
+        }
+
+        Ok(Self {
+            id: Uuid::new_v4().to_string(),
+            config,
+            content,
+            stop,
+            reader,
+        })
+    }
+
+    /// Create the named pipe if not already existing
+    fn create_fifo(path: &Path) -> Fallible<()> {
+        match fs::metadata(path) {
+            Ok(m) if m.file_type().is_fifo() => return Ok(()),
+            Ok(_) => {
+                return Err(format_err!(
+                    "{} exists but is no named pipe",
+                    path.display()
+                ))
+            }
+            Err(_) => {}
+        }
+
+        let c_path = CString::new(path.as_os_str().as_bytes())?;
+        if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } != 0 {
+            return Err(format_err!(
+                "unable to create named pipe {}: {}",
+                path.display(),
+                std::io::Error::last_os_error()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Push the lines of the named pipe until stopped, whereas the pipe
+    /// is only read if data is available and never blocks stopping
+    fn read(mut fifo: fs::File, content: &Content, stop: &Stop) {
+        let mut buffer = vec![];
+        let mut chunk = [0; 4096];
+        while stop.wait(fifo.as_raw_fd()) {
+            match fifo.read(&mut chunk) {
+                Ok(0) => return,
+                Ok(read) => buffer.extend_from_slice(&chunk[..read]),
+                Err(e) => {
+                    warn!("Unable to read from the named pipe: {}", e);
+                    return;
+                }
+            }
+            while let Some(end) = buffer.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=end).collect();
+                let line = String::from_utf8_lossy(&line[..end]);
+                if let Err(e) = Self::push(content, &line) {
+                    warn!("Unable to parse '{}': {}", line, e);
+                }
+            }
+        }
+    }
+
+    /// Replace the content by the parsed text, whereas an empty text
+    /// removes it
+    fn push(content: &Content, text: &str) -> Fallible<()> {
+        if text.trim().is_empty() {
+            *content.lock().unwrap() = None;
+            return Ok(());
+        }
+        let output = SegmentOutput::parse(text)?;
+        *content.lock().unwrap() = Some((output, Instant::now()));
+        Ok(())
+    }
+
+    /// Retrieve the output if no content is available
+    fn placeholder(&self) -> SegmentOutput {
+        match &self.config.placeholder {
+            Some(p) => SegmentOutput::new(p.as_str()),
+            None => SegmentOutput::default().with_hidden(true),
+        }
+    }
+}
+
+impl Segment for Pushed {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn update(&mut self) -> Fallible<SegmentOutput> {
+        let mut content = self.content.lock().unwrap();
+        if let (Some((_, pushed)), Some(expiry)) =
+            (&*content, self.config.expiry)
+        {
+            if pushed.elapsed() >= Duration::from_secs(expiry) {
+                *content = None;
+            }
+        }
+        Ok(match &*content {
+            Some((output, _)) => output.clone(),
+            None => self.placeholder(),
+        })
+    }
+
+    fn width(&self) -> u16 {
+        self.config.width
+    }
+
+    fn set_text(&mut self, text: &str) -> Fallible<()> {
+        Self::push(&self.content, text)
+    }
+}
+
+impl Drop for Pushed {
+    fn drop(&mut self) {
+        // Stopping never touches the pipe, which may already be read by the
+        // segment replacing this one
+        self.stop.stop();
+        if let Some(reader) = self.reader.take() {
+            reader.join().ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::wait::{wait_for, wait_for_text};
+    use std::env;
+
+    #[test]
+    fn hidden_without_content() {
+        let mut pushed = Pushed::new(PushedConfig::default()).unwrap();
+        assert!(pushed.update().unwrap().hidden);
+
+        pushed.set_text("content").unwrap();
+        let output = pushed.update().unwrap();
+        assert!(!output.hidden);
+        assert_eq!(output.text, "content");
+
+        pushed.set_text("").unwrap();
+        assert!(pushed.update().unwrap().hidden);
+    }
+
+    #[test]
+    fn placeholder() {
+        let mut pushed = Pushed::new(PushedConfig {
+            placeholder: Some("-".into()),
+            ..PushedConfig::default()
+        })
+        .unwrap();
+        let output = pushed.update().unwrap();
+        assert!(!output.hidden);
+        assert_eq!(output.text, "-");
+    }
+
+    #[test]
+    fn expiry() {
+        let mut pushed = Pushed::new(PushedConfig {
+            expiry: Some(1),
+            placeholder: Some("-".into()),
+            ..PushedConfig::default()
+        })
+        .unwrap();
+        pushed
+            .set_text(r##"{"full_text":"failed","color":"#ff0000"}"##)
+            .unwrap();
+        let output = pushed.update().unwrap();
+        assert_eq!(output.text, "failed");
+        assert_eq!(output.color, Some([1., 0., 0., 1.]));
+
+        thread::sleep(Duration::from_millis(1100));
+        assert_eq!(pushed.update().unwrap().text, "-");
+    }
+
+    #[test]
+    fn fifo() {
+        let path = env::temp_dir().join(format!("unibar-{}", Uuid::new_v4()));
+        let mut pushed = Pushed::new(PushedConfig {
+            fifo: Some(path.clone()),
+            ..PushedConfig::default()
+        })
+        .unwrap();
+        assert!(fs::metadata(&path).unwrap().file_type().is_fifo());
+
+        let mut fifo = OpenOptions::new().write(true).open(&path).unwrap();
+        writeln!(fifo, "first").unwrap();
+        wait_for_text(&mut pushed, "first");
+        writeln!(fifo, "second").unwrap();
+        wait_for_text(&mut pushed, "second");
+        drop(fifo);
+
+        // Producers may reconnect
+        let mut fifo = OpenOptions::new().write(true).open(&path).unwrap();
+        writeln!(fifo, "third").unwrap();
+        wait_for_text(&mut pushed, "third");
+
+        // Existing pipes are reused
+        drop(pushed);
+        let mut pushed = Pushed::new(PushedConfig {
+            fifo: Some(path.clone()),
+            ..PushedConfig::default()
+        })
+        .unwrap();
+        writeln!(fifo, "fourth").unwrap();
+        wait_for_text(&mut pushed, "fourth");
+
+        // Empty lines remove the content
+        writeln!(fifo).unwrap();
+        wait_for(&mut pushed, |o| o.hidden);
+
+        drop(pushed);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reload() {
+        // The replacing segment reads from the pipe before the old one stops
+        let path = env::temp_dir().join(format!("unibar-{}", Uuid::new_v4()));
+        let config = PushedConfig {
+            fifo: Some(path.clone()),
+            placeholder: Some("-".into()),
+            ..PushedConfig::default()
+        };
+        let old = Pushed::new(config.clone()).unwrap();
+        let mut pushed = Pushed::new(config).unwrap();
+        drop(old);
+
+        let mut fifo = OpenOptions::new().write(true).open(&path).unwrap();
+        assert_eq!(pushed.update().unwrap().text, "-");
+        writeln!(fifo, "content").unwrap();
+        wait_for_text(&mut pushed, "content");
+
+        drop(pushed);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn no_fifo() {
+        let path = env::temp_dir().join(format!("unibar-{}", Uuid::new_v4()));
+        fs::write(&path, "").unwrap();
+        assert!(Pushed::new(PushedConfig {
+            fifo: Some(path.clone()),
+            ..PushedConfig::default()
+        })
+        .is_err());
+        fs::remove_file(path).unwrap();
+    }
+}
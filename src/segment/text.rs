@@ -0,0 +1,71 @@
+//! A segment showing a static text, which can be changed at runtime
+
+use crate::segment::{Segment, SegmentOutput};
+use failure::Fallible;
+use serde_derive::Deserialize;
+use uuid::Uuid;
+
+/// The configuration of a text segment
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct TextConfig {
+    /// The name to address the segment with
+    #[serde(default = "TextConfig::default_name")]
+    pub name: String,
+
+    /// The initially displayed text
+    #[serde(default)]
+    pub text: String,
+
+    /// The minimum width of the segment in pixels
+    #[serde(default = "TextConfig::default_width")]
+    pub width: u16,
+}
+
+impl TextConfig {
+    fn default_name() -> String {
+        "text".to_owned()
+    }
+
+    fn default_width() -> u16 {
+        100
+    }
+}
+
+/// A segment displaying a text
+pub struct Text {
+    id: String,
+    config: TextConfig,
+}
+
+impl Text {
+    /// Create a new text segment
+    pub fn new(config: TextConfig) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            config,
+        }
+    }
+}
+
+impl Segment for Text {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn update(&mut self) -> Fallible<SegmentOutput> {
+        Ok(SegmentOutput::new(self.config.text.as_str()))
+    }
+
+    fn width(&self) -> u16 {
+        self.config.width
+    }
+
+    fn set_text(&mut self, text: &str) -> Fallible<()> {
+        self.config.text = text.to_owned();
+        Ok(())
+    }
+}
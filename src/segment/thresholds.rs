@@ -0,0 +1,259 @@
+//! Coloring of segments by the band their numeric value falls into
+
+use crate::{
+    color::ColorScheme,
+    segment::{Button, Segment, SegmentOutput},
+};
+use failure::{format_err, Fallible};
+use serde_derive::Deserialize;
+use std::collections::BTreeMap;
+
+/// The bands a value falls into
+#[derive(
+    Clone, Copy, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Band {
+    /// Below the degraded threshold
+    Good,
+
+    /// Between the degraded and critical thresholds
+    Degraded,
+
+    /// Beyond the critical threshold
+    Critical,
+}
+
+/// The style of a band
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct BandStyle {
+    /// The text color by name like `red` or as `#rrggbb`
+    #[serde(default)]
+    pub color: Option<String>,
+
+    /// The background color by name or as `#rrggbb`
+    #[serde(default)]
+    pub background: Option<String>,
+
+    /// Mark the output as urgent
+    #[serde(default)]
+    pub urgent: bool,
+}
+
+/// The thresholds of a segment, where lower values are worse if the
+/// critical threshold is below the degraded one, like for a battery
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct ThresholdConfig {
+    /// The value from which on the segment is degraded
+    pub degraded: f64,
+
+    /// The value from which on the segment is critical
+    pub critical: f64,
+
+    /// The styles per band, which default to an orange text if degraded
+    /// and a red one if critical
+    #[serde(default)]
+    pub styles: BTreeMap<Band, BandStyle>,
+}
+
+impl ThresholdConfig {
+    /// Retrieve the band of a value
+    pub fn band(&self, value: f64) -> Band {
+        let reached = |threshold: f64| {
+            if self.critical >= self.degraded {
+                value >= threshold
+            } else {
+                value <= threshold
+            }
+        };
+        if reached(self.critical) {
+            Band::Critical
+        } else if reached(self.degraded) {
+            Band::Degraded
+        } else {
+            Band::Good
+        }
+    }
+
+    /// Retrieve the configured or default style of a band
+    fn style(&self, band: Band) -> BandStyle {
+        if let Some(style) = self.styles.get(&band) {
+            return style.clone();
+        }
+        let color = match band {
+            Band::Good => None,
+            Band::Degraded => Some("orange".to_owned()),
+            Band::Critical => Some("red".to_owned()),
+        };
+        BandStyle {
+            color,
+            ..BandStyle::default()
+        }
+    }
+}
+
+/// A segment colored by the band of its value
+pub struct Thresholds {
+    segment: Box<dyn Segment>,
+    config: ThresholdConfig,
+}
+
+impl Thresholds {
+    /// Wrap a segment, which fails if a color is invalid
+    pub fn new(
+        segment: Box<dyn Segment>,
+        config: ThresholdConfig,
+    ) -> Fallible<Self> {
+        for style in config.styles.values() {
+            for color in style.color.iter().chain(&style.background) {
+                ColorScheme::from_name(color)
+                    .ok_or_else(|| format_err!("invalid color '{}'", color))?;
+            }
+        }
+        Ok(Self { segment, config })
+    }
+}
+
+impl Segment for Thresholds {
+    fn id(&self) -> &str {
+        self.segment.id()
+    }
+
+    fn name(&self) -> &str {
+        self.segment.name()
+    }
+
+    fn update(&mut self) -> Fallible<SegmentOutput> {
+        let mut output = self.segment.update()?;
+        let value = match output.value {
+            Some(value) => value,
+            None => return Ok(output),
+        };
+
+        // The band overrides the colors chosen by the segment itself
+        let style = self.config.style(self.config.band(value));
+        if let Some(color) =
+            style.color.as_deref().and_then(ColorScheme::from_name)
+        {
+            output = output.with_color(color);
+        }
+        if let Some(color) =
+            style.background.as_deref().and_then(ColorScheme::from_name)
+        {
+            output = output.with_background(color);
+        }
+        if style.urgent {
+            output = output.with_urgent(true);
+        }
+        Ok(output)
+    }
+
+    fn click(&mut self, button: Button) {
+        self.segment.click(button)
+    }
+
+    fn click_icon(&mut self, button: Button, index: usize) {
+        self.segment.click_icon(button, index)
+    }
+
+    fn click_at(&mut self, button: Button, position: (i32, i32)) {
+        self.segment.click_at(button, position)
+    }
+
+    fn click_icon_at(
+        &mut self,
+        button: Button,
+        index: usize,
+        position: (i32, i32),
+    ) {
+        self.segment.click_icon_at(button, index, position)
+    }
+
+    fn width(&self) -> u16 {
+        self.segment.width()
+    }
+
+    fn set_text(&mut self, text: &str) -> Fallible<()> {
+        self.segment.set_text(text)
+    }
+
+    fn popup(&mut self) -> Option<Vec<SegmentOutput>> {
+        self.segment.popup()
+    }
+
+    fn animated(&self) -> bool {
+        self.segment.animated()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::segment::Counter;
+
+    fn config(degraded: f64, critical: f64) -> ThresholdConfig {
+        ThresholdConfig {
+            degraded,
+            critical,
+            styles: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn bands() {
+        let rising = config(70., 90.);
+        assert_eq!(rising.band(12.), Band::Good);
+        assert_eq!(rising.band(70.), Band::Degraded);
+        assert_eq!(rising.band(95.), Band::Critical);
+
+        let falling = config(30., 10.);
+        assert_eq!(falling.band(80.), Band::Good);
+        assert_eq!(falling.band(25.), Band::Degraded);
+        assert_eq!(falling.band(10.), Band::Critical);
+    }
+
+    #[test]
+    fn colors() {
+        let mut config = config(1., 2.);
+        config.styles.insert(
+            Band::Critical,
+            BandStyle {
+                color: Some("background".into()),
+                background: Some("#ff0000".into()),
+                urgent: true,
+            },
+        );
+        let mut segment =
+            Thresholds::new(Box::new(Counter::new("a")), config).unwrap();
+        assert_eq!(segment.id(), "a");
+
+        let output = segment.update().unwrap();
+        assert_eq!(output.color, None);
+        assert!(!output.urgent);
+
+        segment.click(Button::Left);
+        let output = segment.update().unwrap();
+        assert_eq!(output.text, "1");
+        assert_eq!(output.color, Some(ColorScheme::orange()));
+        assert_eq!(output.background, None);
+
+        segment.click(Button::Left);
+        let output = segment.update().unwrap();
+        assert_eq!(output.color, Some(ColorScheme::background()));
+        assert_eq!(output.background, Some([1., 0., 0., 1.]));
+        assert!(output.urgent);
+    }
+
+    #[test]
+    fn invalid() {
+        let mut config = config(1., 2.);
+        config.styles.insert(
+            Band::Good,
+            BandStyle {
+                color: Some("magenta".into()),
+                ..BandStyle::default()
+            },
+        );
+        assert!(Thresholds::new(Box::new(Counter::new("a")), config).is_err());
+    }
+}
@@ -0,0 +1,837 @@
+//! A system tray hosting StatusNotifierItems
+
+use crate::{
+    assets, bus,
+    color::ColorScheme,
+    segment::{Button, Icon, Segment, SegmentOutput},
+};
+use dbus::{
+    arg::{PropMap, Variant},
+    blocking::{
+        stdintf::org_freedesktop_dbus::{Properties, RequestNameReply},
+        Connection,
+    },
+    channel::{MatchingReceiver, Sender as _},
+    message::{MatchRule, MessageType},
+    strings::ErrorName,
+    Message,
+};
+use failure::{err_msg, format_err, Fallible};
+use log::{debug, warn};
+use serde_derive::Deserialize;
+use std::{
+    ffi::CString,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Receiver, Sender, TryRecvError},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+use uuid::Uuid;
+
+/// The bus name, interface and object path of the watcher
+const WATCHER: &str = "org.kde.StatusNotifierWatcher";
+const WATCHER_PATH: &str = "/StatusNotifierWatcher";
+
+/// The interface and default object path of items
+const ITEM: &str = "org.kde.StatusNotifierItem";
+const ITEM_PATH: &str = "/StatusNotifierItem";
+
+/// The maximum time to wait for an item to respond
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The preferred size of icons in pixels
+const ICON_SIZE: u32 = 16;
+
+/// The horizontal space of a single item in pixels
+const ITEM_WIDTH: u16 = 20;
+
+/// The sizes of themed icons ordered by preference
+const ICON_SIZES: &[&str] = &["16x16", "22x22", "24x24", "32x32", "48x48"];
+
+/// The categories of themed icons
+const ICON_CATEGORIES: &[&str] = &["apps", "status", "devices", "panel"];
+
+/// The configuration of a tray segment
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct TrayConfig {
+    /// The name to address the segment with
+    #[serde(default = "TrayConfig::default_name")]
+    pub name: String,
+
+    /// The D-Bus address to connect to, the session bus is used if not set
+    #[serde(default)]
+    pub address: Option<String>,
+}
+
+impl Default for TrayConfig {
+    fn default() -> Self {
+        Self {
+            name: Self::default_name(),
+            address: None,
+        }
+    }
+}
+
+impl TrayConfig {
+    fn default_name() -> String {
+        "tray".to_owned()
+    }
+}
+
+/// A change of the registered items
+#[derive(Debug, PartialEq)]
+enum Event {
+    /// A service registered an item
+    Register { service: String, sender: String },
+
+    /// An item or its owner disappeared
+    Unregister(String),
+
+    /// An item owned by the unique name changed
+    Changed(String),
+}
+
+/// A single registered item
+#[derive(Clone, Debug, Default)]
+struct Item {
+    bus: String,
+    path: String,
+    owner: String,
+    title: String,
+    status: String,
+    icon: Option<Icon>,
+}
+
+impl Item {
+    /// The identifier used by the watcher
+    fn key(&self) -> String {
+        format!("{}{}", self.bus, self.path)
+    }
+
+    /// Returns true if the item should be displayed
+    fn is_visible(&self) -> bool {
+        self.status != "Passive"
+    }
+}
+
+/// A segment displaying the icons of StatusNotifierItems, which acts as the
+/// watcher or registers as host of an already running watcher
+pub struct Tray {
+    id: String,
+    config: TrayConfig,
+    items: Arc<Mutex<Vec<Item>>>,
+    clicks: Sender<(Button, usize, (i32, i32))>,
+}
+
+impl Tray {
+    /// Create a new tray and start watching the items
+    pub fn new(config: TrayConfig) -> Fallible<Self> {
+        let connection = bus::connect(config.address.as_deref())?;
+        let items = Arc::new(Mutex::new(vec![]));
+        let (tx, rx) = mpsc::channel();
+
+        let thread_items = items.clone();
+        thread::spawn(move || {
+            if let Err(e) = Self::run(&connection, &thread_items, &rx) {
+                warn!("Unable to host tray items: {}", e);
+                thread_items.lock().unwrap().clear();
+            }
+        });
+
+        Ok(Self {
+            id: Uuid::new_v4().to_string(),
+            config,
+            items,
+            clicks: tx,
+        })
+    }
+
+    /// Track the items and forward the clicks until the segment gets dropped
+    fn run(
+        connection: &Connection,
+        items: &Arc<Mutex<Vec<Item>>>,
+        clicks: &Receiver<(Button, usize, (i32, i32))>,
+    ) -> Fallible<()> {
+        let events = Arc::new(Mutex::new(vec![]));
+        let watcher = connection.request_name(WATCHER, false, false, true)?
+            == RequestNameReply::PrimaryOwner;
+
+        if watcher {
+            debug!("Acting as StatusNotifierWatcher");
+            let (items, events) = (items.clone(), events.clone());
+            connection.start_receive(
+                MatchRule::new_method_call().with_path(WATCHER_PATH),
+                Box::new(move |msg, connection| {
+                    let reply = Self::watcher_reply(&msg, &items, &events)
+                        .unwrap_or_else(|e| {
+                            msg.error(
+                                &ErrorName::from(
+                                    "org.freedesktop.DBus.Error.UnknownMethod",
+                                ),
+                                &CString::new(e.to_string())
+                                    .unwrap_or_default(),
+                            )
+                        });
+                    connection.send(reply).ok();
+                    true
+                }),
+            );
+            Self::signal(connection, "StatusNotifierHostRegistered", None)?;
+        } else {
+            debug!("Registering as host of the running watcher");
+            for (member, register) in &[
+                ("StatusNotifierItemRegistered", true),
+                ("StatusNotifierItemUnregistered", false),
+            ] {
+                let (events, register) = (events.clone(), *register);
+                connection.add_match(
+                    MatchRule::new_signal(WATCHER, *member),
+                    move |(service,): (String,),
+                          _: &Connection,
+                          _: &Message| {
+                        events.lock().unwrap().push(if register {
+                            Event::Register {
+                                service,
+                                sender: String::new(),
+                            }
+                        } else {
+                            Event::Unregister(service)
+                        });
+                        true
+                    },
+                )?;
+            }
+
+            let proxy = connection.with_proxy(WATCHER, WATCHER_PATH, TIMEOUT);
+            proxy.method_call::<(), _, _, _>(
+                WATCHER,
+                "RegisterStatusNotifierHost",
+                (connection.unique_name().to_string(),),
+            )?;
+            let registered: Vec<String> =
+                proxy.get(WATCHER, "RegisteredStatusNotifierItems")?;
+            events.lock().unwrap().extend(registered.into_iter().map(
+                |service| Event::Register {
+                    service,
+                    sender: String::new(),
+                },
+            ));
+        }
+
+        let vanished = events.clone();
+        connection.add_match(
+            MatchRule::new_signal("org.freedesktop.DBus", "NameOwnerChanged"),
+            move |(name, _, new): (String, String, String),
+                  _: &Connection,
+                  _: &Message| {
+                if new.is_empty() {
+                    vanished.lock().unwrap().push(Event::Unregister(name));
+                }
+                true
+            },
+        )?;
+
+        // Items signal changes by `NewIcon`, `NewStatus`, `NewTitle` etc.
+        let changed = events.clone();
+        connection.add_match(
+            MatchRule::new()
+                .with_type(MessageType::Signal)
+                .with_interface(ITEM),
+            move |_: (), _: &Connection, msg: &Message| {
+                if let Some(sender) = msg.sender() {
+                    changed
+                        .lock()
+                        .unwrap()
+                        .push(Event::Changed(sender.to_string()));
+                }
+                true
+            },
+        )?;
+
+        loop {
+            loop {
+                match clicks.try_recv() {
+                    Ok((button, index, position)) => {
+                        let item = items
+                            .lock()
+                            .unwrap()
+                            .iter()
+                            .filter(|i| i.is_visible())
+                            .nth(index)
+                            .cloned();
+                        if let Some(item) = item {
+                            if let Err(e) = Self::control(
+                                connection, &item, button, position,
+                            ) {
+                                warn!("Unable to control tray item: {}", e)
+                            }
+                        }
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        debug!("Stopped hosting tray items");
+                        return Ok(());
+                    }
+                }
+            }
+
+            let pending: Vec<Event> =
+                events.lock().unwrap().drain(..).collect();
+            for event in pending {
+                if let Err(e) = Self::handle(connection, watcher, items, event)
+                {
+                    warn!("Unable to update tray item: {}", e);
+                }
+            }
+            connection.process(Duration::from_millis(100))?;
+        }
+    }
+
+    /// Apply a change of the registered items
+    fn handle(
+        connection: &Connection,
+        watcher: bool,
+        items: &Mutex<Vec<Item>>,
+        event: Event,
+    ) -> Fallible<()> {
+        match event {
+            Event::Register { service, sender } => {
+                let (bus, path) = match service.find('/') {
+                    Some(0) => (sender, service),
+                    Some(i) => {
+                        (service[..i].to_owned(), service[i..].to_owned())
+                    }
+                    None => (service, ITEM_PATH.to_owned()),
+                };
+                let mut item = Item {
+                    owner: Self::owner(connection, &bus)?,
+                    bus,
+                    path,
+                    ..Item::default()
+                };
+                if items.lock().unwrap().iter().any(|i| i.key() == item.key()) {
+                    return Ok(());
+                }
+
+                Self::load(connection, &mut item);
+                debug!("Registered tray item {}", item.key());
+                if watcher {
+                    Self::signal(
+                        connection,
+                        "StatusNotifierItemRegistered",
+                        Some(&item.key()),
+                    )?;
+                }
+                items.lock().unwrap().push(item);
+            }
+            Event::Unregister(name) => {
+                let mut removed = vec![];
+                items.lock().unwrap().retain(|i| {
+                    let vanished =
+                        i.key() == name || i.bus == name || i.owner == name;
+                    if vanished {
+                        removed.push(i.key());
+                    }
+                    !vanished
+                });
+                for key in removed.iter().filter(|_| watcher) {
+                    debug!("Unregistered tray item {}", key);
+                    Self::signal(
+                        connection,
+                        "StatusNotifierItemUnregistered",
+                        Some(key),
+                    )?;
+                }
+            }
+            Event::Changed(sender) => {
+                let mut changed: Vec<Item> = items
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .filter(|i| i.owner == sender)
+                    .cloned()
+                    .collect();
+                for item in changed.iter_mut() {
+                    Self::load(connection, item);
+                }
+                for item in items.lock().unwrap().iter_mut() {
+                    if let Some(c) =
+                        changed.iter().find(|c| c.key() == item.key())
+                    {
+                        *item = c.clone();
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Answer a method call to the watcher
+    fn watcher_reply(
+        msg: &Message,
+        items: &Mutex<Vec<Item>>,
+        events: &Mutex<Vec<Event>>,
+    ) -> Fallible<Message> {
+        let member = msg.member().map(|m| m.to_string()).unwrap_or_default();
+        let sender = msg.sender().map(|s| s.to_string()).unwrap_or_default();
+
+        let mut properties = PropMap::new();
+        let keys: Vec<String> =
+            items.lock().unwrap().iter().map(Item::key).collect();
+        properties.insert(
+            "RegisteredStatusNotifierItems".into(),
+            Variant(Box::new(keys)),
+        );
+        properties.insert(
+            "IsStatusNotifierHostRegistered".into(),
+            Variant(Box::new(true)),
+        );
+        properties.insert("ProtocolVersion".into(), Variant(Box::new(0)));
+
+        Ok(match member.as_str() {
+            "RegisterStatusNotifierItem" => {
+                let service: String = msg.read1()?;
+                events
+                    .lock()
+                    .unwrap()
+                    .push(Event::Register { service, sender });
+                msg.method_return()
+            }
+            "RegisterStatusNotifierHost" => msg.method_return(),
+            "Get" => {
+                let (_, name): (&str, &str) = msg.read2()?;
+                let value = properties.remove(name).ok_or_else(|| {
+                    format_err!("unknown property '{}'", name)
+                })?;
+                msg.method_return().append1(value)
+            }
+            "GetAll" => msg.method_return().append1(properties),
+            _ => return Err(format_err!("unknown method '{}'", member)),
+        })
+    }
+
+    /// Emit a signal of the watcher
+    fn signal(
+        connection: &Connection,
+        member: &str,
+        service: Option<&str>,
+    ) -> Fallible<()> {
+        let mut signal = Message::new_signal(WATCHER_PATH, WATCHER, member)
+            .map_err(err_msg)?;
+        if let Some(service) = service {
+            signal = signal.append1(service);
+        }
+        connection
+            .send(signal)
+            .map_err(|_| format_err!("unable to send {} signal", member))?;
+        Ok(())
+    }
+
+    /// Retrieve the unique name owning the bus name
+    fn owner(connection: &Connection, bus: &str) -> Fallible<String> {
+        if bus.starts_with(':') {
+            return Ok(bus.to_owned());
+        }
+        let (owner,): (String,) = connection
+            .with_proxy(
+                "org.freedesktop.DBus",
+                "/org/freedesktop/DBus",
+                TIMEOUT,
+            )
+            .method_call("org.freedesktop.DBus", "GetNameOwner", (bus,))?;
+        Ok(owner)
+    }
+
+    /// Load the properties of an item, where missing ones are ignored
+    fn load(connection: &Connection, item: &mut Item) {
+        let proxy = connection.with_proxy(
+            item.bus.as_str(),
+            item.path.as_str(),
+            TIMEOUT,
+        );
+        item.title = proxy
+            .get(ITEM, "Title")
+            .or_else(|_| proxy.get(ITEM, "Id"))
+            .unwrap_or_default();
+        item.status = proxy
+            .get(ITEM, "Status")
+            .unwrap_or_else(|_| "Active".to_owned());
+        let theme_path: Option<String> = proxy.get(ITEM, "IconThemePath").ok();
+
+        // Items needing attention may provide a dedicated icon
+        let prefixes: &[&str] = if item.status == "NeedsAttention" {
+            &["Attention", ""]
+        } else {
+            &[""]
+        };
+        item.icon = prefixes.iter().find_map(|prefix| {
+            proxy
+                .get::<String>(ITEM, &format!("{}IconName", prefix))
+                .ok()
+                .and_then(|name| Self::find_icon(&name, theme_path.as_deref()))
+                .or_else(|| {
+                    proxy
+                        .get(ITEM, &format!("{}IconPixmap", prefix))
+                        .ok()
+                        .and_then(Self::pixmap)
+                })
+        });
+    }
+
+    /// Convert the ARGB pixmap closest to the preferred size into an icon
+    fn pixmap(pixmaps: Vec<(i32, i32, Vec<u8>)>) -> Option<Icon> {
+        pixmaps
+            .into_iter()
+            .filter(|(w, h, data)| {
+                *w > 0 && *h > 0 && data.len() == (*w * *h * 4) as usize
+            })
+            .min_by_key(|(w, _, _)| (*w as u32 as i64 - ICON_SIZE as i64).abs())
+            .map(|(width, height, data)| Icon::Rgba {
+                width: width as u32,
+                height: height as u32,
+                data: data
+                    .chunks(4)
+                    .flat_map(|p| vec![p[1], p[2], p[3], p[0]])
+                    .collect(),
+            })
+    }
+
+    /// Find the PNG file of a themed icon
+    fn find_icon(name: &str, theme_path: Option<&str>) -> Option<Icon> {
+        if name.is_empty() {
+            return None;
+        }
+        if Path::new(name).is_absolute() {
+            return Some(Icon::File(PathBuf::from(name)));
+        }
+
+        let mut dirs: Vec<PathBuf> = theme_path
+            .filter(|p| !p.is_empty())
+            .map(PathBuf::from)
+            .into_iter()
+            .collect();
+        dirs.extend(assets::data_dirs().into_iter().map(|d| d.join("icons")));
+
+        let file = format!("{}.png", name);
+        let mut candidates = vec![];
+        for dir in &dirs {
+            candidates.push(dir.join(&file));
+            for size in ICON_SIZES {
+                for category in ICON_CATEGORIES {
+                    candidates.push(
+                        dir.join("hicolor")
+                            .join(size)
+                            .join(category)
+                            .join(&file),
+                    );
+                }
+            }
+        }
+        candidates.push(Path::new("/usr/share/pixmaps").join(&file));
+        candidates.into_iter().find(|c| c.is_file()).map(Icon::File)
+    }
+
+    /// Call the method of the item corresponding to the button, whose
+    /// menus open at the clicked screen position
+    fn control(
+        connection: &Connection,
+        item: &Item,
+        button: Button,
+        (x, y): (i32, i32),
+    ) -> Fallible<()> {
+        let proxy = connection.with_proxy(
+            item.bus.as_str(),
+            item.path.as_str(),
+            TIMEOUT,
+        );
+        match button {
+            Button::Left => proxy.method_call(ITEM, "Activate", (x, y))?,
+            Button::Middle => {
+                proxy.method_call(ITEM, "SecondaryActivate", (x, y))?
+            }
+            Button::Right => proxy.method_call(ITEM, "ContextMenu", (x, y))?,
+            Button::ScrollUp => {
+                proxy.method_call(ITEM, "Scroll", (1, "vertical"))?
+            }
+            Button::ScrollDown => {
+                proxy.method_call(ITEM, "Scroll", (-1, "vertical"))?
+            }
+        }
+        Ok(())
+    }
+
+    /// Create a placeholder for items without any icon
+    fn placeholder() -> Icon {
+        let color = ColorScheme::selection();
+        let pixel: Vec<u8> = color.iter().map(|c| (c * 255.) as u8).collect();
+        Icon::Rgba {
+            width: ICON_SIZE,
+            height: ICON_SIZE,
+            data: pixel
+                .iter()
+                .cycle()
+                .take((ICON_SIZE * ICON_SIZE * 4) as usize)
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+impl Segment for Tray {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn update(&mut self) -> Fallible<SegmentOutput> {
+        let items = self.items.lock().unwrap();
+        let visible: Vec<&Item> =
+            items.iter().filter(|i| i.is_visible()).collect();
+        if visible.is_empty() {
+            return Ok(SegmentOutput::default().with_hidden(true));
+        }
+
+        let titles: Vec<&str> =
+            visible.iter().map(|i| i.title.as_str()).collect();
+        Ok(SegmentOutput::new(titles.join(" "))
+            .with_urgent(visible.iter().any(|i| i.status == "NeedsAttention"))
+            .with_icons(
+                visible
+                    .iter()
+                    .map(|i| i.icon.clone().unwrap_or_else(Self::placeholder))
+                    .collect(),
+            ))
+    }
+
+    fn click(&mut self, button: Button) {
+        self.click_icon(button, 0)
+    }
+
+    fn click_icon(&mut self, button: Button, index: usize) {
+        self.click_icon_at(button, index, (0, 0))
+    }
+
+    fn click_at(&mut self, button: Button, position: (i32, i32)) {
+        self.click_icon_at(button, 0, position)
+    }
+
+    fn click_icon_at(
+        &mut self,
+        button: Button,
+        index: usize,
+        position: (i32, i32),
+    ) {
+        if self.clicks.send((button, index, position)).is_err() {
+            warn!("Tray items are not hosted any more");
+        }
+    }
+
+    fn width(&self) -> u16 {
+        let items = self.items.lock().unwrap();
+        let count = items.iter().filter(|i| i.is_visible()).count() as u16;
+        ITEM_WIDTH * count.max(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{
+        dbus::Bus,
+        tray::{self, ItemState},
+        wait::{wait_for, wait_for_text, wait_until},
+    };
+    use std::{env, fs};
+
+    fn tray(bus: &Bus) -> Tray {
+        Tray::new(TrayConfig {
+            address: Some(bus.address().to_owned()),
+            ..TrayConfig::default()
+        })
+        .unwrap()
+    }
+
+    /// Register the item with the provided bus name at the watcher
+    fn register(bus: &Bus, service: &str) {
+        bus::connect(Some(bus.address()))
+            .unwrap()
+            .with_proxy(WATCHER, WATCHER_PATH, TIMEOUT)
+            .method_call::<(), _, _, _>(
+                WATCHER,
+                "RegisterStatusNotifierItem",
+                (service,),
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn pixmap() {
+        let bus = Bus::start();
+        let mut segment = tray(&bus);
+        assert!(segment.update().unwrap().hidden);
+        assert_eq!(segment.width(), ITEM_WIDTH);
+
+        let item = tray::Item::start(
+            &bus,
+            "org.kde.StatusNotifierItem-1-1",
+            ItemState {
+                title: "pixmap".into(),
+                pixmap: vec![
+                    (32, 32, vec![0; 32 * 32 * 4]),
+                    (1, 1, vec![255, 1, 2, 3]),
+                    (2, 1, vec![0; 3]),
+                ],
+                ..ItemState::default()
+            },
+        );
+        register(&bus, "org.kde.StatusNotifierItem-1-1");
+
+        let output = wait_for_text(&mut segment, "pixmap");
+        assert_eq!(
+            output.icons,
+            vec![Icon::Rgba {
+                width: 1,
+                height: 1,
+                data: vec![1, 2, 3, 255],
+            }]
+        );
+        assert_eq!(segment.width(), ITEM_WIDTH);
+
+        drop(item);
+        wait_for(&mut segment, |o| o.hidden);
+    }
+
+    #[test]
+    fn themed_icon_and_status() {
+        let dir = env::temp_dir().join(format!("unibar-{}", Uuid::new_v4()));
+        let icon = dir.join("hicolor/22x22/status/test-icon.png");
+        fs::create_dir_all(icon.parent().unwrap()).unwrap();
+        fs::write(&icon, "").unwrap();
+
+        let bus = Bus::start();
+        let mut segment = tray(&bus);
+        let first = tray::Item::start(
+            &bus,
+            "org.test.first",
+            ItemState {
+                title: "themed".into(),
+                icon_name: "test-icon".into(),
+                theme_path: dir.to_string_lossy().into_owned(),
+                ..ItemState::default()
+            },
+        );
+        register(&bus, "org.test.first");
+        let output = wait_for_text(&mut segment, "themed");
+        assert_eq!(output.icons, vec![Icon::File(icon)]);
+        assert!(!output.urgent);
+
+        let _second = tray::Item::start(
+            &bus,
+            "org.test.second",
+            ItemState {
+                title: "placeholder".into(),
+                ..ItemState::default()
+            },
+        );
+        register(&bus, "org.test.second/StatusNotifierItem");
+        let output = wait_for_text(&mut segment, "themed placeholder");
+        assert_eq!(output.icons[1], Tray::placeholder());
+        assert_eq!(segment.width(), 2 * ITEM_WIDTH);
+
+        first.change(|s| s.status = "NeedsAttention".into());
+        assert!(wait_for(&mut segment, |o| o.urgent).icons.len() == 2);
+        first.change(|s| s.status = "Passive".into());
+        wait_for_text(&mut segment, "placeholder");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn control() {
+        let bus = Bus::start();
+        let mut segment = tray(&bus);
+        let item =
+            tray::Item::start(&bus, "org.test.item", ItemState::default());
+        register(&bus, "org.test.item");
+        wait_for(&mut segment, |o| !o.hidden);
+
+        segment.click(Button::Left);
+        segment.click_icon(Button::Middle, 0);
+        segment.click_at(Button::Right, (1800, 12));
+        segment.click_icon(Button::ScrollUp, 0);
+        segment.click_icon(Button::ScrollDown, 0);
+        segment.click_icon(Button::Left, 1);
+
+        wait_until(|| item.state().calls.len() >= 5);
+        assert_eq!(
+            item.state().calls,
+            vec![
+                "Activate 0 0",
+                "SecondaryActivate 0 0",
+                "ContextMenu 1800 12",
+                "Scroll 1",
+                "Scroll -1"
+            ]
+        );
+    }
+
+    #[test]
+    fn host() {
+        let bus = Bus::start();
+        let mut watcher = tray(&bus);
+        let _item = tray::Item::start(
+            &bus,
+            "org.test.early",
+            ItemState {
+                title: "early".into(),
+                ..ItemState::default()
+            },
+        );
+        register(&bus, "org.test.early");
+        wait_for_text(&mut watcher, "early");
+
+        // Trays started later register as hosts of the running watcher
+        let mut host = tray(&bus);
+        wait_for_text(&mut host, "early");
+
+        let late = tray::Item::start(
+            &bus,
+            "org.test.late",
+            ItemState {
+                title: "late".into(),
+                ..ItemState::default()
+            },
+        );
+        register(&bus, "org.test.late");
+        wait_for_text(&mut host, "early late");
+
+        drop(late);
+        wait_for_text(&mut host, "early");
+        wait_for_text(&mut watcher, "early");
+    }
+
+    #[test]
+    fn registered_items() {
+        let bus = Bus::start();
+        let mut segment = tray(&bus);
+        let _item =
+            tray::Item::start(&bus, "org.test.item", ItemState::default());
+        register(&bus, "org.test.item");
+        wait_for(&mut segment, |o| !o.hidden);
+
+        let connection = bus::connect(Some(bus.address())).unwrap();
+        let proxy = connection.with_proxy(WATCHER, WATCHER_PATH, TIMEOUT);
+        let items: Vec<String> =
+            proxy.get(WATCHER, "RegisteredStatusNotifierItems").unwrap();
+        assert_eq!(items, vec!["org.test.item/StatusNotifierItem"]);
+        assert!(proxy
+            .get::<bool>(WATCHER, "IsStatusNotifierHostRegistered")
+            .unwrap());
+    }
+}
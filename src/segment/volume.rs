@@ -0,0 +1,668 @@
+//! A segment showing the volume of the default audio sink
+
+use crate::{
+    color::ColorScheme,
+    segment::{Button, Segment, SegmentOutput},
+    template::Template,
+};
+use failure::{format_err, Fallible};
+use log::{debug, warn};
+use serde_derive::Deserialize;
+use std::{
+    cmp,
+    io::{BufRead, BufReader},
+    process::{self, Child, ChildStdout, Stdio},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use uuid::Uuid;
+
+/// The placeholders available within the format
+const PLACEHOLDERS: &[&str] = &["icon", "volume", "muted"];
+
+/// The name of the default sink understood by `pactl`
+const DEFAULT_SINK: &str = "@DEFAULT_SINK@";
+
+/// The initial delay between two subscription attempts
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The maximum delay between two subscription attempts
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// The text displayed while the sound server is unavailable
+const UNAVAILABLE: &str = "\u{f026} unavailable";
+
+/// The configuration of a volume segment
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct VolumeConfig {
+    /// The name to address the segment with
+    #[serde(default = "VolumeConfig::default_name")]
+    pub name: String,
+
+    /// The displayed text, where the placeholders `icon`, `volume` in
+    /// percent and `muted` are available
+    #[serde(default = "VolumeConfig::default_format")]
+    pub format: String,
+
+    /// The volume change per scroll step in percent
+    #[serde(default = "VolumeConfig::default_step")]
+    pub step: u8,
+
+    /// The maximum volume reachable by scrolling in percent
+    #[serde(default = "VolumeConfig::default_max")]
+    pub max: u8,
+
+    /// The minimum width of the segment in pixels
+    #[serde(default = "VolumeConfig::default_width")]
+    pub width: u16,
+}
+
+impl Default for VolumeConfig {
+    fn default() -> Self {
+        Self {
+            name: Self::default_name(),
+            format: Self::default_format(),
+            step: Self::default_step(),
+            max: Self::default_max(),
+            width: Self::default_width(),
+        }
+    }
+}
+
+impl VolumeConfig {
+    fn default_name() -> String {
+        "volume".to_owned()
+    }
+
+    fn default_format() -> String {
+        "{icon} {volume}%".to_owned()
+    }
+
+    fn default_step() -> u8 {
+        5
+    }
+
+    fn default_max() -> u8 {
+        100
+    }
+
+    fn default_width() -> u16 {
+        70
+    }
+}
+
+/// The state of an audio sink
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Sink {
+    /// The volume in percent, which may exceed 100
+    pub volume: u32,
+
+    /// Indicates that the sink is muted
+    pub muted: bool,
+}
+
+/// A sound server providing the default sink
+pub trait AudioBackend: Send {
+    /// Retrieve the current state of the default sink
+    fn sink(&mut self) -> Fallible<Sink>;
+
+    /// Set the volume of the default sink in percent
+    fn set_volume(&mut self, volume: u32) -> Fallible<()>;
+
+    /// Mute or unmute the default sink
+    fn set_muted(&mut self, muted: bool) -> Fallible<()>;
+
+    /// Send an event on every change of the sinks or the server
+    fn subscribe(&mut self, events: Sender<()>) -> Fallible<()>;
+}
+
+/// The running `pactl subscribe` process, which is not restarted any more
+/// once stopped
+#[derive(Default)]
+struct Subscription {
+    child: Option<Child>,
+    stopped: bool,
+}
+
+/// A backend using `pactl`, which works for PulseAudio and PipeWire
+pub struct Pactl {
+    program: String,
+    subscription: Arc<Mutex<Subscription>>,
+}
+
+impl Default for Pactl {
+    fn default() -> Self {
+        Self {
+            program: "pactl".to_owned(),
+            subscription: Arc::default(),
+        }
+    }
+}
+
+impl Pactl {
+    /// Run `pactl` with the provided arguments and retrieve its output
+    fn run(&self, args: &[&str]) -> Fallible<String> {
+        let output = process::Command::new(&self.program)
+            .args(args)
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| format_err!("unable to run pactl: {}", e))?;
+        if !output.status.success() {
+            return Err(format_err!(
+                "pactl {}: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /// Parse the first channel volume of `pactl get-sink-volume`
+    fn parse_volume(output: &str) -> Fallible<u32> {
+        output
+            .split('/')
+            .map(str::trim)
+            .find(|s| s.ends_with('%'))
+            .and_then(|s| s.trim_end_matches('%').parse().ok())
+            .ok_or_else(|| format_err!("no volume in '{}'", output.trim()))
+    }
+
+    /// Parse the output of `pactl get-sink-mute`
+    fn parse_muted(output: &str) -> Fallible<bool> {
+        match output.trim().trim_start_matches("Mute:").trim() {
+            "yes" => Ok(true),
+            "no" => Ok(false),
+            _ => Err(format_err!("no mute state in '{}'", output.trim())),
+        }
+    }
+
+    /// Returns true if a line of `pactl subscribe` affects the default sink
+    fn is_relevant(line: &str) -> bool {
+        line.contains(" on sink #") || line.contains(" on server")
+    }
+
+    /// Start `pactl subscribe` and retrieve its output
+    fn spawn(program: &str) -> Fallible<(Child, ChildStdout)> {
+        let mut child = process::Command::new(program)
+            .arg("subscribe")
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| format_err!("unable to run pactl: {}", e))?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| format_err!("no output of pactl subscribe"))?;
+        Ok((child, stdout))
+    }
+
+    /// Forward the sound server events and restart the subscription with an
+    /// increasing backoff, like after a restart of the sound server
+    fn listen(
+        program: &str,
+        subscription: &Mutex<Subscription>,
+        stdout: ChildStdout,
+        events: &Sender<()>,
+    ) {
+        let mut stdout = Some(stdout);
+        let mut backoff = MIN_BACKOFF;
+
+        loop {
+            let started = Instant::now();
+            if let Some(stdout) = stdout.take() {
+                for line in BufReader::new(stdout).lines() {
+                    match line {
+                        Ok(ref l) if !Self::is_relevant(l) => {}
+                        Ok(_) => {
+                            if events.send(()).is_err() {
+                                return;
+                            }
+                        }
+                        Err(e) => {
+                            warn!("Unable to read sound server events: {}", e);
+                            break;
+                        }
+                    }
+                }
+
+                // The sink is unavailable until the server is back
+                if events.send(()).is_err() {
+                    return;
+                }
+            }
+
+            {
+                let mut subscription = subscription.lock().unwrap();
+                if subscription.stopped {
+                    return;
+                }
+                if let Some(mut child) = subscription.child.take() {
+                    child.wait().ok();
+                }
+            }
+            if started.elapsed() > backoff {
+                backoff = MIN_BACKOFF;
+            }
+            debug!(
+                "Sound server events stopped, resubscribing in {}s",
+                backoff.as_secs()
+            );
+            thread::sleep(backoff);
+            backoff = cmp::min(backoff * 2, MAX_BACKOFF);
+
+            let mut subscription = subscription.lock().unwrap();
+            if subscription.stopped {
+                return;
+            }
+            match Self::spawn(program) {
+                Ok((child, out)) => {
+                    subscription.child = Some(child);
+                    stdout = Some(out);
+
+                    // The sink may have changed while not subscribed
+                    if events.send(()).is_err() {
+                        return;
+                    }
+                }
+                Err(e) => debug!("{}", e),
+            }
+        }
+    }
+}
+
+impl AudioBackend for Pactl {
+    fn sink(&mut self) -> Fallible<Sink> {
+        Ok(Sink {
+            volume: Self::parse_volume(
+                &self.run(&["get-sink-volume", DEFAULT_SINK])?,
+            )?,
+            muted: Self::parse_muted(
+                &self.run(&["get-sink-mute", DEFAULT_SINK])?,
+            )?,
+        })
+    }
+
+    fn set_volume(&mut self, volume: u32) -> Fallible<()> {
+        self.run(&["set-sink-volume", DEFAULT_SINK, &format!("{}%", volume)])?;
+        Ok(())
+    }
+
+    fn set_muted(&mut self, muted: bool) -> Fallible<()> {
+        let muted = if muted { "1" } else { "0" };
+        self.run(&["set-sink-mute", DEFAULT_SINK, muted])?;
+        Ok(())
+    }
+
+    fn subscribe(&mut self, events: Sender<()>) -> Fallible<()> {
+        let (child, stdout) = Self::spawn(&self.program)?;
+        self.subscription.lock().unwrap().child = Some(child);
+
+        let (program, subscription) =
+            (self.program.clone(), self.subscription.clone());
+        thread::spawn(move || {
+            Self::listen(&program, &subscription, stdout, &events)
+        });
+        Ok(())
+    }
+}
+
+impl Drop for Pactl {
+    fn drop(&mut self) {
+        let mut subscription = self.subscription.lock().unwrap();
+        subscription.stopped = true;
+        if let Some(mut child) = subscription.child.take() {
+            child.kill().ok();
+            child.wait().ok();
+        }
+    }
+}
+
+/// A segment showing the volume and mute state of the default sink, which
+/// only queries the backend on server events
+pub struct Volume {
+    id: String,
+    config: VolumeConfig,
+    template: Template,
+    backend: Mutex<Box<dyn AudioBackend>>,
+    events: Mutex<Receiver<()>>,
+    sink: Option<Result<Sink, String>>,
+}
+
+impl Volume {
+    /// Create a new volume segment using `pactl`
+    pub fn new(config: VolumeConfig) -> Fallible<Self> {
+        Self::with_backend(config, Box::new(Pactl::default()))
+    }
+
+    /// Create a new volume segment using the provided backend, which shows
+    /// the sink as unavailable if the backend is
+    pub fn with_backend(
+        config: VolumeConfig,
+        mut backend: Box<dyn AudioBackend>,
+    ) -> Fallible<Self> {
+        let template: Template = config.format.parse()?;
+        template.validate(PLACEHOLDERS)?;
+        let (tx, rx) = mpsc::channel();
+        if let Err(e) = backend.subscribe(tx) {
+            warn!("Unable to subscribe to sound server events: {}", e);
+        }
+        Ok(Self {
+            id: Uuid::new_v4().to_string(),
+            config,
+            template,
+            backend: Mutex::new(backend),
+            events: Mutex::new(rx),
+            sink: None,
+        })
+    }
+
+    /// Apply a change to the cached sink and the backend
+    fn change<F>(&mut self, f: F) -> Fallible<()>
+    where
+        F: FnOnce(&mut Sink),
+    {
+        let mut backend = self.backend.lock().unwrap();
+        let old = match self.sink {
+            Some(Ok(sink)) => sink,
+            _ => backend.sink()?,
+        };
+        let mut new = old;
+        f(&mut new);
+
+        if new.volume != old.volume {
+            backend.set_volume(new.volume)?;
+        }
+        if new.muted != old.muted {
+            backend.set_muted(new.muted)?;
+        }
+        self.sink = Some(Ok(new));
+        Ok(())
+    }
+}
+
+impl Segment for Volume {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn update(&mut self) -> Fallible<SegmentOutput> {
+        // Failures are kept until the next event as well
+        let changed = self.events.lock().unwrap().try_iter().count() > 0;
+        if changed || self.sink.is_none() {
+            let sink = self.backend.lock().unwrap().sink();
+            if let Err(e) = &sink {
+                debug!("Sound server unavailable: {}", e);
+            }
+            self.sink = Some(sink.map_err(|e| e.to_string()));
+        }
+        let sink = match self.sink {
+            Some(Ok(sink)) => sink,
+            _ => {
+                return Ok(SegmentOutput::new(UNAVAILABLE)
+                    .with_color(ColorScheme::selection()))
+            }
+        };
+
+        let icon = match sink.volume {
+            _ if sink.muted => "\u{f026}",
+            0..=49 => "\u{f027}",
+            _ => "\u{f028}",
+        };
+        let output = self
+            .template
+            .output(&[
+                ("icon", icon.into()),
+                ("volume", u64::from(sink.volume).into()),
+                ("muted", u64::from(sink.muted).into()),
+            ])
+            .with_value(f64::from(sink.volume));
+        Ok(if sink.muted {
+            output.with_color(ColorScheme::selection())
+        } else {
+            output
+        })
+    }
+
+    fn click(&mut self, button: Button) {
+        let (step, max) =
+            (u32::from(self.config.step), u32::from(self.config.max));
+        let result = match button {
+            Button::Left => self.change(|s| s.muted = !s.muted),
+            Button::ScrollUp => self.change(|s| {
+                if s.volume < max {
+                    s.volume = (s.volume + step).min(max)
+                }
+            }),
+            Button::ScrollDown => {
+                self.change(|s| s.volume = s.volume.saturating_sub(step))
+            }
+            _ => Ok(()),
+        };
+        if let Err(e) = result {
+            warn!("Unable to change the volume: {}", e);
+        }
+    }
+
+    fn width(&self) -> u16 {
+        self.config.width
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::audio::Backend;
+    use std::{
+        env, fs, os::unix::fs::PermissionsExt, sync::mpsc::RecvTimeoutError,
+    };
+
+    fn volume(backend: &Backend) -> Volume {
+        Volume::with_backend(VolumeConfig::default(), Box::new(backend.clone()))
+            .unwrap()
+    }
+
+    #[test]
+    fn events() {
+        let backend = Backend::new(Sink {
+            volume: 30,
+            muted: false,
+        });
+        let mut segment = volume(&backend);
+
+        let output = segment.update().unwrap();
+        assert_eq!(output.text, "\u{f027} 30%");
+        assert_eq!(output.value, Some(30.));
+        assert_eq!(output.color, None);
+        assert_eq!(backend.queries(), 1);
+
+        // The backend is only queried on events
+        segment.update().unwrap();
+        assert_eq!(backend.queries(), 1);
+
+        backend.change(|s| s.volume = 80);
+        assert_eq!(segment.update().unwrap().text, "\u{f027} 30%");
+        backend.notify();
+        assert_eq!(segment.update().unwrap().text, "\u{f028} 80%");
+        assert_eq!(backend.queries(), 2);
+
+        backend.change(|s| s.muted = true);
+        backend.notify();
+        let output = segment.update().unwrap();
+        assert_eq!(output.text, "\u{f026} 80%");
+        assert_eq!(output.color, Some(ColorScheme::selection()));
+    }
+
+    #[test]
+    fn format() {
+        let backend = Backend::new(Sink {
+            volume: 45,
+            muted: true,
+        });
+        let config = VolumeConfig {
+            format: "{volume:>3}%{?muted} (muted){/}".into(),
+            ..VolumeConfig::default()
+        };
+        let mut segment =
+            Volume::with_backend(config, Box::new(backend.clone())).unwrap();
+        assert_eq!(segment.update().unwrap().text, " 45% (muted)");
+
+        let config = VolumeConfig {
+            format: "{level}".into(),
+            ..VolumeConfig::default()
+        };
+        assert!(Volume::with_backend(config, Box::new(backend)).is_err());
+    }
+
+    #[test]
+    fn scroll() {
+        let backend = Backend::new(Sink {
+            volume: 93,
+            muted: false,
+        });
+        let mut segment = volume(&backend);
+        segment.update().unwrap();
+
+        segment.click(Button::ScrollUp);
+        assert_eq!(backend.sink().volume, 98);
+        segment.click(Button::ScrollUp);
+        assert_eq!(backend.sink().volume, 100);
+        segment.click(Button::ScrollUp);
+        assert_eq!(backend.sink().volume, 100);
+        assert_eq!(segment.update().unwrap().text, "\u{f028} 100%");
+
+        // Volumes above the maximum are never raised but can be lowered
+        backend.change(|s| s.volume = 120);
+        backend.notify();
+        segment.update().unwrap();
+        segment.click(Button::ScrollUp);
+        assert_eq!(backend.sink().volume, 120);
+        segment.click(Button::ScrollDown);
+        assert_eq!(backend.sink().volume, 115);
+
+        backend.change(|s| s.volume = 3);
+        backend.notify();
+        segment.update().unwrap();
+        segment.click(Button::ScrollDown);
+        assert_eq!(backend.sink().volume, 0);
+        assert_eq!(segment.update().unwrap().text, "\u{f027} 0%");
+    }
+
+    #[test]
+    fn mute() {
+        let backend = Backend::new(Sink::default());
+        let mut segment = volume(&backend);
+
+        segment.click(Button::Left);
+        assert!(backend.sink().muted);
+        segment.click(Button::Right);
+        assert!(backend.sink().muted);
+        segment.click(Button::Left);
+        assert!(!backend.sink().muted);
+    }
+
+    #[test]
+    fn unavailable() {
+        let backend = Backend::new(Sink::default());
+        backend.fail(true);
+        let mut segment = volume(&backend);
+        let output = segment.update().unwrap();
+        assert_eq!(output.text, UNAVAILABLE);
+        assert_eq!(output.value, None);
+        segment.click(Button::Left);
+
+        // The server is queried again on its next event
+        backend.fail(false);
+        assert_eq!(segment.update().unwrap().text, UNAVAILABLE);
+        backend.notify();
+        assert_eq!(segment.update().unwrap().text, "\u{f027} 0%");
+
+        // A stopped server replaces the outdated volume
+        backend.fail(true);
+        backend.notify();
+        assert_eq!(segment.update().unwrap().text, UNAVAILABLE);
+        assert_eq!(backend.queries(), 1);
+
+        // Missing programs are no configuration errors
+        let pactl = Pactl {
+            program: "unibar-missing-pactl".into(),
+            subscription: Arc::default(),
+        };
+        let config = VolumeConfig {
+            width: 120,
+            ..VolumeConfig::default()
+        };
+        let mut segment =
+            Volume::with_backend(config, Box::new(pactl)).unwrap();
+        assert_eq!(segment.update().unwrap().text, UNAVAILABLE);
+        assert_eq!(segment.width(), 120);
+    }
+
+    #[test]
+    fn pactl_output() {
+        let output = "Volume: front-left: 32768 /  50% / -18.06 dB,   \
+                      front-right: 32768 /  50% / -18.06 dB\n\
+                      balance 0.00\n";
+        assert_eq!(Pactl::parse_volume(output).unwrap(), 50);
+        assert!(Pactl::parse_volume("Volume:\n").is_err());
+
+        assert!(Pactl::parse_muted("Mute: yes\n").unwrap());
+        assert!(!Pactl::parse_muted("Mute: no\n").unwrap());
+        assert!(Pactl::parse_muted("").is_err());
+
+        assert!(Pactl::is_relevant("Event 'change' on sink #56"));
+        assert!(Pactl::is_relevant("Event 'change' on server #0"));
+        assert!(!Pactl::is_relevant("Event 'change' on sink-input #12"));
+        assert!(!Pactl::is_relevant("Event 'new' on client #80"));
+    }
+
+    #[test]
+    fn resubscribe() {
+        // A pactl which exits like on a restart of the sound server
+        let dir = env::temp_dir().join(format!("unibar-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let program = dir.join("pactl");
+        fs::write(
+            &program,
+            "#!/bin/sh\necho run >> \"$0.runs\"\n\
+             echo \"Event 'change' on sink #1\"\n",
+        )
+        .unwrap();
+        fs::set_permissions(&program, fs::Permissions::from_mode(0o755))
+            .unwrap();
+
+        let mut pactl = Pactl {
+            program: program.to_string_lossy().into_owned(),
+            subscription: Arc::default(),
+        };
+        let (tx, rx) = mpsc::channel();
+        pactl.subscribe(tx).unwrap();
+
+        // The event, the loss, the resubscription and the event again
+        for _ in 0..4 {
+            rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        }
+        let runs = fs::read_to_string(dir.join("pactl.runs")).unwrap();
+        assert!(runs.lines().count() >= 2);
+
+        // The subscription stops with the backend
+        drop(pactl);
+        loop {
+            match rx.recv_timeout(Duration::from_secs(5)) {
+                Ok(()) => {}
+                Err(e) => {
+                    assert_eq!(e, RecvTimeoutError::Disconnected);
+                    break;
+                }
+            }
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
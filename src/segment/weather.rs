@@ -0,0 +1,616 @@
+//! A segment showing the current weather of a HTTP provider
+
+use crate::{
+    color::ColorScheme,
+    http,
+    segment::{Icon, Segment, SegmentOutput},
+    template::{Template, Value},
+};
+use chrono::{Local, TimeZone, Utc};
+use failure::{format_err, Fallible};
+use log::{debug, warn};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value as Json;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    sync::{mpsc::Receiver, Mutex},
+    time::Duration,
+};
+use uuid::Uuid;
+
+/// The placeholders available within the format
+const PLACEHOLDERS: &[&str] =
+    &["temperature", "humidity", "wind", "condition", "updated"];
+
+/// The sprite sheet containing an icon per condition
+const SPRITES: &str = "sprites/weather.png";
+
+/// The configuration of a weather segment
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct WeatherConfig {
+    /// The name to address the segment with
+    #[serde(default = "WeatherConfig::default_name")]
+    pub name: String,
+
+    /// The service to retrieve the weather from
+    pub provider: ProviderConfig,
+
+    /// The minutes between two updates
+    #[serde(default = "WeatherConfig::default_interval")]
+    pub interval: u64,
+
+    /// The file keeping the last report, `$XDG_CACHE_HOME/unibar/<name>.json`
+    /// if not set
+    #[serde(default)]
+    pub cache: Option<PathBuf>,
+
+    /// The displayed text, where the placeholders `temperature`,
+    /// `humidity`, `wind`, `condition` and `updated` are available
+    #[serde(default = "WeatherConfig::default_format")]
+    pub format: String,
+
+    /// The minimum width of the segment in pixels
+    #[serde(default = "WeatherConfig::default_width")]
+    pub width: u16,
+}
+
+impl WeatherConfig {
+    /// Create a new configuration with default values for the provider
+    pub fn new(provider: ProviderConfig) -> Self {
+        Self {
+            name: Self::default_name(),
+            provider,
+            interval: Self::default_interval(),
+            cache: None,
+            format: Self::default_format(),
+            width: Self::default_width(),
+        }
+    }
+
+    fn default_name() -> String {
+        "weather".to_owned()
+    }
+
+    fn default_interval() -> u64 {
+        15
+    }
+
+    fn default_format() -> String {
+        "{temperature:.0}°C".to_owned()
+    }
+
+    fn default_width() -> u16 {
+        100
+    }
+}
+
+/// The supported weather services
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum ProviderConfig {
+    /// The forecast API of Open-Meteo
+    OpenMeteo(OpenMeteo),
+
+    /// The JSON output of wttr.in
+    Wttr(Wttr),
+}
+
+impl ProviderConfig {
+    fn provider(&self) -> Box<dyn Provider> {
+        match self {
+            ProviderConfig::OpenMeteo(p) => Box::new(p.clone()),
+            ProviderConfig::Wttr(p) => Box::new(p.clone()),
+        }
+    }
+}
+
+/// A service reporting the current weather
+pub trait Provider: Send {
+    /// The URL to request the current weather from
+    fn url(&self) -> String;
+
+    /// Parse the response of the service
+    fn parse(&self, body: &str) -> Fallible<Conditions>;
+}
+
+/// The weather conditions in metric units
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Conditions {
+    /// The temperature in degree Celsius
+    pub temperature: f64,
+
+    /// The relative humidity in percent
+    pub humidity: f64,
+
+    /// The wind speed in km/h
+    pub wind: f64,
+
+    /// The kind of weather
+    pub condition: Condition,
+}
+
+/// The kinds of weather, which have an icon each
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Condition {
+    /// A clear sky
+    Clear,
+
+    /// A partly covered sky
+    PartlyCloudy,
+
+    /// An overcast sky
+    Cloudy,
+
+    /// Fog or mist
+    Fog,
+
+    /// Light rain
+    Drizzle,
+
+    /// Rain or showers
+    Rain,
+
+    /// Snow, sleet or hail
+    Snow,
+
+    /// A thunderstorm
+    Thunderstorm,
+}
+
+impl Condition {
+    /// Map a WMO weather interpretation code, where unknown codes are cloudy
+    pub fn from_wmo(code: u64) -> Self {
+        match code {
+            0 => Condition::Clear,
+            1 | 2 => Condition::PartlyCloudy,
+            45 | 48 => Condition::Fog,
+            51..=57 => Condition::Drizzle,
+            61..=67 | 80..=82 => Condition::Rain,
+            71..=77 | 85 | 86 => Condition::Snow,
+            95..=99 => Condition::Thunderstorm,
+            _ => Condition::Cloudy,
+        }
+    }
+
+    /// Map a World Weather Online code, where unknown codes are cloudy
+    pub fn from_wwo(code: u64) -> Self {
+        match code {
+            113 => Condition::Clear,
+            116 => Condition::PartlyCloudy,
+            143 | 248 | 260 => Condition::Fog,
+            176 | 263 | 266 | 281 | 284 | 293 | 296 | 353 => Condition::Drizzle,
+            299 | 302 | 305 | 308 | 311 | 314 | 356 | 359 => Condition::Rain,
+            179 | 182 | 185 | 227 | 230 | 317..=350 | 362..=377 => {
+                Condition::Snow
+            }
+            200 | 386..=395 => Condition::Thunderstorm,
+            _ => Condition::Cloudy,
+        }
+    }
+
+    /// The position of the icon within the sprite sheet
+    fn sprite(self) -> usize {
+        self as usize
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            Condition::Clear => "Clear",
+            Condition::PartlyCloudy => "Partly cloudy",
+            Condition::Cloudy => "Cloudy",
+            Condition::Fog => "Fog",
+            Condition::Drizzle => "Drizzle",
+            Condition::Rain => "Rain",
+            Condition::Snow => "Snow",
+            Condition::Thunderstorm => "Thunderstorm",
+        }
+    }
+}
+
+/// The location of an Open-Meteo forecast
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct OpenMeteo {
+    /// The latitude in degrees
+    pub latitude: f64,
+
+    /// The longitude in degrees
+    pub longitude: f64,
+
+    /// The URL of the forecast API
+    #[serde(default = "OpenMeteo::default_url")]
+    pub url: String,
+}
+
+impl OpenMeteo {
+    fn default_url() -> String {
+        "https://api.open-meteo.com/v1/forecast".to_owned()
+    }
+}
+
+impl Provider for OpenMeteo {
+    fn url(&self) -> String {
+        format!(
+            "{}?latitude={}&longitude={}&current=temperature_2m,\
+             relative_humidity_2m,weather_code,wind_speed_10m",
+            self.url, self.latitude, self.longitude
+        )
+    }
+
+    fn parse(&self, body: &str) -> Fallible<Conditions> {
+        let json: Json = serde_json::from_str(body)?;
+        let current = &json["current"];
+        Ok(Conditions {
+            temperature: number(&current["temperature_2m"])?,
+            humidity: number(&current["relative_humidity_2m"])?,
+            wind: number(&current["wind_speed_10m"])?,
+            condition: Condition::from_wmo(
+                number(&current["weather_code"])? as u64
+            ),
+        })
+    }
+}
+
+/// The location of a wttr.in report
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Wttr {
+    /// The location like a city or an airport code
+    pub location: String,
+
+    /// The URL of the service
+    #[serde(default = "Wttr::default_url")]
+    pub url: String,
+}
+
+impl Wttr {
+    fn default_url() -> String {
+        "https://wttr.in".to_owned()
+    }
+}
+
+impl Provider for Wttr {
+    fn url(&self) -> String {
+        let location: String = self
+            .location
+            .bytes()
+            .map(|b| match b {
+                b' ' => "+".to_owned(),
+                b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'.' => {
+                    char::from(b).to_string()
+                }
+                b => format!("%{:02X}", b),
+            })
+            .collect();
+        format!("{}/{}?format=j1", self.url, location)
+    }
+
+    fn parse(&self, body: &str) -> Fallible<Conditions> {
+        let json: Json = serde_json::from_str(body)?;
+        let current = &json["current_condition"][0];
+        Ok(Conditions {
+            temperature: number(&current["temp_C"])?,
+            humidity: number(&current["humidity"])?,
+            wind: number(&current["windspeedKmph"])?,
+            condition: Condition::from_wwo(
+                number(&current["weatherCode"])? as u64
+            ),
+        })
+    }
+}
+
+/// Retrieve a number of a JSON response, which may be a string as well
+fn number(value: &Json) -> Fallible<f64> {
+    match value {
+        Json::Number(n) => n.as_f64(),
+        Json::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+    .ok_or_else(|| format_err!("invalid or missing number '{}'", value))
+}
+
+/// The conditions and the time they got retrieved as UNIX timestamp
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+struct Report {
+    time: i64,
+    conditions: Conditions,
+}
+
+/// The current weather, which gets retrieved on a background thread and
+/// shows the cached report until the first update arrives
+pub struct Weather {
+    id: String,
+    config: WeatherConfig,
+    template: Template,
+    updates: Mutex<Receiver<Report>>,
+    report: Option<Report>,
+}
+
+impl Weather {
+    /// Create a new instance, which starts polling the provider immediately
+    pub fn new(config: WeatherConfig) -> Fallible<Self> {
+        let template: Template = config.format.parse()?;
+        template.validate(PLACEHOLDERS)?;
+
+        let cache = config.cache.clone().or_else(|| {
+            env::var_os("XDG_CACHE_HOME")
+                .map(PathBuf::from)
+                .or_else(|| {
+                    env::var_os("HOME").map(|h| Path::new(&h).join(".cache"))
+                })
+                .map(|d| d.join("unibar").join(format!("{}.json", config.name)))
+        });
+        let report = cache.as_ref().and_then(|path| match Self::load(path) {
+            Ok(report) => Some(report),
+            Err(e) => {
+                debug!("No cached weather in {}: {}", path.display(), e);
+                None
+            }
+        });
+
+        let provider = config.provider.provider();
+        let url = provider.url();
+        let updates = http::poll(
+            Duration::from_secs(config.interval.max(1) * 60),
+            move || {
+                let report = Report {
+                    time: Utc::now().timestamp(),
+                    conditions: provider.parse(&http::get(&url, &[])?)?,
+                };
+                if let Some(path) = &cache {
+                    if let Err(e) = Self::store(path, &report) {
+                        warn!("Unable to cache the weather: {}", e);
+                    }
+                }
+                Ok(report)
+            },
+        );
+
+        Ok(Self {
+            id: Uuid::new_v4().to_string(),
+            config,
+            template,
+            updates: Mutex::new(updates),
+            report,
+        })
+    }
+
+    fn load(path: &Path) -> Fallible<Report> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    fn store(path: &Path, report: &Report) -> Fallible<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string(report)?)?;
+        Ok(())
+    }
+}
+
+impl Segment for Weather {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn update(&mut self) -> Fallible<SegmentOutput> {
+        if let Some(report) = self.updates.lock().unwrap().try_iter().last() {
+            self.report = Some(report);
+        }
+        let report = match &self.report {
+            Some(report) => report,
+            None => return Ok(SegmentOutput::default().with_hidden(true)),
+        };
+
+        let conditions = &report.conditions;
+        let output = self.template.output(&[
+            ("temperature", conditions.temperature.into()),
+            ("humidity", conditions.humidity.into()),
+            ("wind", conditions.wind.into()),
+            ("condition", conditions.condition.description().into()),
+            (
+                "updated",
+                Value::Text(
+                    Local
+                        .timestamp_opt(report.time, 0)
+                        .single()
+                        .map(|t| t.format("%H:%M").to_string())
+                        .unwrap_or_default(),
+                ),
+            ),
+        ]);
+
+        // Reports missing two updates in a row are dimmed
+        let output = output
+            .with_value(conditions.temperature)
+            .with_leading_icons(vec![Icon::Sprite {
+                sheet: SPRITES.into(),
+                index: conditions.condition.sprite(),
+            }]);
+        let age = Utc::now().timestamp() - report.time;
+        Ok(if age > 2 * 60 * self.config.interval.max(1) as i64 {
+            output.with_color(ColorScheme::selection())
+        } else {
+            output
+        })
+    }
+
+    fn width(&self) -> u16 {
+        self.config.width
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{
+        http::Server,
+        sys::Root,
+        wait::{wait_for, wait_until},
+    };
+
+    const OPEN_METEO: &str = r#"{
+        "latitude": 52.52,
+        "longitude": 13.419998,
+        "current_units": {"temperature_2m": "°C"},
+        "current": {
+            "time": "2026-10-18T12:00",
+            "interval": 900,
+            "temperature_2m": 12.6,
+            "relative_humidity_2m": 81,
+            "weather_code": 61,
+            "wind_speed_10m": 14.3
+        }
+    }"#;
+
+    fn config(server: &Server, root: &Root) -> WeatherConfig {
+        WeatherConfig {
+            cache: Some(root.source().path("/cache/weather.json")),
+            ..WeatherConfig::new(ProviderConfig::OpenMeteo(OpenMeteo {
+                latitude: 52.52,
+                longitude: 13.41,
+                url: server.url("/v1/forecast"),
+            }))
+        }
+    }
+
+    fn path() -> &'static str {
+        "/v1/forecast?latitude=52.52&longitude=13.41&current=temperature_2m,\
+         relative_humidity_2m,weather_code,wind_speed_10m"
+    }
+
+    #[test]
+    fn open_meteo() {
+        let (server, root) = (Server::start(), Root::new());
+        server.respond(path(), 200, OPEN_METEO);
+
+        let mut segment = Weather::new(WeatherConfig {
+            format: "{temperature:.1}°C {humidity}% {wind:.0}km/h {condition}"
+                .into(),
+            ..config(&server, &root)
+        })
+        .unwrap();
+        let output = wait_for(&mut segment, |o| !o.hidden);
+        assert_eq!(output.text, "12.6°C 81% 14km/h Rain");
+        assert_eq!(output.value, Some(12.6));
+        assert_eq!(output.color, None);
+        assert_eq!(
+            output.icons,
+            vec![Icon::Sprite {
+                sheet: SPRITES.into(),
+                index: 5,
+            }]
+        );
+        assert!(output.leading_icons);
+        assert_eq!(server.requests(), vec![path()]);
+
+        // The report got cached
+        let report =
+            Weather::load(&root.source().path("/cache/weather.json")).unwrap();
+        assert_eq!(report.conditions.condition, Condition::Rain);
+    }
+
+    #[test]
+    fn stale_cache() {
+        let (server, root) = (Server::start(), Root::new());
+        let report = Report {
+            time: Utc::now().timestamp() - 3600,
+            conditions: Conditions {
+                temperature: -2.4,
+                humidity: 90.,
+                wind: 3.,
+                condition: Condition::Snow,
+            },
+        };
+        Weather::store(&root.source().path("/cache/weather.json"), &report)
+            .unwrap();
+
+        // The provider is unreachable, which keeps the cached report
+        let mut segment = Weather::new(config(&server, &root)).unwrap();
+        let output = segment.update().unwrap();
+        assert_eq!(output.text, "-2°C");
+        assert_eq!(output.color, Some(ColorScheme::selection()));
+
+        wait_until(|| !server.requests().is_empty());
+        assert_eq!(segment.update().unwrap(), output);
+    }
+
+    #[test]
+    fn missing_report() {
+        let (server, root) = (Server::start(), Root::new());
+        server.respond(path(), 200, "{}");
+        let mut segment = Weather::new(config(&server, &root)).unwrap();
+        assert!(segment.update().unwrap().hidden);
+
+        assert!(Weather::new(WeatherConfig {
+            format: "{pressure}".into(),
+            ..config(&server, &root)
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn wttr() {
+        let wttr = Wttr {
+            location: "New York".into(),
+            url: Wttr::default_url(),
+        };
+        assert_eq!(wttr.url(), "https://wttr.in/New+York?format=j1");
+        assert_eq!(
+            Wttr {
+                location: "Zürich".into(),
+                url: "http://localhost".into(),
+            }
+            .url(),
+            "http://localhost/Z%C3%BCrich?format=j1"
+        );
+
+        let conditions = wttr
+            .parse(
+                r#"{"current_condition": [{"temp_C": "21", "humidity": "40",
+                   "weatherCode": "116", "windspeedKmph": "9"}]}"#,
+            )
+            .unwrap();
+        assert_eq!(
+            conditions,
+            Conditions {
+                temperature: 21.,
+                humidity: 40.,
+                wind: 9.,
+                condition: Condition::PartlyCloudy,
+            }
+        );
+        assert!(wttr.parse(r#"{"current_condition": []}"#).is_err());
+        assert!(wttr.parse("<html>").is_err());
+    }
+
+    #[test]
+    fn conditions() {
+        assert_eq!(Condition::from_wmo(0), Condition::Clear);
+        assert_eq!(Condition::from_wmo(3), Condition::Cloudy);
+        assert_eq!(Condition::from_wmo(48), Condition::Fog);
+        assert_eq!(Condition::from_wmo(81), Condition::Rain);
+        assert_eq!(Condition::from_wmo(86), Condition::Snow);
+        assert_eq!(Condition::from_wmo(96), Condition::Thunderstorm);
+        assert_eq!(Condition::from_wwo(338), Condition::Snow);
+        assert_eq!(Condition::from_wwo(389), Condition::Thunderstorm);
+        assert_eq!(Condition::from_wwo(1), Condition::Cloudy);
+        assert_eq!(Condition::Thunderstorm.sprite(), 7);
+
+        let config: ProviderConfig =
+            serde_yaml::from_str("type: open-meteo\nlatitude: 1\nlongitude: 2")
+                .unwrap();
+        assert_eq!(
+            config,
+            ProviderConfig::OpenMeteo(OpenMeteo {
+                latitude: 1.,
+                longitude: 2.,
+                url: OpenMeteo::default_url(),
+            })
+        );
+    }
+}
@@ -0,0 +1,47 @@
+//! Access to system data like `/proc` and `/sys` below a configurable root
+
+use failure::Fallible;
+use std::{
+    fs::{self, File},
+    io::{prelude::*, BufReader},
+    path::PathBuf,
+};
+
+/// A source of system data, which resolves absolute paths below its root
+#[derive(Clone, Debug)]
+pub struct SysSource {
+    root: PathBuf,
+}
+
+impl Default for SysSource {
+    fn default() -> Self {
+        Self::new("/")
+    }
+}
+
+impl SysSource {
+    /// Create a new source for the provided root directory
+    pub fn new<P>(root: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        Self { root: root.into() }
+    }
+
+    /// Resolve an absolute system path like `/proc/stat` below the root
+    pub fn path(&self, path: &str) -> PathBuf {
+        self.root.join(path.trim_start_matches('/'))
+    }
+
+    /// Read the whole content of a system path
+    pub fn read_to_string(&self, path: &str) -> Fallible<String> {
+        Ok(fs::read_to_string(self.path(path))?)
+    }
+
+    /// Retrieve an iterator over all lines of a system path
+    pub fn lines(&self, path: &str) -> Fallible<impl Iterator<Item = String>> {
+        Ok(BufReader::new(File::open(self.path(path))?)
+            .lines()
+            .scan((), |_, x| x.ok()))
+    }
+}
@@ -1,78 +1,234 @@
 //! Global state handling for the bar
 
 use crate::{
+    assets::Assets,
     color::ColorScheme,
-    segment::{Cpu, Date, Segment},
+    control::Control,
+    font::Fonts,
+    ipc::{Ipc, Request, Response},
+    layout::Layout,
+    segment::{Icon, Segments},
+    system::Run,
 };
 use amethyst::{
     assets::{AssetStorage, Loader},
     core::Transform,
-    ecs::World,
+    ecs::{Entity, World},
     input::{is_close_requested, is_key_down},
     prelude::{
         Builder, GameData, SimpleState, SimpleTrans, StateData, StateEvent,
         Trans,
     },
     renderer::{
-        Camera, PngFormat, Projection, Texture, TextureHandle, TextureMetadata,
+        Camera, HiddenPropagate, PngFormat, Projection, Texture, TextureData,
+        TextureHandle, TextureMetadata, WindowMessages,
+    },
+    ui::{
+        Anchor, FontAsset, FontHandle, Interactable, UiButtonBuilder, UiFinder,
+        UiTransform,
     },
-    ui::{Anchor, FontHandle, TtfFormat, UiButtonBuilder, UiTransform},
     winit::VirtualKeyCode,
 };
+use failure::{format_err, Fallible};
+use log::warn;
+use png::{BitDepth, ColorType, Decoder};
+use std::{collections::HashMap, path::Path};
 
 /// The state representation of the bar
-pub struct State;
+pub struct State {
+    control: Option<Control>,
+    segments: Option<Segments>,
+    ipc: Ipc,
+    fonts: Option<Fonts>,
+    assets: Assets,
+    icons: HashMap<String, Vec<Icon>>,
+}
 
 impl State {
-    fn init_date_segment(&self, world: &mut World) {
-        // Create a new date object and add it to the world
-        let date = Date::new();
-        self.init_button_segment(world, &date, -100, 190);
-        world.add_resource(date);
-
-        // Add a separator
-        self.add_separator(world, -205.);
+    /// Create a new state which displays the provided segments with the
+    /// fonts and assets and handles the IPC requests
+    pub fn new(
+        control: Control,
+        segments: Segments,
+        ipc: Ipc,
+        fonts: Fonts,
+        assets: Assets,
+    ) -> Self {
+        Self {
+            control: Some(control),
+            segments: Some(segments),
+            ipc,
+            fonts: Some(fonts),
+            assets,
+            icons: HashMap::new(),
+        }
     }
 
-    fn init_cpu_segment(&self, world: &mut World) {
-        // Create a new cpu object and add it to the world
-        let cpu = Cpu::new();
-        self.init_button_segment(world, &cpu, -265, 100);
-        world.add_resource(cpu);
+    fn init_segments(&self, world: &mut World) {
+        // Create a button for every segment
+        let segments: Vec<(String, u16)> = world
+            .read_resource::<Segments>()
+            .iter()
+            .map(|s| (s.id().to_owned(), s.width()))
+            .collect();
+        let widths: Vec<u16> = segments.iter().map(|(_, w)| *w).collect();
+        for ((id, width), (x, separator_x)) in
+            segments.iter().zip(Layout::segments(&widths))
+        {
+            self.init_button_segment(world, id, *width, x);
+            let (font, size) = (self.load_font(world), self.font_size(world));
+            Run::create(world, id, &font, size);
+
+            // Add a separator
+            self.add_separator(world, id, separator_x);
+        }
+    }
 
-        // Add a separator
-        self.add_separator(world, -325.);
+    fn remove_segments(&mut self, world: &mut World, ids: &[String]) {
+        let icons = &self.icons;
+        let entities: Vec<Entity> = world.exec(|finder: UiFinder| {
+            ids.iter()
+                .flat_map(|id| {
+                    let count = icons.get(id).map(Vec::len).unwrap_or(0);
+                    vec![
+                        finder.find(id),
+                        finder.find(&format!("{}_btn_txt", id)),
+                        finder.find(&format!("{}_separator", id)),
+                    ]
+                    .into_iter()
+                    .chain((0..count).map(|n| finder.find(&Self::icon(id, n))))
+                    .chain(Run::names(id).iter().map(|n| finder.find(n)))
+                    .collect::<Vec<_>>()
+                })
+                .flatten()
+                .collect()
+        });
+        self.icons.retain(|id, _| !ids.contains(id));
+        if let Err(e) = world.delete_entities(&entities) {
+            warn!("Unable to remove segments: {:?}", e);
+        }
     }
 
-    fn init_button_segment<T>(
+    fn init_button_segment(
         &self,
         world: &mut World,
-        segment: &T,
-        x: i16,
+        id: &str,
         width: u16,
-    ) where
-        T: Segment,
-    {
-        let builder: UiButtonBuilder<u8> =
-            UiButtonBuilder::new(segment.id(), "");
+        x: f32,
+    ) {
+        let builder: UiButtonBuilder<u8> = UiButtonBuilder::new(id, "");
         builder
             .with_anchor(Anchor::TopRight)
             .with_font(self.load_font(world))
-            .with_font_size(Self::font_size())
+            .with_font_size(self.font_size(world))
             .with_image(self.load_texture("images/background.png", world))
-            .with_position(f32::from(x), -10.)
+            .with_position(x, -10.)
             .with_size(f32::from(width), 20.)
             .with_text_color(ColorScheme::foreground())
             .build_from_world(world);
     }
 
-    fn add_separator(&self, world: &mut World, x: f32) {
+    fn icon(id: &str, index: usize) -> String {
+        format!("{}_icon_{}", id, index)
+    }
+
+    /// Recreate the icon entities of segments whose icons changed, which get
+    /// positioned and shown by the segment system
+    fn sync_icons(&mut self, world: &mut World) {
+        let changed: Vec<(String, Vec<Icon>)> = {
+            let control = world.read_resource::<Control>();
+            world
+                .read_resource::<Segments>()
+                .iter()
+                .map(|s| {
+                    let icons = control
+                        .output(s.id())
+                        .map(|o| o.icons.clone())
+                        .unwrap_or_default();
+                    (s.id().to_owned(), icons)
+                })
+                .filter(|(id, icons)| {
+                    self.icons.get(id).unwrap_or(&vec![]) != icons
+                })
+                .collect()
+        };
+
+        for (id, icons) in changed {
+            let count = self.icons.get(&id).map(Vec::len).unwrap_or(0);
+            let entities: Vec<Entity> = world.exec(|finder: UiFinder| {
+                (0..count)
+                    .filter_map(|n| finder.find(&Self::icon(&id, n)))
+                    .collect()
+            });
+            if let Err(e) = world.delete_entities(&entities) {
+                warn!("Unable to remove icons: {:?}", e);
+            }
+
+            for (n, icon) in icons.iter().enumerate() {
+                let transform = UiTransform::new(
+                    Self::icon(&id, n),
+                    Anchor::TopRight,
+                    0.,
+                    -10.,
+                    2.,
+                    Layout::icon_size(),
+                    Layout::icon_size(),
+                );
+                let image = self.load_icon(icon, world);
+                world
+                    .create_entity()
+                    .with(transform)
+                    .with(image)
+                    .with(Interactable)
+                    .with(HiddenPropagate)
+                    .build();
+            }
+            self.icons.insert(id, icons);
+        }
+    }
+
+    /// Handle an IPC request and apply its changes to the world
+    fn handle(&mut self, world: &mut World, request: Request) -> Response {
+        let ids: Vec<String> = world
+            .read_resource::<Segments>()
+            .iter()
+            .map(|s| s.id().to_owned())
+            .collect();
+        let (reload, toggle) =
+            (request == Request::Reload, request == Request::Toggle);
+
+        let response = world
+            .write_resource::<Control>()
+            .handle(request, &mut world.write_resource::<Segments>());
+
+        if response.success && reload {
+            self.remove_segments(world, &ids);
+            self.init_segments(world);
+        }
+        if response.success && toggle {
+            let visible = world.read_resource::<Control>().is_visible();
+            world
+                .write_resource::<WindowMessages>()
+                .send_command(
+                    move |w| {
+                        if visible {
+                            w.show()
+                        } else {
+                            w.hide()
+                        }
+                    },
+                );
+        }
+        response
+    }
+
+    fn add_separator(&self, world: &mut World, id: &str, x: f32) {
         // Load the image
         let image = self.load_texture("images/separator.png", world);
 
         // Build the transform
         let transform = UiTransform::new(
-            "separator".to_string(),
+            format!("{}_separator", id),
             Anchor::TopRight,
             x,
             -10.,
@@ -85,6 +241,32 @@ impl State {
         world.create_entity().with(transform).with(image).build();
     }
 
+    /// Create the hidden lines of the popup below the bar, which are shared
+    /// by all segments
+    fn init_popup(&self, world: &mut World) {
+        for line in 0..Layout::popup_lines() {
+            let name = format!("popup_{}", line);
+            let (font, size) = (self.load_font(world), self.font_size(world));
+            Run::create(world, &name, &font, size);
+            let transform = UiTransform::new(
+                name,
+                Anchor::TopRight,
+                0.,
+                Layout::popup_y(line),
+                1.,
+                Layout::popup_width(),
+                Layout::height() as f32,
+            );
+            let image = self.load_texture("images/background.png", world);
+            world
+                .create_entity()
+                .with(transform)
+                .with(image)
+                .with(HiddenPropagate)
+                .build();
+        }
+    }
+
     fn init_camera(&self, world: &mut World) {
         let mut transform = Transform::default();
         transform.set_z(1.0);
@@ -99,29 +281,96 @@ impl State {
     where
         N: Into<String>,
     {
-        let loader = world.read_resource::<Loader>();
-        loader.load(
-            name,
-            PngFormat,
-            // Screen pixel will be taken from nearest pixel of texture
-            TextureMetadata::srgb_scale(),
-            (),
+        self.assets.texture(
+            &name.into(),
+            &world.read_resource::<Loader>(),
             &world.read_resource::<AssetStorage<Texture>>(),
         )
     }
 
+    /// Load the texture of an icon, either from a PNG file or decoded pixels
+    pub fn load_icon(&self, icon: &Icon, world: &World) -> TextureHandle {
+        let loader = world.read_resource::<Loader>();
+        let storage = world.read_resource::<AssetStorage<Texture>>();
+        match icon {
+            Icon::File(path) => loader.load(
+                path.to_string_lossy(),
+                PngFormat,
+                TextureMetadata::srgb_scale(),
+                (),
+                &storage,
+            ),
+            Icon::Sprite { sheet, index } => {
+                match self.load_sprite(sheet, *index) {
+                    Ok(icon) => self.load_icon(&icon, world),
+                    Err(e) => {
+                        warn!(
+                            "Unable to load sprite {} of {}: {}",
+                            index,
+                            sheet.display(),
+                            e
+                        );
+                        loader.load_from_data(
+                            TextureData::color([0.; 4]),
+                            (),
+                            &storage,
+                        )
+                    }
+                }
+            }
+            Icon::Rgba {
+                width,
+                height,
+                data,
+            } => loader.load_from_data(
+                TextureData::U8(
+                    data.clone(),
+                    TextureMetadata::srgb_scale()
+                        .with_size(*width as u16, *height as u16),
+                ),
+                (),
+                &storage,
+            ),
+        }
+    }
+
+    /// Cut a square sprite out of a horizontal sheet within the assets
+    fn load_sprite(&self, sheet: &Path, index: usize) -> Fallible<Icon> {
+        let sheet = sheet.to_string_lossy();
+        let data = self.assets.read(&sheet)?;
+        let (info, mut reader) = Decoder::new(&data[..]).read_info()?;
+        let mut data = vec![0; info.buffer_size()];
+        reader.next_frame(&mut data)?;
+        if info.color_type != ColorType::RGBA
+            || info.bit_depth != BitDepth::Eight
+        {
+            return Err(format_err!("sprite sheets have to be 8 bit RGBA"));
+        }
+
+        let size = info.height as usize;
+        let (width, left) = (info.width as usize, index * size);
+        if left + size > width {
+            return Err(format_err!("no sprite {} in {}", index, sheet));
+        }
+        let data = data
+            .chunks(width * 4)
+            .flat_map(|row| row[left * 4..(left + size) * 4].to_vec())
+            .collect();
+        Ok(Icon::Rgba {
+            width: size as u32,
+            height: size as u32,
+            data,
+        })
+    }
+
+    /// Retrieve the primary font, which the systems replace by a fallback
+    /// for the runs of characters missing in it
     pub fn load_font(&self, world: &mut World) -> FontHandle {
-        world.read_resource::<Loader>().load(
-            "font/meslo.ttf",
-            TtfFormat,
-            (),
-            (),
-            &world.read_resource(),
-        )
+        world.read_resource::<Fonts>().font_handle(0)
     }
 
-    pub const fn font_size() -> f32 {
-        14.
+    pub fn font_size(&self, world: &mut World) -> f32 {
+        world.read_resource::<Fonts>().size()
     }
 }
 
@@ -129,14 +378,38 @@ impl SimpleState for State {
     fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
         let world = data.world;
 
+        // Add the segments, fonts and assets to the world
+        world.add_resource(self.control.take().unwrap_or_default());
+        world.add_resource(self.segments.take().unwrap_or_default());
+        if let Some(mut fonts) = self.fonts.take() {
+            fonts.register(
+                &world.read_resource::<Loader>(),
+                &world.read_resource::<AssetStorage<FontAsset>>(),
+            );
+            world.add_resource(fonts);
+        }
+        world.add_resource(self.assets.clone());
+
         // Initialize further components
-        self.init_date_segment(world);
-        self.init_cpu_segment(world);
+        self.init_segments(world);
+        self.init_popup(world);
 
         // Initialize the camera
         self.init_camera(world);
     }
 
+    fn update(
+        &mut self,
+        data: &mut StateData<'_, GameData<'_, '_>>,
+    ) -> SimpleTrans {
+        for (request, responder) in self.ipc.poll() {
+            let response = self.handle(data.world, request);
+            responder.send(response).ok();
+        }
+        self.sync_icons(data.world);
+        Trans::None
+    }
+
     fn handle_event(
         &mut self,
         _: StateData<'_, GameData<'_, '_>>,
@@ -0,0 +1,79 @@
+//! Stopping threads, which wait for a file descriptor to become readable
+
+use failure::{format_err, Fallible};
+use std::{
+    fs::File,
+    io::{self, prelude::*},
+    os::unix::io::{AsRawFd, FromRawFd, RawFd},
+};
+
+/// A pipe, which wakes up all threads waiting via `wait` once stopped
+pub struct Stop {
+    read: File,
+    write: File,
+}
+
+impl Stop {
+    /// Create a new pipe
+    pub fn new() -> Fallible<Self> {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+            return Err(format_err!(
+                "unable to create pipe: {}",
+                io::Error::last_os_error()
+            ));
+        }
+        Ok(unsafe {
+            Self {
+                read: File::from_raw_fd(fds[0]),
+                write: File::from_raw_fd(fds[1]),
+            }
+        })
+    }
+
+    /// Stop all current and future waits
+    pub fn stop(&self) {
+        (&self.write).write_all(&[0]).ok();
+    }
+
+    /// Wait until the file descriptor is readable, which returns false if
+    /// stopped
+    pub fn wait(&self, fd: RawFd) -> bool {
+        let poll = |fd| libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let mut fds = [poll(fd), poll(self.read.as_raw_fd())];
+        while unsafe { libc::poll(fds.as_mut_ptr(), 2, -1) } < 0 {
+            if io::Error::last_os_error().kind() != io::ErrorKind::Interrupted {
+                return false;
+            }
+        }
+        fds[1].revents == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{os::unix::net::UnixStream, sync::Arc, thread};
+
+    #[test]
+    fn wait() {
+        let (mut writer, reader) = UnixStream::pair().unwrap();
+        let stop = Arc::new(Stop::new().unwrap());
+        writer.write_all(b"x").unwrap();
+        assert!(stop.wait(reader.as_raw_fd()));
+
+        // Stopping wakes up blocked threads and all later waits
+        let (_writer, reader) = UnixStream::pair().unwrap();
+        let waiting = {
+            let stop = stop.clone();
+            thread::spawn(move || stop.wait(reader.as_raw_fd()))
+        };
+        stop.stop();
+        assert!(!waiting.join().unwrap());
+        assert!(!stop.wait(writer.as_raw_fd()));
+    }
+}
@@ -1,6 +1,11 @@
 //! All available systems
 
 mod segment;
+mod text;
 mod workspace;
 
-pub use self::{segment::SegmentSystem, workspace::WorkspaceSystem};
+pub use self::{
+    segment::SegmentSystem,
+    text::{Run, Texts},
+    workspace::WorkspaceSystem,
+};
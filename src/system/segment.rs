@@ -1,50 +1,457 @@
-use crate::segment::{Cpu, Date, Segment};
+use crate::{
+    assets::Assets,
+    color::ColorScheme,
+    control::Control,
+    font::Fonts,
+    layout::Layout,
+    segment::{Button, SegmentOutput, Segments},
+    system::{Run, Texts},
+};
 use amethyst::{
-    core::{timing::Time, transform::Transform},
-    ecs::{Read, System, Write, WriteStorage},
-    ui::{UiFinder, UiText},
+    assets::{AssetStorage, Loader},
+    core::timing::Time,
+    ecs::{Entity, Read, ReadExpect, System, Write, WriteStorage},
+    renderer::{
+        HiddenPropagate, Texture, TextureData, TextureHandle, WindowMessages,
+    },
+    shrev::{EventChannel, ReaderId},
+    ui::{UiEvent, UiEventType, UiFinder, UiText, UiTransform},
+    winit::{
+        dpi::LogicalSize, ElementState, Event, MouseButton, MouseScrollDelta,
+        WindowEvent,
+    },
 };
+use std::collections::HashMap;
+
+/// The duration of sliding in changed outputs in seconds
+const SLIDE_SECONDS: f64 = 0.3;
+
+/// The duration until an exceeding text scrolls by one character in seconds
+const SCROLL_SECONDS: f64 = 0.2;
 
 #[derive(Default)]
 pub struct SegmentSystem {
     delta: f64,
+    reader_id: Option<ReaderId<UiEvent>>,
+    window_reader_id: Option<ReaderId<Event>>,
+    hovered: Option<Entity>,
+    cursor: (f64, f64),
+    window_position: (f64, f64),
+    animations: HashMap<String, (String, f64)>,
+    backgrounds: HashMap<String, Option<[f32; 4]>>,
+    popup_lines: usize,
 }
 
 impl SegmentSystem {
-    fn button_txt(&self, id: &str) -> String {
-        format!("{}_btn_txt", id)
+    fn separator(&self, id: &str) -> String {
+        format!("{}_separator", id)
+    }
+
+    fn icon(&self, id: &str, index: usize) -> String {
+        format!("{}_icon_{}", id, index)
+    }
+
+    fn popup_line(&self, line: usize) -> String {
+        format!("popup_{}", line)
+    }
+
+    /// Retrieve the button of a window event, whereas left clicks are
+    /// already reported as UI events
+    fn button(event: &WindowEvent) -> Option<Button> {
+        match event {
+            WindowEvent::MouseInput {
+                state: ElementState::Released,
+                button,
+                ..
+            } => match button {
+                MouseButton::Middle => Some(Button::Middle),
+                MouseButton::Right => Some(Button::Right),
+                _ => None,
+            },
+            WindowEvent::MouseWheel { delta, .. } => {
+                let y = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => f64::from(*y),
+                    MouseScrollDelta::PixelDelta(position) => position.y,
+                };
+                if y > 0. {
+                    Some(Button::ScrollUp)
+                } else if y < 0. {
+                    Some(Button::ScrollDown)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Forward a click on the target entity to the corresponding segment
+    /// or icon, returns true if a segment has been clicked
+    fn click(
+        &self,
+        target: Entity,
+        button: Button,
+        segments: &mut Segments,
+        control: &Control,
+        finder: &UiFinder<'_>,
+    ) -> bool {
+        // The cursor position is relative to the window
+        let position = (
+            (self.window_position.0 + self.cursor.0) as i32,
+            (self.window_position.1 + self.cursor.1) as i32,
+        );
+        let mut clicked = false;
+        for segment in segments.iter_mut() {
+            if finder.find(segment.id()) == Some(target) {
+                segment.click_at(button, position);
+                clicked = true;
+            }
+            let count = control
+                .output(segment.id())
+                .map(|o| o.icons.len())
+                .unwrap_or(0);
+            if let Some(index) = (0..count).find(|n| {
+                finder.find(&self.icon(segment.id(), *n)) == Some(target)
+            }) {
+                segment.click_icon_at(button, index, position);
+                clicked = true;
+            }
+        }
+        clicked
+    }
+
+    /// Retrieve the displayed characters and their vertical offset of an
+    /// animated segment, where the time of the latest change is tracked
+    fn animate<T>(
+        &mut self,
+        id: &str,
+        text: &str,
+        chars: &[T],
+        padding: T,
+        width: u16,
+        now: f64,
+    ) -> (Vec<T>, f32)
+    where
+        T: Clone,
+    {
+        let (last, start) = self
+            .animations
+            .entry(id.to_owned())
+            .or_insert_with(|| (text.to_owned(), now));
+        if last != text {
+            *last = text.to_owned();
+            *start = now;
+        }
+
+        let elapsed = now - *start;
+        let offset = (1. - (elapsed / SLIDE_SECONDS).min(1.)) as f32
+            * Layout::height() as f32;
+        let scrolled = ((elapsed - SLIDE_SECONDS) / SCROLL_SECONDS).max(0.);
+        (
+            Layout::scroll(chars, padding, width, scrolled as usize),
+            offset,
+        )
     }
 }
 
 impl<'s> System<'s> for SegmentSystem {
     type SystemData = (
-        WriteStorage<'s, Transform>,
         WriteStorage<'s, UiText>,
+        WriteStorage<'s, UiTransform>,
+        WriteStorage<'s, HiddenPropagate>,
+        WriteStorage<'s, TextureHandle>,
         UiFinder<'s>,
         Read<'s, Time>,
-        Write<'s, Date>,
-        Write<'s, Cpu>,
+        Write<'s, EventChannel<UiEvent>>,
+        Write<'s, EventChannel<Event>>,
+        Write<'s, Segments>,
+        Write<'s, Control>,
+        Write<'s, WindowMessages>,
+        ReadExpect<'s, Fonts>,
+        ReadExpect<'s, Assets>,
+        ReadExpect<'s, Loader>,
+        Read<'s, AssetStorage<Texture>>,
     );
 
     fn run(
         &mut self,
-        (mut _transforms, mut texts, finder, time, mut date, mut cpu): Self::SystemData,
+        (
+            mut texts,
+            mut transforms,
+            mut hidden,
+            mut images,
+            finder,
+            time,
+            mut events,
+            mut window_events,
+            mut segments,
+            mut control,
+            mut window,
+            fonts,
+            assets,
+            loader,
+            texture_storage,
+        ): Self::SystemData,
     ) {
-        // Update all segments every second and on startup
-        if time.absolute_time_seconds() - self.delta >= 1. || self.delta == 0. {
+        // Left clicks are UI events, whereas other buttons and the mouse
+        // wheel are forwarded to the hovered entity
+        let mut clicks = vec![];
+        let reader_id = self
+            .reader_id
+            .get_or_insert_with(|| events.register_reader());
+        for event in events.read(reader_id) {
+            match event.event_type {
+                UiEventType::Click => clicks.push((event.target, Button::Left)),
+                UiEventType::HoverStart => self.hovered = Some(event.target),
+                UiEventType::HoverStop
+                    if self.hovered == Some(event.target) =>
+                {
+                    self.hovered = None
+                }
+                _ => {}
+            }
+        }
+        let window_reader_id = self
+            .window_reader_id
+            .get_or_insert_with(|| window_events.register_reader());
+        for event in window_events.read(window_reader_id) {
+            let event = match event {
+                Event::WindowEvent { event, .. } => event,
+                _ => continue,
+            };
+            match event {
+                WindowEvent::CursorMoved { position, .. } => {
+                    self.cursor = (position.x, position.y)
+                }
+                WindowEvent::Moved(position) => {
+                    self.window_position = (position.x, position.y)
+                }
+                _ => {
+                    if let (Some(target), Some(button)) =
+                        (self.hovered, Self::button(event))
+                    {
+                        clicks.push((target, button))
+                    }
+                }
+            }
+        }
+
+        // Forward the clicks to the corresponding segment
+        let mut clicked = false;
+        for (target, button) in clicks {
+            clicked |=
+                self.click(target, button, &mut segments, &control, &finder);
+        }
+
+        // Update all segments every second, on startup and after clicks
+        if time.absolute_time_seconds() - self.delta >= 1.
+            || self.delta == 0.
+            || clicked
+        {
+            control.update(&mut segments);
+            self.delta = time.absolute_time_seconds();
+        }
+        self.animations
+            .retain(|id, _| segments.iter().any(|s| s.id() == id));
+        self.backgrounds
+            .retain(|id, _| segments.iter().any(|s| s.id() == id));
+
+        // Arrange the visible segments and hide the others
+        let widths: Vec<u16> = segments
+            .iter()
+            .filter(|s| control.is_segment_visible(s.id()))
+            .map(|s| s.width())
+            .collect();
+        let mut positions = Layout::segments(&widths).into_iter();
+        let mut popup: Option<(f32, Vec<SegmentOutput>)> = None;
+        for segment in segments.iter() {
+            let position = if control.is_segment_visible(segment.id()) {
+                positions.next()
+            } else {
+                None
+            };
             if let Some(t) = finder
-                .find(&self.button_txt(date.id()))
-                .and_then(|e| texts.get_mut(e))
+                .find(segment.id())
+                .and_then(|e| transforms.get_mut(e))
             {
-                date.update(t);
+                t.width = f32::from(segment.width());
             }
-            if let Some(t) = finder
-                .find(&self.button_txt(cpu.id()))
-                .and_then(|e| texts.get_mut(e))
+            let placements = [
+                (finder.find(segment.id()), position.map(|(x, _)| x)),
+                (
+                    finder.find(&self.separator(segment.id())),
+                    position.map(|(_, x)| x),
+                ),
+            ];
+            for (entity, x) in
+                placements.iter().filter_map(|(e, x)| e.map(|e| (e, *x)))
             {
-                cpu.update(t);
+                match x {
+                    Some(x) => {
+                        hidden.remove(entity);
+                        if let Some(t) = transforms.get_mut(entity) {
+                            t.local_x = x;
+                        }
+                    }
+                    None => {
+                        hidden.insert(entity, HiddenPropagate).ok();
+                    }
+                }
             }
-            self.delta = time.absolute_time_seconds();
+
+            // Only the popup of the leftmost segment is shown
+            if let (None, Some((x, _)), Some(lines)) =
+                (&popup, position, control.popup(segment.id()))
+            {
+                popup = Some((x, lines.to_vec()));
+            }
+
+            // Display the latest output
+            let output = match control.output(segment.id()) {
+                Some(output) => output,
+                None => continue,
+            };
+
+            // The background color covers the image of the button
+            if self.backgrounds.get(segment.id()) != Some(&output.background) {
+                if let Some(entity) = finder.find(segment.id()) {
+                    let image = match output.background {
+                        Some(color) => loader.load_from_data(
+                            TextureData::color(color),
+                            (),
+                            &texture_storage,
+                        ),
+                        None => assets.texture(
+                            "images/background.png",
+                            &loader,
+                            &texture_storage,
+                        ),
+                    };
+                    images.insert(entity, image).ok();
+                    self.backgrounds
+                        .insert(segment.id().to_owned(), output.background);
+                }
+            }
+
+            // Icons replace the text if the renderer is able to show them,
+            // unless they lead the text
+            let count = output.icons.len();
+            let leading = output.leading_icons;
+            for index in 0..count {
+                let entity = match finder.find(&self.icon(segment.id(), index))
+                {
+                    Some(entity) => entity,
+                    None => continue,
+                };
+                match position {
+                    Some((x, _)) => {
+                        hidden.remove(entity);
+                        if let Some(t) = transforms.get_mut(entity) {
+                            t.local_x = if leading {
+                                Layout::leading_icon_x(
+                                    x,
+                                    segment.width(),
+                                    index,
+                                )
+                            } else {
+                                Layout::icon_x(x, index, count)
+                            };
+                        }
+                    }
+                    None => {
+                        hidden.insert(entity, HiddenPropagate).ok();
+                    }
+                }
+            }
+
+            let chars = Run::chars(output, ColorScheme::foreground());
+            let (chars, offset) = if count > 0 && !leading {
+                (vec![], 0.)
+            } else if segment.animated() {
+                let padding =
+                    (' ', chars.last().map(|(_, s)| *s).unwrap_or_default());
+                self.animate(
+                    segment.id(),
+                    &output.text,
+                    &chars,
+                    padding,
+                    segment.width(),
+                    time.absolute_time_seconds(),
+                )
+            } else {
+                (chars, 0.)
+            };
+            let mut text = Texts {
+                texts: &mut texts,
+                transforms: &mut transforms,
+                hidden: &mut hidden,
+                finder: &finder,
+                fonts: &fonts,
+            };
+            match position {
+                Some((x, _)) => {
+                    let x = if leading {
+                        x + Layout::leading_text_x(count)
+                    } else {
+                        x
+                    };
+                    let runs = Run::split(&chars, &fonts);
+                    text.draw(segment.id(), &runs, (x, offset - 10.));
+                }
+                None => text.hide(segment.id()),
+            }
+        }
+
+        // Show the popup lines and resize the window to fit them
+        let (x, lines) = popup.unwrap_or_default();
+        for line in 0..Layout::popup_lines() {
+            let entity = match finder.find(&self.popup_line(line)) {
+                Some(entity) => entity,
+                None => continue,
+            };
+            match lines.get(line) {
+                Some(_) => {
+                    hidden.remove(entity);
+                    if let Some(t) = transforms.get_mut(entity) {
+                        t.local_x = Layout::popup_x(x);
+                    }
+                }
+                None => {
+                    hidden.insert(entity, HiddenPropagate).ok();
+                }
+            }
+            let mut text = Texts {
+                texts: &mut texts,
+                transforms: &mut transforms,
+                hidden: &mut hidden,
+                finder: &finder,
+                fonts: &fonts,
+            };
+            let runs = lines
+                .get(line)
+                .map(|o| {
+                    Run::split(
+                        &Run::chars(o, ColorScheme::foreground()),
+                        &fonts,
+                    )
+                })
+                .unwrap_or_default();
+            text.draw(
+                &self.popup_line(line),
+                &runs,
+                (Layout::popup_x(x), Layout::popup_y(line)),
+            );
+        }
+        let popup_lines = lines.len().min(Layout::popup_lines());
+        if popup_lines != self.popup_lines {
+            self.popup_lines = popup_lines;
+            let height = Layout::height() as usize * (popup_lines + 1);
+            window.send_command(move |w| {
+                w.set_inner_size(LogicalSize::new(
+                    f64::from(Layout::width()),
+                    height as f64,
+                ))
+            });
         }
     }
 }
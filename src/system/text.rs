@@ -0,0 +1,358 @@
+//! Styled text, which is drawn by one text entity per run of equally styled
+//! characters of the same font, since a text entity only has a single color
+//! and font
+
+use crate::{font::Fonts, layout::Layout, segment::SegmentOutput};
+use amethyst::{
+    ecs::{Entities, Entity, LazyUpdate, World, WriteStorage},
+    prelude::Builder,
+    renderer::HiddenPropagate,
+    ui::{Anchor, FontHandle, UiFinder, UiText, UiTransform},
+};
+
+/// The style of a single character
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Style {
+    /// The text color
+    pub color: [f32; 4],
+
+    /// Indicates that the character is drawn in bold
+    pub bold: bool,
+}
+
+/// A part of a text drawn by a single text entity
+#[derive(Debug, PartialEq)]
+pub struct Run {
+    /// The text of the part
+    pub text: String,
+
+    /// The style of all characters
+    pub style: Style,
+
+    /// The index of the first font containing the characters
+    pub font: usize,
+
+    /// The horizontal center relative to the center of the whole text
+    pub x: f32,
+
+    /// The width in pixels
+    pub width: f32,
+}
+
+impl Run {
+    /// Retrieve the styled characters of an output, whereas unstyled spans
+    /// use the color of the whole output
+    pub fn chars(
+        output: &SegmentOutput,
+        color: [f32; 4],
+    ) -> Vec<(char, Style)> {
+        let color = output.text_color(color);
+        if output.spans.is_empty() {
+            return Self::plain(&output.text, color);
+        }
+        output
+            .spans
+            .iter()
+            .flat_map(|s| {
+                let style = Style {
+                    color: s.color.unwrap_or(color),
+                    bold: s.bold,
+                };
+                s.text.chars().map(move |c| (c, style))
+            })
+            .collect()
+    }
+
+    /// Retrieve the characters of an unstyled text
+    pub fn plain(text: &str, color: [f32; 4]) -> Vec<(char, Style)> {
+        let style = Style { color, bold: false };
+        text.chars().map(|c| (c, style)).collect()
+    }
+
+    /// Split styled characters into runs, whereas whitespace continues the
+    /// previous font if contained. The last run contains the remaining
+    /// characters if the maximum amount of runs is exceeded.
+    pub fn split(chars: &[(char, Style)], fonts: &Fonts) -> Vec<Self> {
+        let mut runs: Vec<Self> = vec![];
+        for (c, style) in chars {
+            let font = match runs.last() {
+                Some(last)
+                    if c.is_whitespace() && fonts.contains(last.font, *c) =>
+                {
+                    last.font
+                }
+                _ => fonts.index(*c),
+            };
+            let width = fonts.advance(font, *c);
+            let full = runs.len() == Layout::text_runs();
+            match runs.last_mut() {
+                Some(last)
+                    if (last.style == *style && last.font == font) || full =>
+                {
+                    last.text.push(*c);
+                    last.width += width;
+                }
+                _ => runs.push(Self {
+                    text: c.to_string(),
+                    style: *style,
+                    font,
+                    x: 0.,
+                    width,
+                }),
+            }
+        }
+
+        // Center the runs next to each other
+        let mut left = -runs.iter().map(|r| r.width).sum::<f32>() / 2.;
+        for run in &mut runs {
+            run.x = left + run.width / 2.;
+            left += run.width;
+        }
+        runs
+    }
+
+    fn name(text: &str, index: usize) -> String {
+        format!("{}_run_{}", text, index)
+    }
+
+    /// Bold runs are drawn a second time with a slight offset
+    fn bold_name(text: &str, index: usize) -> String {
+        format!("{}_bold_{}", text, index)
+    }
+
+    /// Retrieve the names of all entities of a text
+    pub fn names(text: &str) -> Vec<String> {
+        (0..Layout::text_runs())
+            .flat_map(|n| vec![Self::name(text, n), Self::bold_name(text, n)])
+            .collect()
+    }
+
+    /// Create the hidden entities of a text anchored at the top right
+    pub fn create(world: &mut World, text: &str, font: &FontHandle, size: f32) {
+        for name in Self::names(text) {
+            let transform = UiTransform::new(
+                name,
+                Anchor::TopRight,
+                0.,
+                -10.,
+                2.,
+                0.,
+                Layout::height() as f32,
+            );
+            let ui_text =
+                UiText::new(font.clone(), String::new(), [0.; 4], size);
+            world
+                .create_entity()
+                .with(transform)
+                .with(ui_text)
+                .with(HiddenPropagate)
+                .build();
+        }
+    }
+
+    /// Create the entities of a text centered at the position relative to
+    /// the top left, which get added at the end of the frame
+    pub fn spawn(
+        text: &str,
+        runs: &[Run],
+        (x, y): (f32, f32),
+        fonts: &Fonts,
+        entities: &Entities<'_>,
+        lazy: &LazyUpdate,
+    ) -> Vec<Entity> {
+        let mut result = vec![];
+        for (index, run) in runs.iter().enumerate() {
+            let mut names = vec![(Self::name(text, index), 0.)];
+            if run.style.bold {
+                names.push((Self::bold_name(text, index), 1.));
+            }
+            for (name, offset) in names {
+                let transform = UiTransform::new(
+                    name,
+                    Anchor::TopLeft,
+                    x + run.x + offset,
+                    y,
+                    2.,
+                    run.width + Layout::char_width(),
+                    Layout::height() as f32,
+                );
+                let ui_text = UiText::new(
+                    fonts.font_handle(run.font),
+                    run.text.clone(),
+                    run.style.color,
+                    fonts.size(),
+                );
+                result.push(
+                    lazy.create_entity(entities)
+                        .with(transform)
+                        .with(ui_text)
+                        .build(),
+                );
+            }
+        }
+        result
+    }
+}
+
+/// The storages required for drawing runs
+pub struct Texts<'a, 's> {
+    /// The text components
+    pub texts: &'a mut WriteStorage<'s, UiText>,
+
+    /// The transforms of the entities
+    pub transforms: &'a mut WriteStorage<'s, UiTransform>,
+
+    /// The hidden entities
+    pub hidden: &'a mut WriteStorage<'s, HiddenPropagate>,
+
+    /// The finder for the entities of a text
+    pub finder: &'a UiFinder<'s>,
+
+    /// The loaded fonts
+    pub fonts: &'a Fonts,
+}
+
+impl<'a, 's> Texts<'a, 's> {
+    /// Draw the runs of a text centered at the position relative to the
+    /// top right, unused entities are hidden
+    pub fn draw(&mut self, text: &str, runs: &[Run], (x, y): (f32, f32)) {
+        for index in 0..Layout::text_runs() {
+            let run = runs.get(index);
+            let entities = [
+                (Run::name(text, index), run, 0.),
+                (
+                    Run::bold_name(text, index),
+                    run.filter(|r| r.style.bold),
+                    1.,
+                ),
+            ];
+            for (name, run, offset) in entities.iter() {
+                let entity = match self.finder.find(name) {
+                    Some(entity) => entity,
+                    None => continue,
+                };
+                let run = match run {
+                    Some(run) => run,
+                    None => {
+                        self.hidden.insert(entity, HiddenPropagate).ok();
+                        continue;
+                    }
+                };
+                self.hidden.remove(entity);
+                if let Some(t) = self.texts.get_mut(entity) {
+                    t.color = run.style.color;
+                    t.font = self.fonts.font_handle(run.font);
+                    t.text = run.text.clone();
+                }
+
+                // Leave some room for the approximated width
+                if let Some(t) = self.transforms.get_mut(entity) {
+                    t.local_x = x + run.x + offset;
+                    t.local_y = y;
+                    t.width = run.width + Layout::char_width();
+                }
+            }
+        }
+    }
+
+    /// Hide all entities of a text
+    pub fn hide(&mut self, text: &str) {
+        self.draw(text, &[], (0., 0.));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assets::Assets, config::FontConfig, segment::Span};
+
+    fn fonts() -> Fonts {
+        Fonts::load(&FontConfig::default(), &Assets::new(vec![])).unwrap()
+    }
+
+    #[test]
+    fn split_spans() {
+        let red = [1., 0., 0., 1.];
+        let output = SegmentOutput::new("ab c").with_spans(vec![
+            Span {
+                text: "ab".into(),
+                ..Span::default()
+            },
+            Span {
+                text: " c".into(),
+                color: Some(red),
+                bold: true,
+            },
+        ]);
+        let white = [1.; 4];
+        let chars = Run::chars(&output, white);
+        assert_eq!(chars.len(), 4);
+
+        let fonts = fonts();
+        let runs = Run::split(&chars, &fonts);
+        assert_eq!(runs.len(), 2);
+        assert_eq!(runs[0].text, "ab");
+        assert_eq!(
+            runs[0].style,
+            Style {
+                color: white,
+                bold: false
+            }
+        );
+        assert_eq!(runs[1].text, " c");
+        assert_eq!(
+            runs[1].style,
+            Style {
+                color: red,
+                bold: true
+            }
+        );
+
+        // The runs are centered next to each other
+        let width = runs[0].width + runs[1].width;
+        assert!(width > 0.);
+        assert_eq!(runs[0].x, -width / 2. + runs[0].width / 2.);
+        assert_eq!(runs[1].x, width / 2. - runs[1].width / 2.);
+    }
+
+    #[test]
+    fn split_fonts() {
+        // The fallback covers the draughts missing in the bundled font
+        let fallback = "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf";
+        if !std::path::Path::new(fallback).exists() {
+            return;
+        }
+        let config = FontConfig {
+            fallbacks: vec![fallback.into()],
+            ..FontConfig::default()
+        };
+        let fonts = Fonts::load(&config, &Assets::new(vec![])).unwrap();
+        let chars = Run::plain("a\u{26c0} b", [1.; 4]);
+        let runs = Run::split(&chars, &fonts);
+        let parts: Vec<(&str, usize)> =
+            runs.iter().map(|r| (r.text.as_str(), r.font)).collect();
+        assert_eq!(parts, vec![("a", 0), ("\u{26c0} ", 1), ("b", 0)]);
+    }
+
+    #[test]
+    fn split_limit() {
+        let chars: Vec<(char, Style)> = (0..20)
+            .map(|n| {
+                let style = Style {
+                    color: [n as f32 / 20., 0., 0., 1.],
+                    bold: false,
+                };
+                ('x', style)
+            })
+            .collect();
+        let runs = Run::split(&chars, &fonts());
+        assert_eq!(runs.len(), Layout::text_runs());
+        assert_eq!(runs.last().unwrap().text.len(), 20 - 7);
+
+        // Unstyled outputs are a single run
+        let output = SegmentOutput::new("unibar");
+        let runs = Run::split(&Run::chars(&output, [1.; 4]), &fonts());
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].x, 0.);
+    }
+}
@@ -1,69 +1,158 @@
-use crate::workspace::Workspace;
+use crate::{
+    assets::Assets,
+    font::Fonts,
+    i3::{Message, I3},
+    workspace::Workspace,
+};
 use amethyst::{
     assets::{AssetStorage, Loader},
-    ecs::{Entities, Read, ReadExpect, System, Write},
-    error::format_err,
+    ecs::{Entities, LazyUpdate, Read, ReadExpect, System, Write},
     renderer::Texture,
     shrev::{EventChannel, ReaderId},
-    ui::{FontAsset, UiButtonBuilderResources, UiEvent, UiEventType},
-    Error,
-};
-use i3ipc::{event::Event, I3Connection, I3EventListener, Subscription};
-use log::{debug, error, warn};
-use std::{
-    collections::HashMap,
-    sync::mpsc::{self, Receiver, Sender},
-    thread,
+    ui::{UiButtonBuilderResources, UiEvent, UiEventType},
 };
+use failure::{format_err, Fallible};
+use i3ipc::{event::Event, reply::Workspace as I3Workspace};
+use log::{error, warn};
+use std::collections::HashMap;
 
-pub struct WorkspaceSystem {
-    i3_connection: I3Connection,
-    event_receiver: Receiver<Event>,
-    number_of_workspaces: usize,
+/// A change of the displayed workspaces
+#[derive(Debug)]
+pub enum Update {
+    /// Replace all workspaces by the disconnected state
+    Disconnected,
+
+    /// Draw the i3 workspace at the index
+    Draw(usize, I3Workspace),
+
+    /// Remove all workspaces starting from the index
+    Remove(usize),
+}
+
+/// Keeps the displayed workspaces in sync with i3, whereas a single
+/// workspace is drawn per frame
+pub struct WorkspaceTracker {
+    i3: I3,
     workspace_to_draw: usize,
     update_workspaces: bool,
-    workspaces: HashMap<usize, Workspace>,
-    reader_id: Option<ReaderId<UiEvent>>,
+    names: HashMap<usize, String>,
 }
 
-impl WorkspaceSystem {
-    /// Create a new workspace system
-    pub fn new() -> Result<Self, Error> {
-        let mut event_listener = I3EventListener::connect()
-            .map_err(|_| format_err!("unable to establish i3 connection"))?;
-
-        event_listener
-            .subscribe(&[Subscription::Workspace])
-            .map_err(|_| format_err!("unable to subscribe to i3 events"))?;
-
-        let (tx, rx): (Sender<Event>, Receiver<Event>) = mpsc::channel();
-
-        thread::spawn(move || {
-            for event in event_listener.listen() {
-                match event {
-                    Ok(e) => {
-                        debug!("Received i3 event: {:?}", e);
-                        if let Err(err) = tx.send(e) {
-                            error!("Unable to send i3 event: {}", err);
-                        }
-                    }
-                    Err(e) => warn!("Unable to get i3 event: {}", e),
+impl WorkspaceTracker {
+    /// Create a new tracker and start listening for i3 events
+    pub fn new() -> Self {
+        Self {
+            i3: I3::new(),
+            workspace_to_draw: 0,
+            update_workspaces: false,
+            names: HashMap::new(),
+        }
+    }
+
+    /// Process the pending i3 messages and retrieve the updates of the
+    /// current frame
+    pub fn poll(&mut self) -> Vec<Update> {
+        // Check if we need to update the workspaces
+        for message in self.i3.poll() {
+            match message {
+                Message::Connected | Message::Disconnected => {
+                    // Resync all workspaces from the beginning
+                    self.workspace_to_draw = 0;
+                    self.update_workspaces = true;
                 }
+                Message::Event(Event::WorkspaceEvent(_)) => {
+                    self.update_workspaces = true
+                }
+                Message::Event(_) => {}
             }
-        });
+        }
 
-        let i3_connection = I3Connection::connect()
-            .map_err(|_| format_err!("unable to establish i3 connection"))?;
+        if !self.update_workspaces {
+            return vec![];
+        }
 
-        Ok(Self {
-            i3_connection,
-            event_receiver: rx,
-            number_of_workspaces: 0,
-            workspace_to_draw: 0,
-            update_workspaces: true,
+        // Show the disconnected state if i3 is not available
+        if !self.i3.is_connected() {
+            self.names.clear();
+            self.update_workspaces = false;
+            return vec![Update::Disconnected];
+        }
+
+        // A lost connection gets resynced once the listener reconnects
+        let mut workspaces = match self.i3.workspaces() {
+            Ok(workspaces) => workspaces,
+            Err(e) => {
+                warn!("Unable to retrieve workspaces: {}", e);
+                self.workspace_to_draw = 0;
+                self.update_workspaces = false;
+                return vec![];
+            }
+        };
+
+        // Stop if no workspaces were found
+        let number_of_workspaces = workspaces.len();
+        if number_of_workspaces == 0 {
+            error!("No workspaces found");
+            return vec![];
+        }
+
+        // Restart if workspaces vanished while drawing
+        if self.workspace_to_draw >= number_of_workspaces {
+            self.workspace_to_draw = 0;
+        }
+        let index = self.workspace_to_draw;
+        let workspace = workspaces.swap_remove(index);
+        self.names.insert(index, workspace.name.clone());
+        let mut updates = vec![Update::Draw(index, workspace)];
+
+        if index == number_of_workspaces - 1 {
+            self.names.retain(|i, _| *i < number_of_workspaces);
+            updates.push(Update::Remove(number_of_workspaces));
+            self.workspace_to_draw = 0;
+            self.update_workspaces = false;
+        } else {
+            self.workspace_to_draw += 1;
+        }
+        updates
+    }
+
+    /// Switch to the drawn workspace at the index
+    pub fn click(&mut self, index: usize) -> Fallible<()> {
+        let name = self
+            .names
+            .get(&index)
+            .ok_or_else(|| format_err!("no workspace at index {}", index))?;
+        self.i3.switch_workspace(name)
+    }
+}
+
+pub struct WorkspaceSystem {
+    tracker: WorkspaceTracker,
+    workspaces: HashMap<usize, Workspace>,
+    reader_id: Option<ReaderId<UiEvent>>,
+}
+
+impl WorkspaceSystem {
+    /// Create a new workspace system
+    pub fn new() -> Self {
+        Self {
+            tracker: WorkspaceTracker::new(),
             workspaces: HashMap::default(),
             reader_id: None,
-        })
+        }
+    }
+
+    /// Remove all workspaces starting from the provided index
+    fn remove_workspaces(&mut self, start: usize, entities: &Entities<'_>) {
+        self.workspaces.retain(|index, workspace| {
+            if *index < start {
+                return true;
+            }
+            if let Err(e) = workspace.remove(entities) {
+                error!("Unable to remove workspace: {}", e)
+            }
+            false
+        });
     }
 }
 
@@ -72,9 +161,11 @@ impl<'s> System<'s> for WorkspaceSystem {
         Write<'s, EventChannel<UiEvent>>,
         ReadExpect<'s, Loader>,
         Read<'s, AssetStorage<Texture>>,
-        Read<'s, AssetStorage<FontAsset>>,
+        ReadExpect<'s, Fonts>,
+        ReadExpect<'s, Assets>,
         UiButtonBuilderResources<'s, u8>,
         Entities<'s>,
+        Read<'s, LazyUpdate>,
     );
 
     fn run(
@@ -83,9 +174,11 @@ impl<'s> System<'s> for WorkspaceSystem {
             mut events,
             loader,
             texture_storage,
-            font_storage,
+            fonts,
+            assets,
             button_builder_resources,
             entities,
+            lazy,
         ): Self::SystemData,
     ) {
         // Process UI events
@@ -93,68 +186,207 @@ impl<'s> System<'s> for WorkspaceSystem {
             .reader_id
             .get_or_insert_with(|| events.register_reader());
         for event in events.read(reader_id) {
-            // Switch the workspace
+            // Switch to the workspace of the target entity
             if let UiEventType::Click = event.event_type {
-                // Get the corresponding workspace for the target entity
-                for workspace in self.workspaces.values() {
-                    if workspace.has_entity(event.target) {
-                        if let Err(e) = self.i3_connection.run_command(
-                            &format!("workspace {}", workspace.name()),
-                        ) {
-                            error!("Unable to switch workspace: {}", e);
-                        }
-                        break;
+                let index = self
+                    .workspaces
+                    .iter()
+                    .find(|(_, w)| w.has_entity(event.target))
+                    .map(|(index, _)| *index);
+                if let Some(index) = index {
+                    if let Err(e) = self.tracker.click(index) {
+                        error!("Unable to switch workspace: {}", e);
                     }
                 }
             }
         }
 
-        // Check if we need to update the workspaces
-        for e in self.event_receiver.try_iter() {
-            if let Event::WorkspaceEvent(_) = e {
-                self.update_workspaces = true
-            }
-        }
-
-        if self.update_workspaces {
-            match self.i3_connection.get_workspaces() {
-                Ok(response) => {
-                    self.number_of_workspaces = response.workspaces.len();
-
-                    // Stop of no workspaces were found
-                    if self.number_of_workspaces == 0 {
-                        error!("No workspaces found");
-                        return;
+        // The button resources can only be used once per run
+        let mut button_builder_resources = Some(button_builder_resources);
+        for update in self.tracker.poll() {
+            match update {
+                Update::Disconnected => {
+                    self.remove_workspaces(0, &entities);
+                    let workspace =
+                        self.workspaces.entry(0).or_insert_with(Workspace::new);
+                    let (image, _) = Workspace::disconnected_style();
+                    if let Some(resources) = button_builder_resources.take() {
+                        if let Err(e) = workspace.update_disconnected(
+                            assets.texture(image, &loader, &texture_storage),
+                            &fonts,
+                            resources,
+                            &entities,
+                            &lazy,
+                        ) {
+                            error!("Unable to update workspace: {}", e)
+                        }
                     }
-
-                    // Retrieve the workspace
+                }
+                Update::Draw(index, i3_workspace) => {
                     let workspace = self
                         .workspaces
-                        .entry(self.workspace_to_draw)
+                        .entry(index)
                         .or_insert_with(Workspace::new);
-
-                    // Update the workspace
-                    if let Err(e) = workspace.update(
-                        &response.workspaces[self.workspace_to_draw],
-                        &loader,
-                        &texture_storage,
-                        &font_storage,
-                        button_builder_resources,
-                        entities,
-                    ) {
-                        error!("Unable to update workspace: {}", e)
-                    }
-
-                    // Sanitize for index out of bounds checks
-                    if self.workspace_to_draw >= self.number_of_workspaces - 1 {
-                        self.workspace_to_draw = 0;
-                        self.update_workspaces = false;
-                    } else {
-                        self.workspace_to_draw += 1;
+                    let (image, _) = Workspace::style(&i3_workspace);
+                    if let Some(resources) = button_builder_resources.take() {
+                        if let Err(e) = workspace.update(
+                            &i3_workspace,
+                            assets.texture(image, &loader, &texture_storage),
+                            &fonts,
+                            resources,
+                            &entities,
+                            &lazy,
+                        ) {
+                            error!("Unable to update workspace: {}", e)
+                        }
                     }
                 }
-                Err(e) => error!("Unable to retrieve workspaces: {}", e),
+                Update::Remove(start) => {
+                    self.remove_workspaces(start, &entities)
+                }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{
+        i3::{self, Server},
+        wait::wait_until,
+    };
+
+    /// Poll the tracker until an update matches or the timeout exceeds
+    fn wait_for<F>(tracker: &mut WorkspaceTracker, matches: F) -> Vec<Update>
+    where
+        F: Fn(&Update) -> bool,
+    {
+        let mut updates = vec![];
+        wait_until(|| {
+            updates.extend(tracker.poll());
+            updates.iter().any(&matches)
+        });
+        updates
+    }
+
+    fn is_removal(update: &Update) -> bool {
+        matches!(update, Update::Remove(_))
+    }
+
+    fn is_disconnected(update: &Update) -> bool {
+        matches!(update, Update::Disconnected)
+    }
+
+    fn describe(updates: &[Update]) -> Vec<String> {
+        updates
+            .iter()
+            .map(|u| match u {
+                Update::Disconnected => "disconnected".to_owned(),
+                Update::Draw(i, w) => format!("draw {} {}", i, w.name),
+                Update::Remove(i) => format!("remove {}", i),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn draw_one_workspace_per_frame() {
+        let _lock = i3::lock();
+        let path = i3::socket_path();
+        i3::set_socket_path(&path);
+        let server = Server::start(&path);
+        server.set_workspaces(&[
+            (1, "1:term", true, true),
+            (2, "2:web", false, false),
+            (3, "3", false, false),
+        ]);
+
+        let mut tracker = WorkspaceTracker::new();
+        let updates = wait_for(&mut tracker, is_removal);
+        assert_eq!(
+            describe(&updates),
+            vec!["draw 0 1:term", "draw 1 2:web", "draw 2 3", "remove 3"]
+        );
+
+        // Nothing changes without further events
+        assert!(tracker.poll().is_empty());
+    }
+
+    #[test]
+    fn redraw_on_workspace_events() {
+        let _lock = i3::lock();
+        let path = i3::socket_path();
+        i3::set_socket_path(&path);
+        let server = Server::start(&path);
+        server.set_workspaces(&[(1, "1", true, true), (2, "2", true, false)]);
+
+        let mut tracker = WorkspaceTracker::new();
+        wait_for(&mut tracker, is_removal);
+
+        // A closed workspace removes the remaining buttons
+        server.set_workspaces(&[(2, "2", true, true)]);
+        server.send_workspace_event("empty");
+        let updates = wait_for(&mut tracker, is_removal);
+        assert_eq!(describe(&updates), vec!["draw 0 2", "remove 1"]);
+
+        // A new workspace is appended
+        server.set_workspaces(&[(2, "2", true, false), (4, "4", true, true)]);
+        server.send_workspace_event("init");
+        let updates = wait_for(&mut tracker, is_removal);
+        assert_eq!(
+            describe(&updates),
+            vec!["draw 0 2", "draw 1 4", "remove 2"]
+        );
+    }
+
+    #[test]
+    fn click_to_switch() {
+        let _lock = i3::lock();
+        let path = i3::socket_path();
+        i3::set_socket_path(&path);
+        let server = Server::start(&path);
+        server.set_workspaces(&[
+            (1, "1:term", true, true),
+            (2, "2:web", false, false),
+        ]);
+
+        let mut tracker = WorkspaceTracker::new();
+        assert!(tracker.click(0).is_err());
+        wait_for(&mut tracker, is_removal);
+
+        tracker.click(1).unwrap();
+        tracker.click(0).unwrap();
+        assert!(tracker.click(2).is_err());
+        assert_eq!(
+            server.commands(),
+            vec!["workspace 2:web", "workspace 1:term"]
+        );
+    }
+
+    #[test]
+    fn disconnected_until_restarted() {
+        let _lock = i3::lock();
+        let path = i3::socket_path();
+        i3::set_socket_path(&path);
+
+        let mut tracker = WorkspaceTracker::new();
+        let updates = wait_for(&mut tracker, is_disconnected);
+        assert_eq!(describe(&updates), vec!["disconnected"]);
+        assert!(tracker.click(0).is_err());
+
+        // Start i3 later on
+        let server = Server::start(&path);
+        server.set_workspaces(&[(1, "1", true, true)]);
+        let updates = wait_for(&mut tracker, is_removal);
+        assert_eq!(describe(&updates), vec!["draw 0 1", "remove 1"]);
+
+        // A restart shows the disconnected state before redrawing
+        server.restart();
+        let updates = wait_for(&mut tracker, is_disconnected);
+        assert_eq!(describe(&updates), vec!["disconnected"]);
+        let updates = wait_for(&mut tracker, is_removal);
+        assert_eq!(describe(&updates), vec!["draw 0 1", "remove 1"]);
+        tracker.click(0).unwrap();
+        assert_eq!(server.commands(), vec!["workspace 1"]);
+    }
+}
@@ -0,0 +1,564 @@
+//! Format strings with named placeholders like `{load1:>6.2}`, conditional
+//! sections like `{?charging}+{/}`, units like `{memory|bytes:.1}` and
+//! styled spans like `{#red bold}{usage}%{/}`
+
+use crate::{
+    color::ColorScheme,
+    segment::{SegmentOutput, Span},
+};
+use failure::{format_err, Fallible};
+use std::str::FromStr;
+
+/// A value a placeholder gets replaced with
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /// A number, which is right aligned by default
+    Number(f64),
+
+    /// A text, which is left aligned by default
+    Text(String),
+}
+
+impl Value {
+    /// Returns true if the value is neither zero nor an empty text
+    fn is_set(&self) -> bool {
+        match self {
+            Value::Number(n) => *n != 0. && !n.is_nan(),
+            Value::Text(t) => !t.is_empty(),
+        }
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::Number(value)
+    }
+}
+
+impl From<u64> for Value {
+    fn from(value: u64) -> Self {
+        Value::Number(value as f64)
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::Text(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::Text(value.to_owned())
+    }
+}
+
+/// The alignment of a padded value
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Align {
+    Left,
+    Right,
+    Center,
+}
+
+/// The unit a number gets humanized with
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Unit {
+    /// Binary prefixes like `1.5KiB`
+    Bytes,
+
+    /// Decimal prefixes like `1.5k`
+    Si,
+
+    /// Seconds like `4h 12m`
+    Duration,
+}
+
+/// The format specification of a placeholder
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Spec {
+    unit: Option<Unit>,
+    align: Option<Align>,
+    width: usize,
+    precision: Option<usize>,
+}
+
+/// The style of a span, where unset values are inherited
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Style {
+    color: Option<[f32; 4]>,
+    bold: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Part {
+    Text(String),
+    Placeholder(String, Spec),
+
+    /// Parts shown if the value is set, or not set if negated
+    Section(String, bool, Vec<Part>),
+
+    /// Parts shown with a different style
+    Styled(Style, Vec<Part>),
+}
+
+/// A parsed format string, where `{{` and `}}` are literal braces
+#[derive(Clone, Debug, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl FromStr for Template {
+    type Err = failure::Error;
+
+    fn from_str(s: &str) -> Fallible<Self> {
+        // The currently open blocks with their parts so far
+        let mut stack: Vec<(Option<Part>, Vec<Part>)> = vec![(None, vec![])];
+        let mut text = String::new();
+        let mut chars = s.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut tag = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => tag.push(c),
+                            None => {
+                                return Err(format_err!(
+                                    "unclosed '{{' in '{}'",
+                                    s
+                                ))
+                            }
+                        }
+                    }
+                    let parts = &mut stack.last_mut().unwrap().1;
+                    if !text.is_empty() {
+                        parts.push(Part::Text(text.split_off(0)));
+                    }
+
+                    if tag == "/" {
+                        let (block, parts) = stack.pop().unwrap();
+                        let block = match block {
+                            Some(Part::Section(name, negated, _)) => {
+                                Part::Section(name, negated, parts)
+                            }
+                            Some(Part::Styled(style, _)) => {
+                                Part::Styled(style, parts)
+                            }
+                            _ => {
+                                return Err(format_err!(
+                                    "unmatched '{{/}}' in '{}'",
+                                    s
+                                ))
+                            }
+                        };
+                        stack.last_mut().unwrap().1.push(block);
+                    } else if tag.starts_with(&['?', '!'][..]) {
+                        let name = tag[1..].trim();
+                        if name.is_empty() {
+                            return Err(format_err!(
+                                "empty section in '{}'",
+                                s
+                            ));
+                        }
+                        let section = Part::Section(
+                            name.to_owned(),
+                            tag.starts_with('!'),
+                            vec![],
+                        );
+                        stack.push((Some(section), vec![]));
+                    } else if let Some(style) = tag.strip_prefix('#') {
+                        let style = Self::style(s, style)?;
+                        stack.push((Some(Part::Styled(style, vec![])), vec![]));
+                    } else {
+                        parts.push(Self::placeholder(s, &tag)?);
+                    }
+                }
+                '}' => return Err(format_err!("unmatched '}}' in '{}'", s)),
+                c => text.push(c),
+            }
+        }
+
+        let (_, mut parts) = stack.pop().unwrap();
+        if !stack.is_empty() {
+            return Err(format_err!("unclosed section in '{}'", s));
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        Ok(Self { parts })
+    }
+}
+
+impl Template {
+    /// Parse a placeholder like `name|bytes:>3.1` without its braces
+    fn placeholder(template: &str, placeholder: &str) -> Fallible<Part> {
+        let mut split = placeholder.splitn(2, ':');
+        let mut name = split.next().unwrap_or_default().splitn(2, '|');
+        let (name, unit) =
+            (name.next().unwrap_or_default().trim(), name.next());
+        if name.is_empty() {
+            return Err(format_err!("empty placeholder in '{}'", template));
+        }
+        let invalid =
+            || format_err!("invalid format of '{}' in '{}'", name, template);
+
+        let unit = match unit.map(str::trim) {
+            None => None,
+            Some("bytes") => Some(Unit::Bytes),
+            Some("si") => Some(Unit::Si),
+            Some("duration") => Some(Unit::Duration),
+            Some(_) => return Err(invalid()),
+        };
+
+        let mut spec = split.next().unwrap_or_default();
+        let align = match spec.chars().next() {
+            Some('<') => Some(Align::Left),
+            Some('>') => Some(Align::Right),
+            Some('^') => Some(Align::Center),
+            _ => None,
+        };
+        if align.is_some() {
+            spec = &spec[1..];
+        }
+        let mut result = Spec {
+            unit,
+            align,
+            ..Spec::default()
+        };
+
+        let (width, precision) = match spec.find('.') {
+            Some(i) => (&spec[..i], Some(&spec[i + 1..])),
+            None => (spec, None),
+        };
+        if !width.is_empty() {
+            result.width = width.parse().map_err(|_| invalid())?;
+        }
+        if let Some(precision) = precision {
+            result.precision = Some(precision.parse().map_err(|_| invalid())?);
+        }
+        Ok(Part::Placeholder(name.to_owned(), result))
+    }
+
+    /// Parse a style like `red bold` or `#ff5555`
+    fn style(template: &str, style: &str) -> Fallible<Style> {
+        let mut result = Style::default();
+        for word in style.split_whitespace() {
+            match word {
+                "bold" => result.bold = true,
+                color => {
+                    result.color = Some(
+                        ColorScheme::from_name(color).ok_or_else(|| {
+                            format_err!(
+                                "invalid style '{}' in '{}'",
+                                color,
+                                template
+                            )
+                        })?,
+                    )
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Ensure that the template only uses the provided placeholder names
+    pub fn validate(&self, names: &[&str]) -> Fallible<()> {
+        Self::validate_parts(&self.parts, names)
+    }
+
+    fn validate_parts(parts: &[Part], names: &[&str]) -> Fallible<()> {
+        for part in parts {
+            let name = match part {
+                Part::Text(_) => continue,
+                Part::Placeholder(name, _) => name,
+                Part::Section(name, _, parts) => {
+                    Self::validate_parts(parts, names)?;
+                    name
+                }
+                Part::Styled(_, parts) => {
+                    Self::validate_parts(parts, names)?;
+                    continue;
+                }
+            };
+            if !names.contains(&name.as_str()) {
+                return Err(format_err!(
+                    "unknown placeholder '{}', available are: {}",
+                    name,
+                    names.join(", ")
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Replace the placeholders by the provided values, where missing ones
+    /// result in empty text and spans are only set if styles are used
+    pub fn output(&self, values: &[(&str, Value)]) -> SegmentOutput {
+        let spans = self.spans(values);
+        let text: String = spans.iter().map(|s| s.text.as_str()).collect();
+        let styled = spans.iter().any(|s| s.color.is_some() || s.bold);
+        SegmentOutput::new(text).with_spans(if styled { spans } else { vec![] })
+    }
+
+    /// Render the template into consecutive spans of the same style
+    pub fn spans(&self, values: &[(&str, Value)]) -> Vec<Span> {
+        let mut spans = vec![];
+        Self::render_parts(&self.parts, values, &Style::default(), &mut spans);
+        spans
+    }
+
+    fn render_parts(
+        parts: &[Part],
+        values: &[(&str, Value)],
+        style: &Style,
+        spans: &mut Vec<Span>,
+    ) {
+        let value = |name: &str| {
+            values.iter().find(|(n, _)| *n == name).map(|(_, v)| v)
+        };
+        for part in parts {
+            let text = match part {
+                Part::Text(text) => text.clone(),
+                Part::Placeholder(name, spec) => match value(name) {
+                    Some(value) => Self::format(value, spec),
+                    None => continue,
+                },
+                Part::Section(name, negated, parts) => {
+                    if value(name).into_iter().any(Value::is_set) != *negated {
+                        Self::render_parts(parts, values, style, spans);
+                    }
+                    continue;
+                }
+                Part::Styled(inner, parts) => {
+                    let style = Style {
+                        color: inner.color.or(style.color),
+                        bold: inner.bold || style.bold,
+                    };
+                    Self::render_parts(parts, values, &style, spans);
+                    continue;
+                }
+            };
+
+            match spans.last_mut() {
+                Some(last)
+                    if last.color == style.color && last.bold == style.bold =>
+                {
+                    last.text.push_str(&text)
+                }
+                _ => spans.push(Span {
+                    text,
+                    color: style.color,
+                    bold: style.bold,
+                }),
+            }
+        }
+    }
+
+    fn format(value: &Value, spec: &Spec) -> String {
+        let (text, default) = match (value, spec.unit) {
+            (Value::Number(n), Some(unit)) => {
+                (Self::humanize(*n, unit, spec.precision), Align::Right)
+            }
+            (Value::Number(n), None) => (
+                match spec.precision {
+                    Some(p) => format!("{:.*}", p, n),
+                    None => n.to_string(),
+                },
+                Align::Right,
+            ),
+            (Value::Text(t), _) => (
+                match spec.precision {
+                    Some(p) => t.chars().take(p).collect(),
+                    None => t.clone(),
+                },
+                Align::Left,
+            ),
+        };
+
+        let width = spec.width;
+        match spec.align.unwrap_or(default) {
+            Align::Left => format!("{:<1$}", text, width),
+            Align::Right => format!("{:>1$}", text, width),
+            Align::Center => format!("{:^1$}", text, width),
+        }
+    }
+
+    /// Format a number with the largest fitting prefix of the unit, where
+    /// the precision defaults to one decimal place for prefixed values
+    fn humanize(number: f64, unit: Unit, precision: Option<usize>) -> String {
+        let (base, prefixes): (f64, &[&str]) = match unit {
+            Unit::Bytes => (1024., &["B", "KiB", "MiB", "GiB", "TiB", "PiB"]),
+            Unit::Si => (1000., &["", "k", "M", "G", "T", "P"]),
+            Unit::Duration => return duration(number.max(0.) as u64),
+        };
+        let mut scaled = number;
+        let mut index = 0;
+        while scaled.abs() >= base && index < prefixes.len() - 1 {
+            scaled /= base;
+            index += 1;
+        }
+        let precision = precision.unwrap_or(if index == 0 { 0 } else { 1 });
+        format!("{:.*}{}", precision, scaled, prefixes[index])
+    }
+}
+
+/// Format seconds like `3d 4h`, `4h 12m` or `12m`
+pub fn duration(seconds: u64) -> String {
+    let (days, hours, minutes) =
+        (seconds / 86400, seconds / 3600 % 24, seconds / 60 % 60);
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else {
+        format!("{}m", minutes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(template: &str, values: &[(&str, Value)]) -> String {
+        template.parse::<Template>().unwrap().output(values).text
+    }
+
+    #[test]
+    fn placeholders() {
+        let values = [("usage", Value::from(7u64)), ("freq", 2.456.into())];
+        assert_eq!(render("{usage:>3}% {freq:.1}GHz", &values), "  7% 2.5GHz");
+        assert_eq!(render("{usage}{usage:<3}|", &values), "77  |");
+        assert_eq!(render("{freq:^8.2}", &values), "  2.46  ");
+        assert_eq!(render("{{{usage}}}", &values), "{7}");
+        assert_eq!(render("{missing}!", &values), "!");
+    }
+
+    #[test]
+    fn texts() {
+        let values = [("name", Value::from("unibar"))];
+        assert_eq!(render("[{name:8}]", &values), "[unibar  ]");
+        assert_eq!(render("[{name:>8.3}]", &values), "[     uni]");
+    }
+
+    #[test]
+    fn sections() {
+        let values = [
+            ("charging", Value::from(1u64)),
+            ("full", 0.into()),
+            ("title", "".into()),
+            ("artist", "Queen".into()),
+        ];
+        assert_eq!(render("{?charging}+{/}{?full}={/}", &values), "+");
+        assert_eq!(render("{!full}not full{/}", &values), "not full");
+        assert_eq!(render("{?missing}x{/}{!missing}y{/}", &values), "y");
+        assert_eq!(
+            render("{?artist}{artist}{?title} - {title}{/}{/}", &values),
+            "Queen"
+        );
+    }
+
+    #[test]
+    fn units() {
+        let values = [
+            ("small", Value::from(512u64)),
+            ("memory", 1_610_612_736u64.into()),
+            ("rate", 1_234_567u64.into()),
+            ("uptime", 97_380u64.into()),
+            ("name", "text".into()),
+        ];
+        assert_eq!(render("{small|bytes}", &values), "512B");
+        assert_eq!(render("{memory|bytes}", &values), "1.5GiB");
+        assert_eq!(render("{memory|bytes:>8.2}", &values), " 1.50GiB");
+        assert_eq!(render("{rate|si}bit/s", &values), "1.2Mbit/s");
+        assert_eq!(render("{small|si}", &values), "512");
+        assert_eq!(render("{uptime|duration}", &values), "1d 3h");
+        assert_eq!(render("{name|bytes}", &values), "text");
+    }
+
+    #[test]
+    fn spans() {
+        let template: Template =
+            "CPU {#red bold}{usage}%{/} {#cyan}{?high}!{/}{/}"
+                .parse()
+                .unwrap();
+        let spans =
+            template.spans(&[("usage", 95u64.into()), ("high", 1u64.into())]);
+        assert_eq!(
+            spans,
+            vec![
+                Span {
+                    text: "CPU ".into(),
+                    color: None,
+                    bold: false,
+                },
+                Span {
+                    text: "95%".into(),
+                    color: Some(ColorScheme::red()),
+                    bold: true,
+                },
+                Span {
+                    text: " ".into(),
+                    color: None,
+                    bold: false,
+                },
+                Span {
+                    text: "!".into(),
+                    color: Some(ColorScheme::cyan()),
+                    bold: false,
+                },
+            ]
+        );
+
+        let output = template.output(&[("usage", 5u64.into())]);
+        assert_eq!(output.text, "CPU 5% ");
+        assert_eq!(output.spans.len(), 3);
+
+        let output = "{usage}%".parse::<Template>().unwrap().output(&[]);
+        assert_eq!(output.text, "%");
+        assert!(output.spans.is_empty());
+    }
+
+    #[test]
+    fn duration() {
+        assert_eq!(super::duration(59), "0m");
+        assert_eq!(super::duration(754), "12m");
+        assert_eq!(super::duration(15120), "4h 12m");
+        assert_eq!(super::duration(97380), "1d 3h");
+    }
+
+    #[test]
+    fn invalid() {
+        for template in &[
+            "{}",
+            "{a",
+            "{a:x}",
+            "{a:.y}",
+            "a}",
+            "{a:3.}",
+            "{a|unit}",
+            "{?a}",
+            "{/}",
+            "{?}{/}",
+            "{#magenta}{/}",
+            "{#red}{?a}{/}",
+        ] {
+            assert!(template.parse::<Template>().is_err(), "{}", template);
+        }
+
+        let template: Template = "{a} {?b}{#red}{c}{/}{/}".parse().unwrap();
+        assert!(template.validate(&["a", "b", "c"]).is_ok());
+        assert!(template.validate(&["a", "b"]).is_err());
+        assert!(template.validate(&["a", "c"]).is_err());
+    }
+}
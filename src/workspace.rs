@@ -1,106 +1,142 @@
-use crate::{color::ColorScheme, state::State};
+use crate::{color::ColorScheme, font::Fonts, layout::Layout, system::Run};
 use amethyst::{
-    assets::{AssetStorage, Loader},
-    ecs::{Entities, Entity, Read, ReadExpect},
-    renderer::{PngFormat, Texture, TextureHandle, TextureMetadata},
-    ui::{
-        Anchor, FontAsset, FontHandle, TtfFormat, UiButtonBuilder,
-        UiButtonBuilderResources,
-    },
+    ecs::{Entities, Entity, LazyUpdate},
+    renderer::TextureHandle,
+    ui::{Anchor, UiButtonBuilder, UiButtonBuilderResources},
 };
 use failure::Fallible;
 use i3ipc::reply::Workspace as I3Workspace;
 
 #[derive(Default)]
 pub struct Workspace {
-    name: String,
     entity: Option<Entity>,
+    labels: Vec<Entity>,
 }
 
 impl Workspace {
     /// Create a new workspace instance
     pub fn new() -> Self {
         Self {
-            name: "".to_owned(),
             entity: None,
+            labels: vec![],
         }
     }
 
-    /// Update the workspace
+    /// Update the workspace, whose image is the texture of its style
     pub fn update<'s>(
         &mut self,
         i3_workspace: &I3Workspace,
-        loader: &ReadExpect<'s, Loader>,
-        texture_storage: &Read<'s, AssetStorage<Texture>>,
-        font_storage: &Read<'s, AssetStorage<FontAsset>>,
+        image: TextureHandle,
+        fonts: &Fonts,
         button_builder_resources: UiButtonBuilderResources<'s, u8>,
-        entities: Entities<'s>,
+        entities: &Entities<'s>,
+        lazy: &LazyUpdate,
     ) -> Fallible<()> {
-        // Remove the entity if available
-        if let Some(e) = self.entity {
+        let (_, text_color) = Self::style(i3_workspace);
+        self.build(
+            &format!("ws_button_{}", i3_workspace.num),
+            Self::label(i3_workspace),
+            (
+                Layout::workspace_x(i3_workspace.num),
+                Layout::workspace_width(),
+            ),
+            (image, text_color),
+            fonts,
+            (button_builder_resources, entities, lazy),
+        )
+    }
+
+    /// Show that no connection to i3 is available
+    pub fn update_disconnected<'s>(
+        &mut self,
+        image: TextureHandle,
+        fonts: &Fonts,
+        button_builder_resources: UiButtonBuilderResources<'s, u8>,
+        entities: &Entities<'s>,
+        lazy: &LazyUpdate,
+    ) -> Fallible<()> {
+        let (_, text_color) = Self::disconnected_style();
+        self.build(
+            "ws_button_disconnected",
+            Self::disconnected_label(),
+            Layout::disconnected(),
+            (image, text_color),
+            fonts,
+            (button_builder_resources, entities, lazy),
+        )
+    }
+
+    /// Replace the button and its label, which is drawn by one text entity
+    /// per font
+    fn build<'s>(
+        &mut self,
+        id: &str,
+        label: &str,
+        (x, width): (f32, f32),
+        (image, text_color): (TextureHandle, [f32; 4]),
+        fonts: &Fonts,
+        (button_builder_resources, entities, lazy): (
+            UiButtonBuilderResources<'s, u8>,
+            &Entities<'s>,
+            &LazyUpdate,
+        ),
+    ) -> Fallible<()> {
+        // Remove the entities if available
+        self.remove(entities)?;
+
+        // Create the button
+        let button_builder = UiButtonBuilder::new(id, "")
+            .with_anchor(Anchor::TopLeft)
+            .with_font(fonts.font_handle(0))
+            .with_font_size(fonts.size())
+            .with_position(x, -10.)
+            .with_size(width, 20.)
+            .with_image(image)
+            .with_text_color(text_color);
+
+        // Build the entities and add them to the world
+        self.entity = Some(button_builder.build(button_builder_resources));
+        let runs = Run::split(&Run::plain(label, text_color), fonts);
+        self.labels = Run::spawn(id, &runs, (x, -10.), fonts, entities, lazy);
+        Ok(())
+    }
+
+    /// Remove the workspace entities from the world
+    pub fn remove(&mut self, entities: &Entities<'_>) -> Fallible<()> {
+        for e in self.entity.take().into_iter().chain(self.labels.drain(..)) {
             entities.delete(e)?;
         }
+        Ok(())
+    }
 
-        // Load the font
-        let font = self.load_font(loader, font_storage);
-
-        // Prepare workspace name
-        let name = i3_workspace
+    /// Retrieve the displayed label of an i3 workspace
+    pub fn label(i3_workspace: &I3Workspace) -> &str {
+        i3_workspace
             .name
             .split(':')
             .last()
-            .unwrap_or(&i3_workspace.name);
-
-        // Create the button
-        let mut button_builder = UiButtonBuilder::new(
-            format!("ws_button_{}", i3_workspace.num),
-            name,
-        )
-        .with_anchor(Anchor::TopLeft)
-        .with_font(font.clone())
-        .with_font_size(State::font_size())
-        .with_position((20 * (i3_workspace.num - 1) + 10) as f32, -10.)
-        .with_size(20., 20.);
+            .unwrap_or(&i3_workspace.name)
+    }
 
+    /// Retrieve the image and text color of an i3 workspace
+    pub fn style(i3_workspace: &I3Workspace) -> (&'static str, [f32; 4]) {
         match (i3_workspace.visible, i3_workspace.focused) {
-            (true, true) => {
-                button_builder = button_builder
-                    .with_image(self.load_texture(
-                        "images/purple.png",
-                        loader,
-                        texture_storage,
-                    ))
-                    .with_text_color(ColorScheme::black())
-            }
+            (true, true) => ("images/purple.png", ColorScheme::black()),
             (true, false) => {
-                button_builder = button_builder
-                    .with_image(self.load_texture(
-                        "images/selection.png",
-                        loader,
-                        texture_storage,
-                    ))
-                    .with_text_color(ColorScheme::foreground())
-            }
-            (false, _) => {
-                button_builder = button_builder
-                    .with_image(self.load_texture(
-                        "images/background.png",
-                        loader,
-                        texture_storage,
-                    ))
-                    .with_text_color(ColorScheme::foreground())
+                ("images/selection.png", ColorScheme::foreground())
             }
+            (false, _) => ("images/background.png", ColorScheme::foreground()),
         }
+    }
 
-        // Build the entity and add to the world
-        self.entity = Some(button_builder.build(button_builder_resources));
-        self.name = i3_workspace.name.to_owned();
-        Ok(())
+    /// Retrieve the displayed label if i3 is not connected
+    pub const fn disconnected_label() -> &'static str {
+        "i3 disconnected"
     }
 
-    /// Retrieve the name of the workspace
-    pub fn name(&self) -> &str {
-        &self.name
+    /// Retrieve the image and text color if i3 is not connected
+    pub const fn disconnected_style() -> (&'static str, [f32; 4]) {
+        ("images/red.png", ColorScheme::black())
     }
 
     /// Retrieve the entity
@@ -111,31 +147,44 @@ impl Workspace {
             false
         }
     }
+}
 
-    fn load_texture<'s, N>(
-        &self,
-        name: N,
-        loader: &ReadExpect<'s, Loader>,
-        storage: &Read<'s, AssetStorage<Texture>>,
-    ) -> TextureHandle
-    where
-        N: Into<String>,
-    {
-        loader.load(
-            name,
-            PngFormat,
-            // Screen pixel will be taken from nearest pixel of texture
-            TextureMetadata::srgb_scale(),
-            (),
-            storage,
-        )
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workspace(name: &str, visible: bool, focused: bool) -> I3Workspace {
+        I3Workspace {
+            num: 1,
+            name: name.to_owned(),
+            visible,
+            focused,
+            urgent: false,
+            rect: (0, 0, 1920, 1080),
+            output: "eDP-1".to_owned(),
+        }
+    }
+
+    #[test]
+    fn label_strips_number_prefix() {
+        assert_eq!(Workspace::label(&workspace("1", true, true)), "1");
+        assert_eq!(Workspace::label(&workspace("2:web", true, true)), "web");
+        assert_eq!(Workspace::label(&workspace("3:a:b", true, true)), "b");
     }
 
-    fn load_font<'s>(
-        &self,
-        loader: &ReadExpect<'s, Loader>,
-        storage: &Read<'s, AssetStorage<FontAsset>>,
-    ) -> FontHandle {
-        loader.load("font/meslo.ttf", TtfFormat, (), (), storage)
+    #[test]
+    fn style_depends_on_focus() {
+        assert_eq!(
+            Workspace::style(&workspace("1", true, true)),
+            ("images/purple.png", ColorScheme::black())
+        );
+        assert_eq!(
+            Workspace::style(&workspace("1", true, false)),
+            ("images/selection.png", ColorScheme::foreground())
+        );
+        assert_eq!(
+            Workspace::style(&workspace("1", false, false)),
+            ("images/background.png", ColorScheme::foreground())
+        );
     }
 }